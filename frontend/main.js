@@ -1,4 +1,29 @@
-const { invoke } = window.__TAURI__.core;
+// Commands fail with { code, category, message, details, action }; the code is stable, the
+// rest is for the user
+class CommandError extends Error {
+  constructor({ code, category, message, details, action }) {
+    super(message);
+    this.code = code;
+    this.category = category;
+    this.details = details;
+    this.action = action;
+  }
+
+  // Shown as it is in the status line, so it says what to do about it too
+  toString() {
+    const text = this.details ? `${this.message}: ${this.details}` : this.message;
+    return this.action ? `${text} ${this.action}` : text;
+  }
+}
+
+async function invoke(command, args) {
+  try {
+    return await window.__TAURI__.core.invoke(command, args);
+  } catch (error) {
+    // Tauri itself rejects with text, e.g. for arguments that do not deserialize
+    throw error && error.code ? new CommandError(error) : error;
+  }
+}
 
 // UI Elements
 let currentTab = 'sender';
@@ -67,9 +92,13 @@ function loadTabContent(tabName) {
     // Load both description and amount for invoice tab
     document.getElementById('description-editor').value = fileData.description || '';
     document.getElementById('amount-editor').value = fileData.amount || '';
+    updateLivePreviewRecipients();
+    scheduleLivePreview();
   } else if (tabName === 'history') {
     // Load invoice history
     loadInvoiceHistory();
+    loadBatches();
+    loadDrafts();
   } else if (tabName === 'settings') {
     // Load settings
     loadSettings();
@@ -129,15 +158,131 @@ async function saveAllFiles() {
   }
 }
 
+// Live preview of one invoice from the unsaved values, rendered once typing pauses
+const LIVE_PREVIEW_DELAY_MS = 500;
+let livePreviewTimer = null;
+let livePreviewRequest = 0;
+
+// Recipients are blocks separated by blank lines, the first line being the name
+function recipientNames(recipients) {
+  return (recipients || '')
+    .split(/\n\s*\n/)
+    .map(block => block.trim().split('\n')[0].trim())
+    .filter(name => name);
+}
+
+function updateLivePreviewRecipients() {
+  const select = document.getElementById('live-preview-recipient');
+  const selected = select.value;
+  select.innerHTML = '';
+  recipientNames(fileData.recipients).forEach(name => {
+    const option = document.createElement('option');
+    option.value = name;
+    option.textContent = name;
+    select.appendChild(option);
+  });
+  if (recipientNames(fileData.recipients).includes(selected)) {
+    select.value = selected;
+  }
+}
+
+function scheduleLivePreview() {
+  clearTimeout(livePreviewTimer);
+  livePreviewTimer = setTimeout(renderLivePreview, LIVE_PREVIEW_DELAY_MS);
+}
+
+async function renderLivePreview() {
+  saveCurrentTabContent();
+  const recipient = document.getElementById('live-preview-recipient').value;
+  const frame = document.getElementById('live-preview-frame');
+  const message = document.getElementById('live-preview-message');
+  if (!recipient) {
+    return;
+  }
+
+  const request = ++livePreviewRequest;
+  try {
+    const preview = await invoke('preview_invoice', {
+      values: {
+        sender: fileData.sender || '',
+        bankdetails: fileData.bankdetails || '',
+        description: fileData.description || '',
+        amount: fileData.amount || '',
+        recipients: fileData.recipients || ''
+      },
+      recipient
+    });
+    // A later edit started another render; only the newest one is shown
+    if (request !== livePreviewRequest) {
+      return;
+    }
+    if (frame.src) {
+      URL.revokeObjectURL(frame.src);
+    }
+    frame.src = URL.createObjectURL(base64ToBlob(preview.pdf_base64, 'application/pdf'));
+    frame.classList.remove('hidden');
+    message.classList.add('hidden');
+  } catch (error) {
+    if (request === livePreviewRequest) {
+      message.textContent = `Preview unavailable: ${error}`;
+      message.classList.remove('hidden');
+    }
+  }
+}
+
+function formatSplitPreview(preview) {
+  const lines = preview.lines.flatMap(line => [
+    `${line.invoice_number.padEnd(12)} ${line.total.padStart(12)}  due ${line.due_date}  ${line.recipient}`,
+    ...line.descriptions.map(description => `${''.padEnd(27)}${description}`),
+  ]);
+  lines.push(`${'Total'.padEnd(12)} ${preview.total.padStart(12)}  ${preview.currency}`);
+  preview.warnings.forEach(warning => lines.push(`Warning: ${warning}`));
+  return lines.join('\n');
+}
+
+// Generate what was previewed; if recent invoices already billed the same, ask before forcing.
+// Returns null when the user declines.
+async function confirmGeneration(configHash) {
+  try {
+    return await invoke('confirm_generation', { configHash });
+  } catch (error) {
+    if (error.code !== 'duplicates_blocked') {
+      throw error;
+    }
+    if (!confirm(`${error.message}:\n${error.details}\n\nGenerate these invoices anyway?`)) {
+      return null;
+    }
+    return await invoke('confirm_generation', { configHash, options: { force: true } });
+  }
+}
+
 async function generateInvoices(dryRun = false) {
   try {
     // Save files first
     await saveAllFiles();
 
+    // Show who will be billed what before anything is stored
+    let preview = null;
+    if (!dryRun) {
+      preview = await invoke('preview_split');
+      const summary = formatSplitPreview(preview);
+      showOutput(summary);
+      if (!confirm(`Generate these invoices?\n\n${summary}`)) {
+        showStatus('Generation cancelled', 'info');
+        return;
+      }
+    }
+
     const mode = dryRun ? 'preview' : 'normal';
     showStatus(`Generating invoices in ${mode} mode...`, 'info');
 
-    const output = await invoke('generate_invoices', { dryRun });
+    const output = dryRun
+      ? await invoke('generate_invoices', { dryRun })
+      : await confirmGeneration(preview.config_hash);
+    if (output === null) {
+      showStatus('Generation cancelled', 'info');
+      return;
+    }
     showStatus(`Invoices generated successfully in ${mode} mode!`, 'success');
     showOutput(output);
 
@@ -149,7 +294,63 @@ async function generateInvoices(dryRun = false) {
     }
   } catch (error) {
     showStatus(`Error generating invoices: ${error}`, 'error');
-    showOutput(error);
+    // Point at the cause when the environment is broken rather than the invoice data
+    const problems = await diagnosticProblems();
+    showOutput(problems ? `${error}\n\nEnvironment problems:\n${problems}` : error);
+  }
+}
+
+function formatChecks(checks) {
+  return checks
+    .map(check => {
+      const mark = { ok: '✓', warning: '!', failed: '✗' }[check.status];
+      return check.fix
+        ? `${mark} ${check.name}: ${check.detail}\n    Fix: ${check.fix}`
+        : `${mark} ${check.name}: ${check.detail}`;
+    })
+    .join('\n');
+}
+
+// Checks that did not pass, formatted; empty when everything is fine or the check itself fails
+async function diagnosticProblems() {
+  try {
+    const report = await invoke('run_diagnostics');
+    return formatChecks(report.checks.filter(check => check.status !== 'ok'));
+  } catch (error) {
+    return '';
+  }
+}
+
+async function runDiagnostics() {
+  try {
+    const report = await invoke('run_diagnostics');
+    showOutput(formatChecks(report.checks));
+    showStatus(report.ok ? 'All checks passed' : 'Some checks failed', report.ok ? 'success' : 'error');
+  } catch (error) {
+    showStatus(`Error running checks: ${error}`, 'error');
+  }
+}
+
+// Recent generation runs with what the backend printed, newest first
+async function showRunLog() {
+  try {
+    const logs = await invoke('list_run_logs', { limit: 10 });
+    if (logs.length === 0) {
+      showOutput('No generation runs yet');
+      return;
+    }
+    showOutput(logs
+      .map(log => {
+        const mark = log.succeeded ? '✓' : '✗';
+        const kind = log.dry_run ? ' (preview)' : '';
+        let text = `${mark} ${log.started_at} UTC, ${log.renderer}${kind}: ${log.detail}`;
+        if (log.stdout.trim()) text += `\n${log.stdout.trimEnd()}`;
+        if (log.stderr.trim()) text += `\nErrors:\n${log.stderr.trimEnd()}`;
+        return text;
+      })
+      .join('\n\n'));
+  } catch (error) {
+    showStatus(`Error loading run log: ${error}`, 'error');
   }
 }
 
@@ -168,6 +369,11 @@ window.addEventListener("DOMContentLoaded", async () => {
   document.getElementById('generate-btn').addEventListener('click', () => generateInvoices(false));
   document.getElementById('preview-btn').addEventListener('click', () => generateInvoices(true));
 
+  // Live invoice preview while editing
+  document.getElementById('description-editor').addEventListener('input', scheduleLivePreview);
+  document.getElementById('amount-editor').addEventListener('input', scheduleLivePreview);
+  document.getElementById('live-preview-recipient').addEventListener('change', scheduleLivePreview);
+
   // Settings button events
   document.getElementById('save-settings-btn').addEventListener('click', saveSettings);
   document.getElementById('reset-settings-btn').addEventListener('click', resetSettings);
@@ -180,10 +386,20 @@ window.addEventListener("DOMContentLoaded", async () => {
   // History refresh button
   document.getElementById('refresh-history-btn').addEventListener('click', () => {
     loadInvoiceHistory();
+    loadBatches();
+    loadDrafts();
     showStatus('Refreshing invoice history...', 'info');
   });
 
+  // Drafts
+  document.getElementById('create-drafts-btn').addEventListener('click', createDrafts);
+  document.getElementById('save-draft-btn').addEventListener('click', saveEditedDraft);
+  document.getElementById('preview-draft-btn').addEventListener('click', () => showDraftPdf(draftFromEditor()));
+  document.getElementById('cancel-draft-btn').addEventListener('click', closeDraftEditor);
+
   // Database reset button and modal
+  document.getElementById('diagnostics-btn').addEventListener('click', runDiagnostics);
+  document.getElementById('run-log-btn').addEventListener('click', showRunLog);
   document.getElementById('reset-database-btn').addEventListener('click', openResetDatabaseModal);
   document.getElementById('close-reset-modal').addEventListener('click', closeResetDatabaseModal);
   document.getElementById('cancel-reset-btn').addEventListener('click', closeResetDatabaseModal);
@@ -196,8 +412,31 @@ window.addEventListener("DOMContentLoaded", async () => {
 
   // Load initial data
   await loadAllFiles();
+
+  // Offer recurring runs that came due since the app was last used
+  await checkScheduledRuns();
 });
 
+// Scheduled runs are only generated after the user confirms each one; declined runs stay due
+async function checkScheduledRuns() {
+  try {
+    const due = await invoke('due_scheduled_runs');
+    for (const run of due) {
+      const args = { scheduleId: run.schedule_id, runDate: run.run_date };
+      const preview = await invoke('preview_scheduled_run', args);
+      const summary = formatSplitPreview(preview);
+      if (!confirm(`Scheduled run "${run.name}" for ${run.run_date} is due. Generate these invoices?\n\n${summary}`)) {
+        continue;
+      }
+      const output = await invoke('run_scheduled', { ...args, configHash: preview.config_hash });
+      showStatus(`Generated scheduled run "${run.name}" for ${run.run_date}`, 'success');
+      showOutput(output);
+    }
+  } catch (error) {
+    showStatus(`Error in scheduled run: ${error}`, 'error');
+  }
+}
+
 // Invoice History Functions
 async function loadInvoiceHistory() {
   try {
@@ -241,7 +480,7 @@ function displayInvoices(invoices) {
     row.dataset.invoiceId = invoice.id;
 
     row.innerHTML = `
-      <td><span class="invoice-number">${invoice.invoice_number}</span></td>
+      <td><span class="invoice-number">${invoice.invoice_number}</span>${invoice.voided ? ' <span class="void-badge">VOID</span>' : ''}</td>
       <td>${invoice.service}</td>
       <td>${formatDate(invoice.invoice_date)}</td>
       <td>${formatDate(invoice.due_date)}</td>
@@ -249,6 +488,10 @@ function displayInvoices(invoices) {
       <td>
         <button class="action-btn" onclick="previewInvoice(${invoice.id})">Preview</button>
         <button class="action-btn" onclick="downloadInvoice(${invoice.id}, '${invoice.invoice_number}')">Download</button>
+        <button class="action-btn" onclick="regenerateInvoice(${invoice.id}, '${invoice.invoice_number}')">Regenerate</button>
+        <button class="action-btn" onclick="showInvoiceRevisions(${invoice.id}, '${invoice.invoice_number}')">Revisions</button>
+        <button class="action-btn" onclick="cloneInvoice(${invoice.id}, '${invoice.invoice_number}', 'draft')">Copy to draft</button>
+        <button class="action-btn" onclick="cloneInvoice(${invoice.id}, '${invoice.invoice_number}', 'settings')">Copy to settings</button>
       </td>
     `;
 
@@ -259,6 +502,234 @@ function displayInvoices(invoices) {
   document.getElementById('invoices-table').classList.remove('hidden');
 }
 
+// Generation runs, each with what it stored and how it ended
+let loadedBatches = [];
+
+async function loadBatches() {
+  try {
+    const batches = await invoke('list_batches', { limit: 20 });
+    loadedBatches = batches;
+    const tbody = document.getElementById('batches-tbody');
+    tbody.innerHTML = '';
+    document.getElementById('batches-empty').classList.toggle('hidden', batches.length > 0);
+    document.getElementById('batches-table').classList.toggle('hidden', batches.length === 0);
+
+    batches.forEach(batch => {
+      const outcome = batch.voided_at
+        ? `Voided ${formatDate(batch.voided_at)}`
+        : (batch.log.succeeded ? 'Completed' : `Failed: ${batch.log.detail}`);
+      const row = document.createElement('tr');
+      row.innerHTML = `
+        <td>${batch.id}${batch.rerun_of ? ` (re-run of ${batch.rerun_of})` : ''}</td>
+        <td>${batch.log.started_at}</td>
+        <td>${formatDate(batch.invoice_date)}</td>
+        <td>${batch.invoice_numbers.join(', ') || '–'}</td>
+        <td></td>
+        <td>
+          <button class="action-btn" onclick="rerunBatch(${batch.id})">Re-run next month</button>
+          <button class="action-btn" onclick="voidBatch(${batch.id})" ${batch.voided_at || batch.invoice_numbers.length === 0 ? 'disabled' : ''}>Void</button>
+          <button class="action-btn" onclick="showBatchLog(${batch.id})">Log</button>
+        </td>
+      `;
+      row.children[4].textContent = outcome;
+      tbody.appendChild(row);
+    });
+  } catch (error) {
+    showStatus(`Error loading generation runs: ${error}`, 'error');
+  }
+}
+
+async function rerunBatch(batchId) {
+  try {
+    const preview = await invoke('preview_batch_rerun', { id: batchId });
+    if (!confirm(`Repeat run ${batchId} one month later with the configuration it used?\n\n${formatSplitPreview(preview)}`)) {
+      return;
+    }
+    const output = await invoke('rerun_batch', { id: batchId, configHash: preview.config_hash });
+    showStatus(`Run ${batchId} repeated`, 'success');
+    showOutput(output);
+    loadInvoiceHistory();
+    loadBatches();
+  } catch (error) {
+    showStatus(`Error repeating run: ${error}`, 'error');
+  }
+}
+
+async function voidBatch(batchId) {
+  const reason = prompt(`Void every invoice from run ${batchId}? The invoice numbers are kept. Reason:`);
+  if (reason === null) return;
+  try {
+    const voided = await invoke('void_batch', { id: batchId, reason });
+    showStatus(`Voided ${voided.join(', ')}`, 'success');
+    loadInvoiceHistory();
+    loadBatches();
+  } catch (error) {
+    showStatus(`Error voiding run: ${error}`, 'error');
+  }
+}
+
+function showBatchLog(batchId) {
+  const batch = loadedBatches.find(b => b.id === batchId);
+  if (!batch) return;
+  const parts = [`Run ${batch.id}: ${batch.log.detail}`, batch.log.stdout.trimEnd()];
+  if (batch.log.stderr.trim()) parts.push(`Errors:\n${batch.log.stderr.trimEnd()}`);
+  if (batch.void_reason) parts.push(`Void reason: ${batch.void_reason}`);
+  showOutput(parts.filter(Boolean).join('\n\n'));
+}
+
+// Drafts: invoices that are edited freely and numbered only when finalized
+let loadedDrafts = [];
+let editedDraft = null;
+
+async function loadDrafts() {
+  try {
+    const drafts = await invoke('list_drafts');
+    loadedDrafts = drafts;
+    const tbody = document.getElementById('drafts-tbody');
+    tbody.innerHTML = '';
+    document.getElementById('drafts-empty').classList.toggle('hidden', drafts.length > 0);
+    document.getElementById('drafts-table').classList.toggle('hidden', drafts.length === 0);
+
+    drafts.forEach(draft => {
+      const finalized = draft.invoice_number !== '';
+      const row = document.createElement('tr');
+      row.innerHTML = `
+        <td>${draft.id}</td>
+        <td></td>
+        <td>${draft.invoice_date ? formatDate(draft.invoice_date) : '–'}</td>
+        <td>${draft.due_date ? formatDate(draft.due_date) : '–'}</td>
+        <td>${finalized ? `Invoice ${draft.invoice_number}` : 'Draft'}</td>
+        <td>
+          <button class="action-btn" onclick="previewDraft(${draft.id})">Preview</button>
+          <button class="action-btn" onclick="editDraft(${draft.id})" ${finalized ? 'disabled' : ''}>Edit</button>
+          <button class="action-btn" onclick="finalizeDraft(${draft.id})" ${finalized ? 'disabled' : ''}>Finalize</button>
+          <button class="action-btn" onclick="deleteDraft(${draft.id})" ${finalized ? 'disabled' : ''}>Delete</button>
+        </td>
+      `;
+      row.children[1].textContent = draft.customer_name;
+      tbody.appendChild(row);
+    });
+  } catch (error) {
+    showStatus(`Error loading drafts: ${error}`, 'error');
+  }
+}
+
+async function createDrafts() {
+  try {
+    const drafts = await invoke('create_drafts', { options: null });
+    showStatus(`Created ${drafts.length} drafts`, 'success');
+    loadDrafts();
+  } catch (error) {
+    showStatus(`Error creating drafts: ${error}`, 'error');
+  }
+}
+
+function showPdf(pdfBase64) {
+  const pdfUrl = URL.createObjectURL(base64ToBlob(pdfBase64, 'application/pdf'));
+  document.getElementById('pdf-frame').src = pdfUrl;
+  document.getElementById('pdf-viewer').classList.remove('hidden');
+  document.getElementById('pdf-placeholder').classList.add('hidden');
+}
+
+async function showDraftPdf(draft) {
+  try {
+    showPdf(await invoke('preview_draft', { draft }));
+  } catch (error) {
+    showStatus(`Error previewing draft: ${error}`, 'error');
+  }
+}
+
+function previewDraft(draftId) {
+  const draft = loadedDrafts.find(d => d.id === draftId);
+  if (draft) showDraftPdf(draft);
+}
+
+function editDraft(draftId) {
+  const draft = loadedDrafts.find(d => d.id === draftId);
+  if (!draft) return;
+  editedDraft = draft;
+  document.getElementById('draft-editor-title').textContent = `Edit draft ${draft.id}`;
+  document.getElementById('draft-customer').value = draft.customer_name;
+  document.getElementById('draft-invoice-date').value = draft.invoice_date;
+  document.getElementById('draft-due-date').value = draft.due_date;
+  document.getElementById('draft-lines').value = draft.lines
+    .map(line => `${line.description} | ${line.amount}`)
+    .join('\n');
+  document.getElementById('draft-editor').classList.remove('hidden');
+}
+
+function closeDraftEditor() {
+  editedDraft = null;
+  document.getElementById('draft-editor').classList.add('hidden');
+}
+
+// The edited draft with the editor's values; lines keep the VAT of the line they replace
+function draftFromEditor() {
+  const rows = document.getElementById('draft-lines').value
+    .split('\n')
+    .map(row => row.trim())
+    .filter(Boolean);
+  const lines = rows.map((row, index) => {
+    const split = row.lastIndexOf('|');
+    const previous = editedDraft.lines[index] || editedDraft.lines[0] || {};
+    return {
+      date: previous.date || '',
+      description: split < 0 ? row : row.slice(0, split).trim(),
+      amount: split < 0 ? '' : row.slice(split + 1).trim(),
+      vat_rate: previous.vat_rate || 0,
+      vat_treatment: previous.vat_treatment || 'standard',
+    };
+  });
+  return {
+    ...editedDraft,
+    customer_name: document.getElementById('draft-customer').value,
+    invoice_date: document.getElementById('draft-invoice-date').value,
+    due_date: document.getElementById('draft-due-date').value,
+    lines,
+  };
+}
+
+async function saveEditedDraft() {
+  try {
+    const saved = await invoke('save_draft', { draft: draftFromEditor() });
+    showStatus(`Draft ${saved.id} saved`, 'success');
+    closeDraftEditor();
+    loadDrafts();
+  } catch (error) {
+    showStatus(`Error saving draft: ${error}`, 'error');
+  }
+}
+
+async function finalizeDraft(draftId) {
+  if (!confirm(`Finalize draft ${draftId}? It gets the next invoice number and can no longer be edited.`)) {
+    return;
+  }
+  try {
+    const draft = await invoke('finalize_draft', { id: draftId });
+    const message = `Draft ${draftId} finalized as invoice ${draft.invoice_number}`;
+    if (draft.warnings && draft.warnings.length > 0) {
+      showStatus(`${message}. ${draft.warnings.join(' ')}`, 'warning');
+    } else {
+      showStatus(message, 'success');
+    }
+    loadInvoiceHistory();
+    loadDrafts();
+  } catch (error) {
+    showStatus(`Error finalizing draft: ${error}`, 'error');
+  }
+}
+
+async function deleteDraft(draftId) {
+  if (!confirm(`Delete draft ${draftId}?`)) return;
+  try {
+    await invoke('delete_draft', { id: draftId });
+    showStatus(`Draft ${draftId} deleted`, 'success');
+    loadDrafts();
+  } catch (error) {
+    showStatus(`Error deleting draft: ${error}`, 'error');
+  }
+}
+
 async function previewInvoice(invoiceId) {
   try {
     // Remove previous selection
@@ -292,6 +763,80 @@ async function previewInvoice(invoiceId) {
   }
 }
 
+// Render an invoice again with the current layout; the original PDF is kept as revision 1.
+// Revisions always use the built-in renderer, whichever renderer is configured.
+async function regenerateInvoice(invoiceId, invoiceNumber) {
+  const reason = prompt(`Render invoice ${invoiceNumber} again as a new revision with the built-in renderer? The original PDF is kept. Reason:`);
+  if (reason === null) return;
+  try {
+    const revision = await invoke('regenerate_invoice', { id: invoiceId, reason });
+    showPdf(revision.pdf_base64);
+    const message = `Invoice ${invoiceNumber} rendered again as revision ${revision.revision}`;
+    if (revision.warnings && revision.warnings.length > 0) {
+      showStatus(`${message}. ${revision.warnings.join(' ')}`, 'warning');
+    } else {
+      showStatus(message, 'success');
+    }
+  } catch (error) {
+    showStatus(`Error regenerating invoice: ${error}`, 'error');
+  }
+}
+
+// List an invoice's revisions and what changed from the original to the latest
+async function showInvoiceRevisions(invoiceId, invoiceNumber) {
+  try {
+    const revisions = await invoke('list_invoice_revisions', { id: invoiceId });
+    if (revisions.length === 0) {
+      showStatus(`Invoice ${invoiceNumber} was never rendered again or corrected`, 'info');
+      return;
+    }
+    const first = revisions[0];
+    const latest = revisions[revisions.length - 1];
+    const changes = await invoke('diff_invoice_revisions', {
+      id: invoiceId,
+      from: first.revision,
+      to: latest.revision,
+    });
+    const parts = [
+      `Revisions of invoice ${invoiceNumber}:`,
+      revisions.map(r => `${r.revision}  ${r.created_at}  ${r.reason}`).join('\n'),
+      changes.length === 0
+        ? `Revision ${latest.revision} has the same data as revision ${first.revision}`
+        : `Changes from revision ${first.revision} to ${latest.revision}:\n` +
+          changes.map(c => `${c.field}: "${c.before}" → "${c.after}"`).join('\n'),
+    ];
+    showPdf(latest.pdf_base64);
+    showOutput(parts.join('\n\n'));
+  } catch (error) {
+    showStatus(`Error loading revisions: ${error}`, 'error');
+  }
+}
+
+// Bill the same again: copy an invoice into a new draft or into the settings, dates moved on
+async function cloneInvoice(invoiceId, invoiceNumber, target) {
+  const destination = target === 'draft' ? 'a new draft' : 'the current settings, replacing them';
+  const months = prompt(`Copy invoice ${invoiceNumber} into ${destination}. Move the dates on by how many months?`, '1');
+  if (months === null) return;
+  const monthCount = parseInt(months, 10);
+  if (isNaN(monthCount) || monthCount < 0) {
+    showStatus(`Invalid number of months: ${months}`, 'error');
+    return;
+  }
+  try {
+    if (target === 'draft') {
+      const draft = await invoke('clone_invoice_to_draft', { id: invoiceId, months: monthCount });
+      showStatus(`Invoice ${invoiceNumber} copied to draft ${draft.id}`, 'success');
+      loadDrafts();
+    } else {
+      await invoke('clone_invoice_to_settings', { id: invoiceId, months: monthCount });
+      await loadAllFiles();
+      showStatus(`Invoice ${invoiceNumber} copied into the settings`, 'success');
+    }
+  } catch (error) {
+    showStatus(`Error copying invoice: ${error}`, 'error');
+  }
+}
+
 async function downloadInvoice(invoiceId, invoiceNumber) {
   try {
     const invoice = await invoke('get_invoice_by_id', { id: invoiceId });
@@ -347,6 +892,14 @@ async function loadSettings() {
 
 function displaySettings() {
   document.getElementById('output-directory').value = appSettings.output_directory || '';
+  document.getElementById('collision-policy').value = appSettings.collision_policy || 'suffix';
+  document.getElementById('renderer').value = appSettings.renderer || 'ocaml';
+  document.getElementById('backend-timeout').value = appSettings.backend_timeout_secs ?? 300;
+  document.getElementById('batch-mode').value = appSettings.batch_mode || 'best_effort';
+  document.getElementById('duplicate-policy').value = appSettings.duplicate_policy || 'block';
+  document.getElementById('duplicate-window').value = appSettings.duplicate_window_days ?? 31;
+  document.getElementById('filename-template').value = appSettings.filename_template || '';
+  document.getElementById('subfolder-template').value = appSettings.subfolder_template || '';
 }
 
 async function saveSettings() {
@@ -354,8 +907,16 @@ async function saveSettings() {
     showStatus('Saving settings...', 'info');
 
     const newSettings = {
+      ...appSettings,
       output_directory: document.getElementById('output-directory').value,
-      config_directory: document.getElementById('config-directory').value
+      collision_policy: document.getElementById('collision-policy').value,
+      renderer: document.getElementById('renderer').value,
+      backend_timeout_secs: Math.max(0, parseInt(document.getElementById('backend-timeout').value, 10) || 0),
+      batch_mode: document.getElementById('batch-mode').value,
+      duplicate_policy: document.getElementById('duplicate-policy').value,
+      duplicate_window_days: Math.max(0, parseInt(document.getElementById('duplicate-window').value, 10) || 0),
+      filename_template: document.getElementById('filename-template').value || 'invoice-{number}',
+      subfolder_template: document.getElementById('subfolder-template').value
     };
 
     await invoke('save_app_settings', { settings: newSettings });
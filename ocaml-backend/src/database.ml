@@ -223,6 +223,18 @@ let store_invoice db invoice_number (invoice_data : Types.invoice_data) pdf_cont
     let _ = finalize stmt in
     Error ("Failed to store invoice: " ^ (Rc.to_string rc))
 
+let exec_statement db sql action =
+  match exec db sql with
+  | Rc.OK -> Ok ()
+  | rc -> Error ("Failed to " ^ action ^ ": " ^ (Rc.to_string rc))
+
+(* Counter increments and stored invoices between these are kept or discarded together *)
+let begin_transaction db = exec_statement db "BEGIN IMMEDIATE" "start transaction"
+
+let commit_transaction db = exec_statement db "COMMIT" "commit transaction"
+
+let rollback_transaction db = exec_statement db "ROLLBACK" "roll back transaction"
+
 let close_connection db =
   match db_close db with
   | true -> Ok ()
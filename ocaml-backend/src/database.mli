@@ -15,4 +15,10 @@ val generate_invoice_number : Sqlite3.db -> (string, string) result
 
 val store_invoice : Sqlite3.db -> string -> Types.invoice_data -> string -> (unit, string) result
 
+val begin_transaction : Sqlite3.db -> (unit, string) result
+
+val commit_transaction : Sqlite3.db -> (unit, string) result
+
+val rollback_transaction : Sqlite3.db -> (unit, string) result
+
 val close_connection : Sqlite3.db -> (unit, string) result
\ No newline at end of file
@@ -103,10 +103,28 @@ let add_line_items state invoice_data =
   
   List.fold_left process_line_item state invoice_data.lines
 
+(* Label for one VAT summary row *)
+let vat_line_text vat_line currency =
+  let amount = format_currency (float_of_string vat_line.vat_amount) currency in
+  match vat_line.treatment with
+  | "exempt" -> Printf.sprintf "Fritatt for mva (%s): %s" (format_currency (float_of_string vat_line.net) currency) amount
+  | "reverse_charge" -> Printf.sprintf "Omvendt avgiftsplikt (%s): %s" (format_currency (float_of_string vat_line.net) currency) amount
+  | _ -> Printf.sprintf "Moms (%d%%): %s" vat_line.vat_rate amount
+
 (* Totals section *)
 let add_totals state invoice_data =
   let subtotal = List.fold_left (fun acc line -> acc +. (float_of_string line.price)) 0.0 invoice_data.lines in
-  let (base_total, vat_amount, final_total) = calculate_vat (string_of_float subtotal) invoice_data.vat.rate invoice_data.vat.enabled in
+  let (base_total, vat_amount, final_total) =
+    match invoice_data.vat_lines with
+    | [] -> calculate_vat (string_of_float subtotal) invoice_data.vat.rate invoice_data.vat.enabled
+    | vat_lines ->
+        (* VAT per rate was computed exactly by the app, so it is summed rather than recalculated *)
+        let vat_total = List.fold_left (fun acc line -> acc +. float_of_string line.vat_amount) 0.0 vat_lines in
+        (subtotal, vat_total, subtotal +. vat_total) in
+  let vat_texts =
+    match invoice_data.vat_lines with
+    | [] -> [Printf.sprintf "Moms (%d%%): %s" invoice_data.vat.rate (format_currency vat_amount invoice_data.currency)]
+    | vat_lines -> List.map (fun line -> vat_line_text line invoice_data.currency) vat_lines in
   let price_column_right = state.config.width -. state.config.margin in
   
   (* Add separator line *)
@@ -117,13 +135,16 @@ let add_totals state invoice_data =
   (* Add VAT breakdown if enabled *)
   let state = 
     if invoice_data.vat.enabled then
+      let row_height = state.config.font_size_normal +. 3.0 in
       let subtotal_text = Printf.sprintf "Subtotal: %s" (format_currency base_total invoice_data.currency) in
-      let vat_text = Printf.sprintf "Moms (%d%%): %s" invoice_data.vat.rate (format_currency vat_amount invoice_data.currency) in
-      let subtotal_ops = right_aligned_text subtotal_text price_column_right state.current_y state.config.font_size_normal in
-      let vat_ops = right_aligned_text vat_text price_column_right (state.current_y -. state.config.font_size_normal -. 3.0) state.config.font_size_normal in
-      let state1 = add_content_to_state state subtotal_ops in
-      let state2 = add_content_to_state state1 vat_ops in
-      move_y state2 (2.0 *. (state.config.font_size_normal +. 3.0) +. 5.0)
+      let texts = subtotal_text :: vat_texts in
+      let state2 =
+        List.fold_left (fun (acc, i) text ->
+          let ops = right_aligned_text text price_column_right (state.current_y -. float_of_int i *. row_height) state.config.font_size_normal in
+          (add_content_to_state acc ops, i + 1)
+        ) (state, 0) texts
+        |> fst in
+      move_y state2 (float_of_int (List.length texts) *. row_height +. 5.0)
     else
       state
   in
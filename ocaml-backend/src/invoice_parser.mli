@@ -3,6 +3,9 @@ type invoice_info = {
   total_amount : float;
 }
 
+(* Parse a plain decimal amount such as "1250.50" or "1250,50" *)
+val parse_amount : string -> float option
+
 val parse_invoice_files : string -> string -> invoice_info option
 
 val parse_invoice_file : string -> invoice_info option
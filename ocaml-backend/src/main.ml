@@ -1,10 +1,20 @@
 open Printf
 
-let usage_msg = "invoice-splitter [-dry]"
+let usage_msg = "invoice-splitter [-dry] [-all-or-nothing] [-manifest <file>] [-plan <file>] [-version]"
+
+(* Checked by the desktop app's diagnostics; change it together with BACKEND_VERSION in generation.rs *)
+let backend_version = "invoice-splitter-backend 1"
 let dry_run = ref false
+let all_or_nothing = ref false
+let manifest_file = ref ""
+let plan_file = ref ""
 
 let spec_list = [
   ("-dry", Arg.Set dry_run, " Generate PDF without saving to database (preview mode)");
+  ("-all-or-nothing", Arg.Set all_or_nothing, " Store the invoices only if every recipient succeeds; otherwise store none");
+  ("-manifest", Arg.Set_string manifest_file, " Append the path of every PDF produced by this run to <file>");
+  ("-plan", Arg.Set_string plan_file, " Read per-recipient amounts computed by the desktop app from <file>");
+  ("-version", Arg.Unit (fun () -> print_endline backend_version; exit 0), " Print the backend version and exit");
 ]
 
 let ensure_output_directory () =
@@ -17,19 +27,78 @@ let ensure_output_directory () =
       Unix.mkdir out_dir 0o755
   | _ -> ()
 
-let generate_single_invoice invoice_data bank_lines customer_info invoice_number_or_preview is_preview invoice_info_opt =
+let output_file_for invoice_number_or_preview =
+  Printf.sprintf "out/invoice-%s.pdf" invoice_number_or_preview
+
+let manifest_field s =
+  String.map (function '\t' | '\n' | '\r' -> ' ' | c -> c) s
+
+(* Record a finished PDF so the caller can tell this run's files apart from leftovers in out/.
+   Each line is: path, invoice number, customer name, service, invoice date (tab separated) *)
+let record_in_manifest output_file invoice_data =
+  if !manifest_file <> "" then (
+    let open Invoice_src.Types in
+    let fields = [
+      output_file;
+      invoice_data.invoice_meta.invoice_number.value;
+      invoice_data.customer.name;
+      invoice_data.service;
+      invoice_data.invoice_meta.invoice_date.value;
+    ] in
+    let oc = open_out_gen [Open_append; Open_creat; Open_text] 0o644 !manifest_file in
+    output_string oc (String.concat "\t" (List.map manifest_field fields) ^ "\n");
+    close_out oc
+  )
+
+(* Description and amount for the recipient at [index]: taken from the run plan when it has them,
+   otherwise the configured description and an equal share *)
+let invoice_info_for_recipient plan info recipient_count index =
+  let open Invoice_src.Invoice_parser in
+  let amount =
+    match Invoice_src.Run_plan.amount_for plan index with
+    | Some amount -> amount
+    | None -> calculate_amount_per_recipient info.total_amount recipient_count in
+  let description =
+    Option.value (Invoice_src.Run_plan.description_for plan index) ~default:info.description in
+  { description; total_amount = amount }
+
+(* Currency code used in console output; NOK unless the run plan names another *)
+let currency_code plan =
+  match plan.Invoice_src.Run_plan.currency with
+  | Some currency -> currency.Invoice_src.Types.short
+  | None -> "NOK"
+
+let print_split plan info recipient_count =
+  let total = info.Invoice_src.Invoice_parser.total_amount in
+  let code = currency_code plan in
+  if plan.Invoice_src.Run_plan.amounts <> [] then
+    printf "Splitting total amount %.2f %s among %d recipients as planned by the app\n" total code recipient_count
+  else
+    printf "Dividing total amount %.2f %s equally among %d recipients: %.2f %s each\n"
+      total code recipient_count (Invoice_src.Invoice_parser.calculate_amount_per_recipient total recipient_count) code
+
+let generate_single_invoice invoice_data bank_lines customer_info invoice_number_or_preview is_preview invoice_info_opt planned_lines =
   let updated_with_customer = 
     Invoice_src.Types.update_invoice_customer_and_number invoice_data customer_info invoice_number_or_preview in
   
+  (* Lines from the run plan take precedence over the single description and amount *)
   let final_invoice_data = 
-    match invoice_info_opt with
-    | Some info ->
+    match planned_lines, invoice_info_opt with
+    | (_ :: _ as lines), _ ->
+        { updated_with_customer with Invoice_src.Types.lines = lines }
+    | [], Some info ->
         Invoice_src.Types.update_invoice_with_single_line updated_with_customer info.Invoice_src.Invoice_parser.description info.Invoice_src.Invoice_parser.total_amount
-    | None ->
+    | [], None ->
         updated_with_customer in
+  (* The service is the recipient's description, also when the plan gives the invoice lines *)
+  let final_invoice_data =
+    match invoice_info_opt with
+    | Some info ->
+        { final_invoice_data with Invoice_src.Types.service = info.Invoice_src.Invoice_parser.description }
+    | None -> final_invoice_data in
   
   ensure_output_directory ();
-  let output_file = Printf.sprintf "out/invoice-%s.pdf" invoice_number_or_preview in
+  let output_file = output_file_for invoice_number_or_preview in
   printf "Generating PDF: %s...\n" output_file;
   Invoice_src.Pdf_generator.generate_invoice_pdf final_invoice_data bank_lines output_file;
   printf "PDF generated successfully: %s\n" output_file;
@@ -44,10 +113,14 @@ let generate_single_invoice invoice_data bank_lines customer_info invoice_number
       content in
     (final_invoice_data, pdf_content)
   ) else (
+    record_in_manifest output_file final_invoice_data;
     (final_invoice_data, "")
   )
 
-let process_single_invoice db invoice_data bank_lines customer_info invoice_info_opt =
+let remove_output_file invoice_number =
+  try Sys.remove (output_file_for invoice_number) with Sys_error _ -> ()
+
+let process_single_invoice db invoice_data bank_lines customer_info invoice_info_opt planned_lines =
   match Invoice_src.Database.generate_invoice_number db with
   | Error msg ->
       eprintf "Failed to generate invoice number: %s\n" msg;
@@ -56,19 +129,77 @@ let process_single_invoice db invoice_data bank_lines customer_info invoice_info
       printf "Generated invoice number: %s\n" invoice_number;
       
       let (updated_invoice_data, pdf_content) = 
-        generate_single_invoice invoice_data bank_lines customer_info invoice_number false invoice_info_opt in
+        generate_single_invoice invoice_data bank_lines customer_info invoice_number false invoice_info_opt planned_lines in
       
       printf "Storing invoice in database...\n";
       match Invoice_src.Database.store_invoice db invoice_number updated_invoice_data pdf_content with
       | Error msg ->
           eprintf "Failed to store invoice: %s\n" msg;
+          remove_output_file invoice_number;
           Error msg
       | Ok () ->
           printf "Invoice stored in database with number: %s\n" invoice_number;
-          Ok invoice_number
+          Ok (invoice_number, updated_invoice_data)
   )
 
-let process_recipients invoice_data bank_lines recipients invoice_info_opt =
+(* Keep going after a failed recipient; each invoice is stored on its own *)
+let process_best_effort process_one recipients =
+  let rec process_all index successful_count = function
+    | [] -> successful_count
+    | recipient :: remaining_recipients ->
+        match process_one index recipient with
+        | Ok (invoice_number, invoice_data) ->
+            record_in_manifest (output_file_for invoice_number) invoice_data;
+            printf "✓ Successfully generated invoice %s for %s\n" invoice_number recipient.Invoice_src.Types.name;
+            process_all (index + 1) (successful_count + 1) remaining_recipients
+        | Error _ ->
+            eprintf "✗ Failed to generate invoice for %s\n" recipient.Invoice_src.Types.name;
+            process_all (index + 1) successful_count remaining_recipients
+  in
+  process_all 0 0 recipients
+
+(* Store every invoice and counter increment in one transaction, committed only if every
+   recipient succeeds. The PDFs are listed in the manifest after the commit and removed if the
+   run is rolled back, so a failed run leaves neither invoices nor numbers behind. *)
+let process_all_or_nothing db process_one recipients =
+  let discard generated =
+    List.iter (fun (invoice_number, _) -> remove_output_file invoice_number) generated in
+  let rec process_all index generated = function
+    | [] -> Ok (List.rev generated)
+    | recipient :: remaining_recipients ->
+        match process_one index recipient with
+        | Ok invoice ->
+            printf "✓ Generated invoice %s for %s (stored when all recipients succeed)\n" (fst invoice) recipient.Invoice_src.Types.name;
+            process_all (index + 1) (invoice :: generated) remaining_recipients
+        | Error msg -> Error (recipient.Invoice_src.Types.name, msg, generated)
+  in
+  let fail generated msg =
+    let _ = Invoice_src.Database.rollback_transaction db in
+    discard generated;
+    eprintf "%s\n" msg;
+    eprintf "All-or-nothing mode: the run was rolled back and no invoices were stored\n";
+    let _ = Invoice_src.Database.close_connection db in
+    exit 1
+  in
+  (match Invoice_src.Database.begin_transaction db with
+   | Ok () -> ()
+   | Error msg ->
+       eprintf "Database error: %s\n" msg;
+       exit 1);
+  match process_all 0 [] recipients with
+  | Error (name, msg, generated) ->
+      fail generated (sprintf "✗ Failed to generate invoice for %s: %s" name msg)
+  | Ok generated -> (
+      match Invoice_src.Database.commit_transaction db with
+      | Error msg -> fail generated msg
+      | Ok () ->
+          List.iter (fun (invoice_number, invoice_data) ->
+            record_in_manifest (output_file_for invoice_number) invoice_data
+          ) generated;
+          List.length generated
+    )
+
+let process_recipients invoice_data bank_lines recipients invoice_info_opt plan =
   printf "Connecting to database...\n";
   match Invoice_src.Database.get_or_create_connection () with
   | Error msg ->
@@ -77,48 +208,61 @@ let process_recipients invoice_data bank_lines recipients invoice_info_opt =
   | Ok db -> (
       printf "Processing %d recipients...\n" (List.length recipients);
       
+      let recipient_count = List.length recipients in
+      (match invoice_info_opt with
+       | Some info -> print_split plan info recipient_count
+       | None -> ());
+      
       (* Calculate per-recipient invoice info if invoice.txt is present *)
-      let per_recipient_invoice_info = 
-        match invoice_info_opt with
-        | Some info ->
-            let amount_per_recipient = Invoice_src.Invoice_parser.calculate_amount_per_recipient info.Invoice_src.Invoice_parser.total_amount (List.length recipients) in
-            printf "Dividing total amount %.2f NOK equally among %d recipients: %.2f NOK each\n" 
-              info.Invoice_src.Invoice_parser.total_amount (List.length recipients) amount_per_recipient;
-            Some { Invoice_src.Invoice_parser.description = info.Invoice_src.Invoice_parser.description; total_amount = amount_per_recipient }
-        | None -> 
-            None in
+      let per_recipient_invoice_info index =
+        Option.map (fun info -> invoice_info_for_recipient plan info recipient_count index) invoice_info_opt in
       
-      let rec process_all successful_count = function
-        | [] -> 
-            printf "Batch processing complete: %d invoices generated successfully\n" successful_count;
-            let _ = Invoice_src.Database.close_connection db in
-            ()
-        | recipient :: remaining_recipients ->
-            printf "\n--- Processing recipient: %s ---\n" recipient.Invoice_src.Types.name;
-            match process_single_invoice db invoice_data bank_lines recipient per_recipient_invoice_info with
-            | Ok invoice_number ->
-                printf "✓ Successfully generated invoice %s for %s\n" invoice_number recipient.Invoice_src.Types.name;
-                process_all (successful_count + 1) remaining_recipients
-            | Error _ ->
-                eprintf "✗ Failed to generate invoice for %s\n" recipient.Invoice_src.Types.name;
-                process_all successful_count remaining_recipients
+      let process_one index recipient =
+        printf "\n--- Processing recipient: %s ---\n" recipient.Invoice_src.Types.name;
+        let planned_lines = Invoice_src.Run_plan.lines_for plan index in
+        let invoice_data =
+          Invoice_src.Types.update_invoice_with_vat_lines invoice_data (Invoice_src.Run_plan.vat_for plan index) in
+        let invoice_data =
+          Invoice_src.Types.update_invoice_dates invoice_data (Invoice_src.Run_plan.dates_for plan index) in
+        (* A PDF that cannot be written fails this recipient rather than the whole run *)
+        try process_single_invoice db invoice_data bank_lines recipient (per_recipient_invoice_info index) planned_lines
+        with exn ->
+          let msg = match exn with
+            | Sys_error msg | Failure msg -> msg
+            | exn -> Printexc.to_string exn in
+          eprintf "Error: %s\n" msg;
+          Error msg
       in
-      process_all 0 recipients
+      
+      let successful_count =
+        if !all_or_nothing then process_all_or_nothing db process_one recipients
+        else process_best_effort process_one recipients in
+      printf "Batch processing complete: %d invoices generated successfully\n" successful_count;
+      let _ = Invoice_src.Database.close_connection db in
+      ()
   )
 
 let run_cli_mode dry_run =
   try
+    let plan =
+      if !plan_file = "" then Invoice_src.Run_plan.empty
+      else Invoice_src.Run_plan.load !plan_file in
+    
     (* Get database connection *)
     let db = match Invoice_src.Database.get_or_create_connection () with
       | Ok db -> db
       | Error msg -> failwith ("Database error: " ^ msg)
     in
     
-    (* Read configuration from database *)
-    let sender_info = Invoice_src.Database.get_setting_or_default db "sender" "" in
-    let bank_info = Invoice_src.Database.get_setting_or_default db "bankdetails" "" in
-    let description = Invoice_src.Database.get_setting_or_default db "description" "" in
-    let amount_str = Invoice_src.Database.get_setting_or_default db "amount" "" in
+    (* Read configuration from the plan, falling back to the database *)
+    let setting key =
+      match Invoice_src.Run_plan.setting plan key with
+      | Some value -> value
+      | None -> Invoice_src.Database.get_setting_or_default db key "" in
+    let sender_info = setting "sender" in
+    let bank_info = setting "bankdetails" in
+    let description = setting "description" in
+    let amount_str = setting "amount" in
     
     (* Check required settings *)
     if String.trim sender_info = "" then (
@@ -154,17 +298,24 @@ let run_cli_mode dry_run =
     
     (* Parse invoice details from database *)
     printf "Reading invoice details from database (description and amount)...\n";
-    let amount_value = try Float.of_string (String.trim amount_str) with _ -> 0.0 in
+    let amount_value =
+      match Invoice_src.Invoice_parser.parse_amount amount_str with
+      | Some amount -> amount
+      | None ->
+          eprintf "Error: Invalid amount '%s' in database\n" (String.trim amount_str);
+          eprintf "Please enter the amount again in the application\n";
+          exit 1
+    in
     let invoice_info = {
       Invoice_src.Invoice_parser.description = description;
       Invoice_src.Invoice_parser.total_amount = amount_value;
     } in
     
     printf "Invoice description: %s\n" (if invoice_info.Invoice_src.Invoice_parser.description = "" then "(empty)" else invoice_info.Invoice_src.Invoice_parser.description);
-    printf "Total amount: %.2f NOK\n" invoice_info.Invoice_src.Invoice_parser.total_amount;
+    printf "Total amount: %.2f %s\n" invoice_info.Invoice_src.Invoice_parser.total_amount (currency_code plan);
     
     (* Check for recipients in database *)
-    let recipients_info = Invoice_src.Database.get_setting_or_default db "recipients" "" in
+    let recipients_info = setting "recipients" in
     
     if String.trim recipients_info <> "" then (
       printf "Found recipients in database - generating invoices for multiple recipients\n";
@@ -182,26 +333,33 @@ let run_cli_mode dry_run =
         sender_lines bank_lines dummy_customer "TEMP" 
         invoice_info.Invoice_src.Invoice_parser.description 
         invoice_info.Invoice_src.Invoice_parser.total_amount in
+      let base_invoice_data =
+        match plan.Invoice_src.Run_plan.currency with
+        | Some currency -> { base_invoice_data with Invoice_src.Types.currency = currency }
+        | None -> base_invoice_data in
       
       if dry_run then (
         printf "DRY RUN MODE - Preview mode for batch processing\n";
         printf "Would generate %d invoices for:\n" (List.length recipients);
         
-        let amount_per_recipient = Invoice_src.Invoice_parser.calculate_amount_per_recipient invoice_info.Invoice_src.Invoice_parser.total_amount (List.length recipients) in
-        printf "Would divide total amount %.2f NOK equally among %d recipients: %.2f NOK each\n" 
-          invoice_info.Invoice_src.Invoice_parser.total_amount (List.length recipients) amount_per_recipient;
-        
-        let per_recipient_info = { Invoice_src.Invoice_parser.description = invoice_info.Invoice_src.Invoice_parser.description; total_amount = amount_per_recipient } in
+        let recipient_count = List.length recipients in
+        print_split plan invoice_info recipient_count;
         
         List.iteri (fun i recipient ->
-          printf "  %d. %s\n" (i + 1) recipient.Invoice_src.Types.name;
+          let per_recipient_info = invoice_info_for_recipient plan invoice_info recipient_count i in
+          printf "  %d. %s: %.2f %s\n" (i + 1) recipient.Invoice_src.Types.name per_recipient_info.Invoice_src.Invoice_parser.total_amount (currency_code plan);
           let preview_name = Printf.sprintf "PREVIEW-%d" (i + 1) in
-          let (_, _) = generate_single_invoice base_invoice_data bank_lines recipient preview_name true (Some per_recipient_info) in
+          let planned_lines = Invoice_src.Run_plan.lines_for plan i in
+          let invoice_data =
+            Invoice_src.Types.update_invoice_with_vat_lines base_invoice_data (Invoice_src.Run_plan.vat_for plan i) in
+          let invoice_data =
+            Invoice_src.Types.update_invoice_dates invoice_data (Invoice_src.Run_plan.dates_for plan i) in
+          let (_, _) = generate_single_invoice invoice_data bank_lines recipient preview_name true (Some per_recipient_info) planned_lines in
           ()
         ) recipients;
         printf "\nUse without -dry flag to generate actual invoices with database storage\n"
       ) else (
-        process_recipients base_invoice_data bank_lines recipients (Some invoice_info)
+        process_recipients base_invoice_data bank_lines recipients (Some invoice_info) plan
       )
     ) else (
       eprintf "Error: No recipients found in database\n";
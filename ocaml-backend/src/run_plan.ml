@@ -0,0 +1,120 @@
+(* Per-run data computed by the desktop app and passed with -plan <file>.
+   One record per line, tab separated, the first field naming the record:
+     setting <key> <value>
+     currency <code> <name> <symbol>
+     amount <recipient index> <amount>
+     description <recipient index> <description>
+     line <recipient index> <date> <description> <amount>
+     vat <recipient index> <rate> <treatment> <net> <vat>
+     dates <recipient index> <invoice date> <due date> <payment days>
+   Tabs, newlines and backslashes inside fields are escaped as \t, \n and \\.
+   Unknown records are ignored so older backends keep working with newer plans. *)
+
+type t = {
+  settings : (string * string) list;
+  currency : Types.currency option;
+  amounts : (int * float) list;
+  descriptions : (int * string) list;
+  lines : (int * Types.line_item) list;
+  vat : (int * Types.vat_line) list;
+  dates : (int * (string * string)) list;
+}
+
+let empty = { settings = []; currency = None; amounts = []; descriptions = []; lines = []; vat = []; dates = [] }
+
+let unescape_field field =
+  let buffer = Buffer.create (String.length field) in
+  let rec loop i =
+    if i < String.length field then
+      if field.[i] = '\\' && i + 1 < String.length field then (
+        (match field.[i + 1] with
+         | 't' -> Buffer.add_char buffer '\t'
+         | 'n' -> Buffer.add_char buffer '\n'
+         | c -> Buffer.add_char buffer c);
+        loop (i + 2))
+      else (
+        Buffer.add_char buffer field.[i];
+        loop (i + 1))
+  in
+  loop 0;
+  Buffer.contents buffer
+
+let parse_line plan line =
+  match String.split_on_char '\t' line with
+  | ["setting"; key; value] ->
+      { plan with settings = (String.trim key, unescape_field value) :: plan.settings }
+  | ["setting"; key] ->
+      (* Trailing tabs are trimmed off records with an empty value *)
+      { plan with settings = (String.trim key, "") :: plan.settings }
+  | ["currency"; code; name; symbol] ->
+      let symbol = String.trim symbol in
+      let currency = {
+        Types.short = String.trim code;
+        name = String.trim name;
+        symbol = (if symbol = "" then None else Some symbol);
+      } in
+      { plan with currency = Some currency }
+  | ["amount"; index; value] ->
+      (match int_of_string_opt (String.trim index), float_of_string_opt (String.trim value) with
+       | Some i, Some amount -> { plan with amounts = (i, amount) :: plan.amounts }
+       | _ -> failwith (Printf.sprintf "Invalid amount record in run plan: %s" line))
+  | ["description"; index; description] ->
+      (match int_of_string_opt (String.trim index) with
+       | Some i -> { plan with descriptions = (i, unescape_field description) :: plan.descriptions }
+       | None -> failwith (Printf.sprintf "Invalid description record in run plan: %s" line))
+  | ["line"; index; date; description; value] ->
+      (match int_of_string_opt (String.trim index), float_of_string_opt (String.trim value) with
+       | Some i, Some _ ->
+           let item = {
+             Types.date = unescape_field date;
+             description = unescape_field description;
+             (* Keep the app's exact decimal rather than reformatting the float *)
+             price = String.trim value;
+           } in
+           { plan with lines = (i, item) :: plan.lines }
+       | _ -> failwith (Printf.sprintf "Invalid line record in run plan: %s" line))
+  | ["vat"; index; rate; treatment; net; vat] ->
+      (match int_of_string_opt (String.trim index), int_of_string_opt (String.trim rate),
+             float_of_string_opt (String.trim net), float_of_string_opt (String.trim vat) with
+       | Some i, Some vat_rate, Some _, Some _ ->
+           let vat_line = {
+             Types.vat_rate;
+             treatment = String.trim treatment;
+             net = String.trim net;
+             vat_amount = String.trim vat;
+           } in
+           { plan with vat = (i, vat_line) :: plan.vat }
+       | _ -> failwith (Printf.sprintf "Invalid vat record in run plan: %s" line))
+  | ["dates"; index; invoice_date; due_date; _payment_days] ->
+      (match int_of_string_opt (String.trim index) with
+       | Some i -> { plan with dates = (i, (String.trim invoice_date, String.trim due_date)) :: plan.dates }
+       | None -> failwith (Printf.sprintf "Invalid dates record in run plan: %s" line))
+  | _ -> plan
+
+let parse_string content =
+  let plan =
+    String.split_on_char '\n' content
+    |> List.map (fun line -> String.trim line)
+    |> List.filter (fun line -> line <> "")
+    |> List.fold_left parse_line empty in
+  { plan with amounts = List.rev plan.amounts; lines = List.rev plan.lines; vat = List.rev plan.vat }
+
+let load filename =
+  let ic = open_in filename in
+  let content = really_input_string ic (in_channel_length ic) in
+  close_in ic;
+  parse_string content
+
+let setting plan key = List.assoc_opt key plan.settings
+
+let amount_for plan index = List.assoc_opt index plan.amounts
+
+let description_for plan index = List.assoc_opt index plan.descriptions
+
+let lines_for plan index =
+  List.filter_map (fun (i, item) -> if i = index then Some item else None) plan.lines
+
+let dates_for plan index = List.assoc_opt index plan.dates
+
+let vat_for plan index =
+  List.filter_map (fun (i, line) -> if i = index then Some line else None) plan.vat
@@ -0,0 +1,35 @@
+(* Per-run data computed by the desktop app, passed with -plan <file> *)
+type t = {
+  (* Settings chosen for this run, used instead of the ones stored in the database *)
+  settings : (string * string) list;
+  (* Currency chosen in the app; None for plans without one, which keep the invoice default *)
+  currency : Types.currency option;
+  amounts : (int * float) list;
+  (* Descriptions with placeholders expanded for each recipient *)
+  descriptions : (int * string) list;
+  lines : (int * Types.line_item) list;
+  vat : (int * Types.vat_line) list;
+  dates : (int * (string * string)) list;
+}
+
+val empty : t
+
+val parse_string : string -> t
+
+val load : string -> t
+
+val setting : t -> string -> string option
+
+val amount_for : t -> int -> float option
+
+(* Description for a recipient's invoice, if the plan has one *)
+val description_for : t -> int -> string option
+
+(* Invoice lines planned for a recipient, in order; empty if the plan has none *)
+val lines_for : t -> int -> Types.line_item list
+
+(* Invoice and due date (YYYY-MM-DD) planned for a recipient, if the plan has them *)
+val dates_for : t -> int -> (string * string) option
+
+(* VAT summary planned for a recipient's invoice, highest rate first; empty without VAT *)
+val vat_for : t -> int -> Types.vat_line list
@@ -32,6 +32,14 @@ type vat = {
   rate : int;
 }
 
+(* VAT for one rate on an invoice; treatment is "standard", "exempt" or "reverse_charge" *)
+type vat_line = {
+  vat_rate : int;
+  treatment : string;
+  net : string;
+  vat_amount : string;
+}
+
 type line_item = {
   date : string;
   description : string;
@@ -57,6 +65,8 @@ type invoice_data = {
   lines : line_item list;
   pdf_title : string;
   vat : vat;
+  (* Per-rate VAT computed by the desktop app; when empty, [vat] alone decides the totals *)
+  vat_lines : vat_line list;
   meta : meta_strings;
 }
 
@@ -71,6 +81,24 @@ let update_invoice_customer_and_number invoice_data new_customer new_invoice_num
     invoice_meta = updated_meta;
   }
 
+(* Use the invoice and due dates chosen in the app instead of today and today + 30 days *)
+let update_invoice_dates invoice_data dates =
+  match dates with
+  | None -> invoice_data
+  | Some (invoice_date, due_date) ->
+      { invoice_data with
+        invoice_meta = { invoice_data.invoice_meta with
+                         invoice_date = { value = invoice_date };
+                         due_date = { value = due_date } } }
+
+(* Apply a VAT summary: the invoice is marked VAT registered with its highest rate *)
+let update_invoice_with_vat_lines invoice_data vat_lines =
+  match vat_lines with
+  | [] -> invoice_data
+  | _ ->
+      let rate = List.fold_left (fun acc line -> max acc line.vat_rate) 0 vat_lines in
+      { invoice_data with vat = { enabled = true; rate }; vat_lines }
+
 let get_customer invoice_data = invoice_data.customer
 
 let update_invoice_with_single_line invoice_data description amount =
@@ -118,6 +146,7 @@ let create_basic_invoice_data sender_lines _bank_lines customer invoice_number d
     lines = [line_item];
     pdf_title = "Invoice";
     vat = { enabled = false; rate = 0 };
+    vat_lines = [];
     meta = {
       title = [("nb-NO", "FAKTURA")];
       pay_info = [("nb-NO", [
@@ -3,6 +3,8 @@ open Invoice_src.Types
 
 let _test_currency = { name = "Norwegian Krone"; short = "NOK"; symbol = Some "kr" }
 
+let test_customer = { name = "Customer"; orgno = ""; adr = [] }
+
 let test_format_number () =
   let result = Invoice_src.Formatting_utils.format_number 1234.56 in
   check string "format number" "1 234,56" result
@@ -19,6 +21,66 @@ let test_text_width () =
   let width = Invoice_src.Pdf_text.calculate_text_width "Hello" 12.0 in
   check bool "text width positive" true (width > 0.0)
 
+let test_run_plan_amounts () =
+  let plan = Invoice_src.Run_plan.parse_string "amount\t0\t333.34\nunknown\tx\namount\t1\t333.33\n" in
+  check (option (float 0.001)) "first amount" (Some 333.34) (Invoice_src.Run_plan.amount_for plan 0);
+  check (option (float 0.001)) "second amount" (Some 333.33) (Invoice_src.Run_plan.amount_for plan 1);
+  check (option (float 0.001)) "missing amount" None (Invoice_src.Run_plan.amount_for plan 2)
+
+let test_run_plan_lines () =
+  let plan = Invoice_src.Run_plan.parse_string
+    "line\t0\t2025-01-31\tCabin rent\\n2 nights\t1500.00\nline\t1\t\tCleaning\t450.00\nline\t0\t\tFirewood\t120.00\n" in
+  let lines = Invoice_src.Run_plan.lines_for plan 0 in
+  check (list string) "descriptions" ["Cabin rent\n2 nights"; "Firewood"] (List.map (fun l -> l.description) lines);
+  check (list string) "prices" ["1500.00"; "120.00"] (List.map (fun l -> l.price) lines);
+  check int "no lines" 0 (List.length (Invoice_src.Run_plan.lines_for plan 2))
+
+let test_run_plan_currency () =
+  let plan = Invoice_src.Run_plan.parse_string "currency\tEUR\tEuro\t€\namount\t0\t12.50\n" in
+  let code = Option.map (fun c -> c.short) plan.Invoice_src.Run_plan.currency in
+  check (option string) "currency code" (Some "EUR") code;
+  check (option (float 0.001)) "amount" (Some 12.5) (Invoice_src.Run_plan.amount_for plan 0);
+  check (option string) "no currency" None
+    (Option.map (fun c -> c.short) (Invoice_src.Run_plan.parse_string "amount\t0\t1\n").Invoice_src.Run_plan.currency)
+
+let test_run_plan_vat () =
+  let plan = Invoice_src.Run_plan.parse_string
+    "vat\t0\t25\tstandard\t1000.00\t250.00\nvat\t0\t0\treverse_charge\t200.00\t0.00\n" in
+  let vat_lines = Invoice_src.Run_plan.vat_for plan 0 in
+  check (list int) "rates" [25; 0] (List.map (fun l -> l.vat_rate) vat_lines);
+  check (list string) "treatments" ["standard"; "reverse_charge"] (List.map (fun l -> l.treatment) vat_lines);
+  let invoice = Invoice_src.Types.create_basic_invoice_data [] [] test_customer "1" "Rent" 1200.0 in
+  let with_vat = Invoice_src.Types.update_invoice_with_vat_lines invoice vat_lines in
+  check bool "vat enabled" true with_vat.vat.enabled;
+  check int "highest rate" 25 with_vat.vat.rate;
+  check bool "no vat without lines" false
+    (Invoice_src.Types.update_invoice_with_vat_lines invoice []).vat.enabled
+
+let test_run_plan_dates () =
+  let plan = Invoice_src.Run_plan.parse_string "dates\t1\t2025-01-31\t2025-02-14\t14\n" in
+  check (option (pair string string)) "dates" (Some ("2025-01-31", "2025-02-14")) (Invoice_src.Run_plan.dates_for plan 1);
+  check (option (pair string string)) "no dates" None (Invoice_src.Run_plan.dates_for plan 0);
+  let invoice = Invoice_src.Types.create_basic_invoice_data [] [] test_customer "1" "Rent" 100.0 in
+  let dated = Invoice_src.Types.update_invoice_dates invoice (Invoice_src.Run_plan.dates_for plan 1) in
+  check string "due date" "2025-02-14" dated.invoice_meta.due_date.value
+
+let test_run_plan_settings () =
+  let plan = Invoice_src.Run_plan.parse_string "setting\tdescription\tRent\\nJanuary\nsetting\tamount\t\n" in
+  check (option string) "description" (Some "Rent\nJanuary") (Invoice_src.Run_plan.setting plan "description");
+  check (option string) "empty value" (Some "") (Invoice_src.Run_plan.setting plan "amount");
+  check (option string) "not in plan" None (Invoice_src.Run_plan.setting plan "sender")
+
+let test_run_plan_descriptions () =
+  let plan = Invoice_src.Run_plan.parse_string "description\t0\tStrøm januar 2025\ndescription\t1\tLine\\none\n" in
+  check (option string) "first" (Some "Strøm januar 2025") (Invoice_src.Run_plan.description_for plan 0);
+  check (option string) "escaped" (Some "Line\none") (Invoice_src.Run_plan.description_for plan 1);
+  check (option string) "missing" None (Invoice_src.Run_plan.description_for plan 2)
+
+let test_parse_amount () =
+  check (option (float 0.001)) "dot" (Some 1250.5) (Invoice_src.Invoice_parser.parse_amount "1250.50");
+  check (option (float 0.001)) "comma" (Some 1250.5) (Invoice_src.Invoice_parser.parse_amount " 1250,50 ");
+  check (option (float 0.001)) "invalid" None (Invoice_src.Invoice_parser.parse_amount "abc")
+
 let () =
   run "Basic Tests" [
     ("Formatting", [
@@ -29,4 +91,14 @@ let () =
     ("PDF Text", [
       test_case "text_width" `Quick test_text_width;
     ]);
+    ("Run Plan", [
+      test_case "amounts" `Quick test_run_plan_amounts;
+      test_case "lines" `Quick test_run_plan_lines;
+      test_case "currency" `Quick test_run_plan_currency;
+      test_case "vat" `Quick test_run_plan_vat;
+      test_case "dates" `Quick test_run_plan_dates;
+      test_case "settings" `Quick test_run_plan_settings;
+      test_case "descriptions" `Quick test_run_plan_descriptions;
+      test_case "parse_amount" `Quick test_parse_amount;
+    ]);
   ]
\ No newline at end of file
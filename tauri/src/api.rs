@@ -0,0 +1,377 @@
+// Local HTTP API exposing the app commands as JSON endpoints for integrations
+use crate::errors::{Error, ErrorCode};
+use crate::generation;
+use crate::invoices;
+use crate::preview;
+use crate::settings::{self, AppSettings, CONFIG_KEYS};
+use crate::terms::RunOptions;
+use base64::{engine::general_purpose, Engine as _};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tiny_http::{Header, Response, Server};
+
+pub const DEFAULT_PORT: u16 = 17878;
+
+const OPENAPI_SPEC: &str = include_str!("../openapi.json");
+
+// Where the API started with the app listens, or why it did not start; None while it is
+// disabled or was not started in this process
+static LAUNCH_STATUS: Mutex<Option<Result<SocketAddr, String>>> = Mutex::new(None);
+
+pub struct ApiResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl ApiResponse {
+    fn json(status: u16, value: Value) -> Self {
+        ApiResponse {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, json!({ "error": message.into() }))
+    }
+
+    fn from_result<T: serde::Serialize>(result: Result<T, Error>) -> Self {
+        match result
+            .and_then(|v| serde_json::to_value(v).map_err(|e| Error::invalid(e.to_string())))
+        {
+            Ok(value) => Self::json(200, value),
+            Err(error) => Self::error(status_of(error.code), error.to_string()),
+        }
+    }
+}
+
+// The HTTP status an error is answered with
+pub fn status_of(code: ErrorCode) -> u16 {
+    match code {
+        ErrorCode::InvalidInput => 400,
+        ErrorCode::NotFound => 404,
+        ErrorCode::StalePreview | ErrorCode::DuplicatesBlocked | ErrorCode::RunInProgress => 409,
+        ErrorCode::DatabaseError
+        | ErrorCode::BackendFailed
+        | ErrorCode::BackendTimeout
+        | ErrorCode::FilesystemError => 500,
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigValue {
+    value: String,
+}
+
+#[derive(Deserialize, Default)]
+struct GenerateRequest {
+    #[serde(default)]
+    dry_run: bool,
+    // Hash from GET /api/preview; generation is refused if the configuration changed since
+    #[serde(default)]
+    config_hash: Option<String>,
+    #[serde(flatten)]
+    options: RunOptions,
+}
+
+pub fn generate_token() -> Result<String, Error> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| Error::filesystem("Failed to generate API token").details(e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Compare without short-circuiting so response timing does not leak the token
+fn token_matches(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn parse_body<'a, T: Deserialize<'a> + Default>(body: &'a str) -> Result<T, ApiResponse> {
+    if body.trim().is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(body).map_err(|e| ApiResponse::error(400, format!("Invalid JSON: {}", e)))
+}
+
+fn parse_id(raw: &str) -> Result<i32, ApiResponse> {
+    raw.parse()
+        .map_err(|_| ApiResponse::error(400, format!("Invalid invoice id '{}'", raw)))
+}
+
+fn config_key(raw: &str) -> Result<&str, ApiResponse> {
+    if CONFIG_KEYS.contains(&raw) {
+        Ok(raw)
+    } else {
+        Err(ApiResponse::error(
+            404,
+            format!("Unknown configuration key '{}'", raw),
+        ))
+    }
+}
+
+// The API cannot change its own port or token, and never returns the token
+fn redacted(mut app_settings: AppSettings) -> AppSettings {
+    app_settings.api_token = String::new();
+    app_settings
+}
+
+// Run options from a query string such as "invoice_date=2025-01-31&payment_days=14"
+fn run_options(query: &str) -> Result<RunOptions, ApiResponse> {
+    let mut options = RunOptions::default();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match name {
+            "invoice_date" => options.invoice_date = Some(value.to_string()),
+            "due_date" => options.due_date = Some(value.to_string()),
+            "payment_days" => {
+                options.payment_days = Some(value.parse().map_err(|_| {
+                    ApiResponse::error(400, format!("Invalid payment_days '{}'", value))
+                })?)
+            }
+            _ => {}
+        }
+    }
+    Ok(options)
+}
+
+fn route(
+    method: &str,
+    segments: &[&str],
+    query: &str,
+    body: &str,
+) -> Result<ApiResponse, ApiResponse> {
+    let response = match (method, segments) {
+        ("GET", ["config"]) => ApiResponse::from_result(settings::read_all_files()),
+        ("GET", ["config", key]) => {
+            let key = config_key(key)?;
+            ApiResponse::from_result(
+                settings::get_config_setting(key.to_string())
+                    .map(|value| json!({ "key": key, "value": value })),
+            )
+        }
+        ("PUT", ["config", key]) => {
+            let key = config_key(key)?;
+            let request: ConfigValue = serde_json::from_str(body)
+                .map_err(|e| ApiResponse::error(400, format!("Invalid JSON: {}", e)))?;
+            ApiResponse::from_result(
+                settings::set_config_setting(key.to_string(), request.value)
+                    .map(|_| json!({ "key": key, "updated": true })),
+            )
+        }
+        ("GET", ["app-settings"]) => {
+            ApiResponse::from_result(settings::get_app_settings().map(redacted))
+        }
+        ("PUT", ["app-settings"]) => {
+            let mut update: AppSettings = serde_json::from_str(body)
+                .map_err(|e| ApiResponse::error(400, format!("Invalid JSON: {}", e)))?;
+            let result = settings::get_app_settings().and_then(|current| {
+                update.api_enabled = current.api_enabled;
+                update.api_port = current.api_port;
+                update.api_token = current.api_token;
+                settings::save_app_settings(update.clone()).map(|_| redacted(update))
+            });
+            ApiResponse::from_result(result)
+        }
+        ("POST", ["generate"]) => {
+            let request: GenerateRequest = parse_body(body)?;
+            let result = match request.config_hash {
+                Some(_) if request.dry_run => {
+                    return Err(ApiResponse::error(
+                        400,
+                        "config_hash cannot be combined with dry_run",
+                    ))
+                }
+                Some(config_hash) => {
+                    preview::confirm_generation(config_hash, Some(request.options))
+                }
+                None => generation::generate_invoices(request.dry_run, Some(request.options)),
+            };
+            ApiResponse::from_result(
+                result.map(|output| json!({ "dry_run": request.dry_run, "output": output })),
+            )
+        }
+        ("GET", ["preview"]) => {
+            ApiResponse::from_result(preview::preview_split(Some(run_options(query)?)))
+        }
+        ("GET", ["invoices"]) => ApiResponse::from_result(invoices::list_invoice_summaries()),
+        ("GET", ["invoices", id]) => match invoices::find_invoice(parse_id(id)?) {
+            Ok(Some(invoice)) => ApiResponse::from_result(Ok(invoice)),
+            Ok(None) => ApiResponse::error(404, format!("Invoice {} not found", id)),
+            Err(error) => ApiResponse::error(status_of(error.code), error.to_string()),
+        },
+        ("GET", ["invoices", id, "pdf"]) => match invoices::find_invoice(parse_id(id)?) {
+            Ok(Some(invoice)) => match general_purpose::STANDARD.decode(&invoice.pdf_base64) {
+                Ok(bytes) => ApiResponse {
+                    status: 200,
+                    content_type: "application/pdf",
+                    body: bytes,
+                },
+                Err(e) => ApiResponse::error(500, format!("Failed to decode PDF: {}", e)),
+            },
+            Ok(None) => ApiResponse::error(404, format!("Invoice {} not found", id)),
+            Err(error) => ApiResponse::error(status_of(error.code), error.to_string()),
+        },
+        (
+            _,
+            ["config"]
+            | ["config", _]
+            | ["app-settings"]
+            | ["generate"]
+            | ["preview"]
+            | ["invoices"]
+            | ["invoices", _]
+            | ["invoices", _, "pdf"],
+        ) => ApiResponse::error(405, format!("Method {} not allowed", method)),
+        _ => ApiResponse::error(404, "Not found"),
+    };
+
+    Ok(response)
+}
+
+// Handle one request; kept free of any server type so it can be exercised directly
+pub fn handle_request(
+    method: &str,
+    url: &str,
+    authorization: Option<&str>,
+    body: &str,
+    token: &str,
+) -> ApiResponse {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    if method == "GET" && path == "/api/openapi.json" {
+        return ApiResponse {
+            status: 200,
+            content_type: "application/json",
+            body: OPENAPI_SPEC.as_bytes().to_vec(),
+        };
+    }
+
+    let provided = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+
+    if token.is_empty() || !token_matches(token, provided) {
+        return ApiResponse::error(401, "Missing or invalid API token");
+    }
+
+    let segments: Vec<&str> = match path.strip_prefix("/api/") {
+        Some(rest) => rest.split('/').filter(|s| !s.is_empty()).collect(),
+        None => return ApiResponse::error(404, "Not found"),
+    };
+
+    route(method, &segments, query, body).unwrap_or_else(|error| error)
+}
+
+pub struct ApiServer {
+    server: Arc<Server>,
+    addr: SocketAddr,
+    thread: JoinHandle<()>,
+}
+
+impl ApiServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // Block until the server stops
+    pub fn wait(self) {
+        let _ = self.thread.join();
+    }
+
+    pub fn stop(self) {
+        self.server.unblock();
+        let _ = self.thread.join();
+    }
+}
+
+fn serve(server: &Server, token: &str) {
+    for mut request in server.incoming_requests() {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.as_str().to_string());
+
+        let mut body = String::new();
+        let response = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => handle_request(
+                request.method().as_str(),
+                request.url(),
+                authorization.as_deref(),
+                &body,
+                token,
+            ),
+            Err(e) => ApiResponse::error(400, format!("Failed to read request body: {}", e)),
+        };
+
+        let content_type = Header::from_bytes(&b"Content-Type"[..], response.content_type)
+            .expect("static header is valid");
+        let _ = request.respond(
+            Response::from_data(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type),
+        );
+    }
+}
+
+// Start the API on localhost; port 0 picks a free port
+pub fn start(port: u16, token: String) -> Result<ApiServer, Error> {
+    if token.trim().is_empty() {
+        return Err(Error::invalid("The local API requires a token"));
+    }
+
+    let server = Server::http(("127.0.0.1", port)).map_err(|e| {
+        Error::invalid(format!("Failed to start local API on port {}", port)).details(e)
+    })?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| Error::invalid("Local API is not listening on a TCP address"))?;
+
+    let server = Arc::new(server);
+    let thread_server = Arc::clone(&server);
+    let thread = std::thread::Builder::new()
+        .name("local-api".to_string())
+        .spawn(move || serve(&thread_server, &token))
+        .map_err(|e| Error::filesystem("Failed to start local API thread").details(e))?;
+
+    Ok(ApiServer {
+        server,
+        addr,
+        thread,
+    })
+}
+
+// Start the API if it is enabled in the app settings, recording the outcome for the diagnostics
+pub fn start_from_settings() -> Result<Option<ApiServer>, Error> {
+    let started = settings::get_app_settings().and_then(|app_settings| {
+        if !app_settings.api_enabled {
+            return Ok(None);
+        }
+        start(app_settings.api_port, app_settings.api_token).map(Some)
+    });
+
+    *LAUNCH_STATUS.lock().unwrap_or_else(|e| e.into_inner()) = match &started {
+        Ok(server) => server.as_ref().map(|server| Ok(server.addr())),
+        Err(e) => Some(Err(e.to_string())),
+    };
+    started
+}
+
+pub fn launch_status() -> Option<Result<SocketAddr, String>> {
+    LAUNCH_STATUS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
@@ -0,0 +1,88 @@
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::settings::get_app_data_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub size_bytes: u64,
+    pub created_unix: u64,
+}
+
+fn get_backup_dir() -> Result<PathBuf, Error> {
+    let backups = get_app_data_dir()?.join("backups");
+
+    fs::create_dir_all(&backups)
+        .map_err(|e| Error::filesystem("Failed to create backup directory").details(e))?;
+
+    Ok(backups)
+}
+
+fn backup_info(path: &Path) -> Result<BackupInfo, Error> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| Error::filesystem("Failed to read backup metadata").details(e))?;
+
+    let created_unix = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    Ok(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        size_bytes: metadata.len(),
+        created_unix,
+    })
+}
+
+// Write a consistent copy of the database, by default into the app data backups folder
+pub fn backup_database(destination: Option<String>) -> Result<BackupInfo, Error> {
+    let target = match destination {
+        Some(path) if !path.trim().is_empty() => PathBuf::from(path),
+        _ => {
+            let stamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            get_backup_dir()?.join(format!("invoices-{}.db", stamp))
+        }
+    };
+
+    if target.exists() {
+        return Err(Error::invalid(format!(
+            "Backup file already exists: {}",
+            target.display()
+        )));
+    }
+
+    // VACUUM INTO produces a compact snapshot even while the database is in use
+    let conn = connect_database()?;
+    conn.execute("VACUUM INTO ?1", [target.to_string_lossy().to_string()])
+        .map_err(|e| Error::filesystem("Failed to back up database").details(e))?;
+
+    backup_info(&target)
+}
+
+pub fn list_backups() -> Result<Vec<BackupInfo>, Error> {
+    let entries = fs::read_dir(get_backup_dir()?)
+        .map_err(|e| Error::filesystem("Failed to read backup directory").details(e))?;
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| Error::filesystem("Failed to read directory entry").details(e))?;
+        let path = entry.path();
+
+        if path.extension().and_then(|s| s.to_str()) == Some("db") {
+            backups.push(backup_info(&path)?);
+        }
+    }
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_unix));
+    Ok(backups)
+}
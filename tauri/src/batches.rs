@@ -0,0 +1,262 @@
+// Generation runs recorded as batches: the configuration a run billed with and the invoices
+// it stored, so a run can be repeated for the next period or voided as a whole
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::generation;
+use crate::preview::{self, SplitPreview};
+use crate::runs::{self, RunLog};
+use crate::settings::InvoiceConfig;
+use crate::terms::{parse_date, RunOptions};
+use chrono::{Datelike, Local, Months};
+use serde::Serialize;
+
+// Months a re-run moves the dates on by unless told otherwise
+pub const DEFAULT_RERUN_MONTHS: u32 = 1;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Batch {
+    pub id: i64,
+    // When the run started and ended, how it ended and what it printed
+    pub log: RunLog,
+    pub config: InvoiceConfig,
+    pub options: RunOptions,
+    pub recipients: Vec<String>,
+    pub invoice_date: String,
+    // Invoices the run stored, also when it failed partway
+    pub invoice_numbers: Vec<String>,
+    // The batch this one repeated
+    pub rerun_of: Option<i64>,
+    // Empty unless the batch was voided
+    pub voided_at: String,
+    pub void_reason: String,
+}
+
+// A finished run to record
+pub struct NewBatch<'a> {
+    pub run_log_id: i64,
+    pub config: &'a InvoiceConfig,
+    pub options: &'a RunOptions,
+    pub recipients: &'a [String],
+    pub invoice_date: &'a str,
+    pub invoice_numbers: &'a [String],
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(|e| Error::invalid("Failed to serialize batch").details(e))
+}
+
+pub fn record_batch(batch: &NewBatch) -> Result<i64, Error> {
+    let conn = connect_database()?;
+
+    conn.execute(
+        "INSERT INTO batches (run_log_id, config, options, recipients, invoice_date,
+         invoice_numbers) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            batch.run_log_id,
+            encode(batch.config)?,
+            encode(batch.options)?,
+            encode(&batch.recipients)?,
+            batch.invoice_date,
+            batch.invoice_numbers.join(",")
+        ],
+    )
+    .map_err(|e| Error::database("Failed to record batch").details(e))?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+// The batch columns as stored, before the JSON in them is decoded
+struct BatchRow {
+    id: i64,
+    config: String,
+    options: String,
+    recipients: String,
+    invoice_date: String,
+    invoice_numbers: String,
+    rerun_of: Option<i64>,
+    voided_at: String,
+    void_reason: String,
+    log: RunLog,
+}
+
+impl BatchRow {
+    fn decode(self) -> Result<Batch, Error> {
+        let invalid =
+            |e: serde_json::Error| Error::database(format!("Invalid batch {}", self.id)).details(e);
+        Ok(Batch {
+            id: self.id,
+            config: serde_json::from_str(&self.config).map_err(invalid)?,
+            options: serde_json::from_str(&self.options).map_err(invalid)?,
+            recipients: serde_json::from_str(&self.recipients).map_err(invalid)?,
+            invoice_date: self.invoice_date,
+            invoice_numbers: self
+                .invoice_numbers
+                .split(',')
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+                .collect(),
+            rerun_of: self.rerun_of,
+            voided_at: self.voided_at,
+            void_reason: self.void_reason,
+            log: self.log,
+        })
+    }
+}
+
+// The batch with the given id, or the most recent batches
+fn query_batches(id: Option<i64>, limit: u32) -> Result<Vec<Batch>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT batches.id, config, options, recipients, invoice_date, invoice_numbers,
+             rerun_of, voided_at, void_reason, {}
+             FROM batches JOIN run_logs ON run_logs.id = batches.run_log_id
+             WHERE ?1 IS NULL OR batches.id = ?1 ORDER BY batches.id DESC LIMIT ?2",
+            runs::RUN_LOG_COLUMNS
+        ))
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map(rusqlite::params![id, limit], |row| {
+            Ok(BatchRow {
+                id: row.get(0)?,
+                config: row.get(1)?,
+                options: row.get(2)?,
+                recipients: row.get(3)?,
+                invoice_date: row.get(4)?,
+                invoice_numbers: row.get(5)?,
+                rerun_of: row.get(6)?,
+                voided_at: row.get(7)?,
+                void_reason: row.get(8)?,
+                log: runs::run_log_from_row(row, 9)?,
+            })
+        })
+        .map_err(|e| Error::database("Failed to get batches").details(e))?;
+
+    rows.map(|row| {
+        row.map_err(|e| Error::database("Failed to read batch").details(e))?
+            .decode()
+    })
+    .collect()
+}
+
+// The most recent batches, newest first
+pub fn list_batches(limit: Option<u32>) -> Result<Vec<Batch>, Error> {
+    query_batches(None, limit.unwrap_or(20))
+}
+
+pub fn find_batch(id: i64) -> Result<Batch, Error> {
+    query_batches(Some(id), 1)?
+        .pop()
+        .ok_or_else(|| Error::not_found(format!("Batch {} not found", id)))
+}
+
+// The date `months` months after `date`, moved back to the last day of a shorter month. The
+// last day of a month stays the last day, so month ends do not drift to the 28th.
+pub fn add_months(date: &str, months: u32) -> Result<String, Error> {
+    let date = parse_date(date)?;
+    let month_end = date.succ_opt().is_some_and(|next| next.day() == 1);
+    let moved = if month_end {
+        date.with_day(1)
+            .and_then(|first| first.checked_add_months(Months::new(months + 1)))
+            .and_then(|first| first.pred_opt())
+    } else {
+        date.checked_add_months(Months::new(months))
+    };
+    moved
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| Error::invalid(format!("Date {} is out of range", date)))
+}
+
+// Options that repeat a batch `months` later. It is dated today, like any run, and bills for
+// its period moved on. A fixed due date becomes the same number of payment days.
+pub fn next_period_options(batch: &Batch, months: u32) -> Result<RunOptions, Error> {
+    if months == 0 {
+        return Err(Error::invalid(
+            "A re-run must move the dates on by at least one month",
+        ));
+    }
+
+    let period_date = batch
+        .options
+        .period_date
+        .as_deref()
+        .unwrap_or(&batch.invoice_date);
+    let payment_days = match batch.options.due_date.as_deref() {
+        Some(due_date) => {
+            Some((parse_date(due_date)? - parse_date(&batch.invoice_date)?).num_days() as u32)
+        }
+        None => batch.options.payment_days,
+    };
+
+    Ok(RunOptions {
+        invoice_date: Some(Local::now().date_naive().format("%Y-%m-%d").to_string()),
+        period_date: Some(add_months(period_date, months)?),
+        payment_days,
+        ..RunOptions::default()
+    })
+}
+
+fn rerun_options(id: i64, months: Option<u32>) -> Result<(Batch, RunOptions), Error> {
+    let batch = find_batch(id)?;
+    let options = next_period_options(&batch, months.unwrap_or(DEFAULT_RERUN_MONTHS))?;
+    Ok((batch, options))
+}
+
+// What repeating a batch would bill, with a hash to confirm the re-run with
+pub fn preview_batch_rerun(id: i64, months: Option<u32>) -> Result<SplitPreview, Error> {
+    let (batch, options) = rerun_options(id, months)?;
+    preview::preview_run(&batch.config, &options)
+}
+
+// Repeat a batch as previewed, with the configuration it was run with and its dates moved on
+pub fn rerun_batch(id: i64, config_hash: String, months: Option<u32>) -> Result<String, Error> {
+    let (batch, options) = rerun_options(id, months)?;
+    let result = generation::run_generation(&batch.config, false, &options, Some(&config_hash))?;
+    if let Some(new_id) = result.batch_id {
+        let conn = connect_database()?;
+        conn.execute(
+            "UPDATE batches SET rerun_of = ?1 WHERE id = ?2",
+            [batch.id, new_id],
+        )
+        .map_err(|e| Error::database("Failed to record re-run").details(e))?;
+    }
+
+    Ok(result.output)
+}
+
+// Void every invoice a batch stored. The invoices and their numbers stay, marked as voided.
+pub fn void_batch(id: i64, reason: String) -> Result<Vec<String>, Error> {
+    let batch = find_batch(id)?;
+    if !batch.voided_at.is_empty() {
+        return Err(Error::invalid(format!(
+            "Batch {} was already voided on {}",
+            id, batch.voided_at
+        )));
+    }
+    if batch.invoice_numbers.is_empty() {
+        return Err(Error::invalid(format!("Batch {} stored no invoices", id)));
+    }
+
+    let mut conn = connect_database()?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    for number in &batch.invoice_numbers {
+        tx.execute(
+            "INSERT OR IGNORE INTO voided_invoices (invoice_number, batch_id, reason)
+             VALUES (?1, ?2, ?3)",
+            rusqlite::params![number, id, reason.trim()],
+        )
+        .map_err(|e| Error::database(format!("Failed to void invoice {}", number)).details(e))?;
+    }
+    tx.execute(
+        "UPDATE batches SET voided_at = CURRENT_TIMESTAMP, void_reason = ?1 WHERE id = ?2",
+        rusqlite::params![reason.trim(), id],
+    )
+    .map_err(|e| Error::database("Failed to void batch").details(e))?;
+    tx.commit()
+        .map_err(|e| Error::database("Failed to void batch").details(e))?;
+
+    Ok(batch.invoice_numbers)
+}
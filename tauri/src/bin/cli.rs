@@ -0,0 +1,919 @@
+// Headless command-line interface sharing the desktop app's database and backend
+use base64::{engine::general_purpose, Engine as _};
+use invoice_splitter_lib::diagnostics::{self, CheckStatus};
+use invoice_splitter_lib::preview::SplitPreview;
+use invoice_splitter_lib::schedule::{self, Schedule};
+use invoice_splitter_lib::settings::CONFIG_KEYS;
+use invoice_splitter_lib::terms::RunOptions;
+use invoice_splitter_lib::{
+    api, backup, batches, cloning, drafts, generation, invoices, placeholders, preview, revisions,
+    runs, settings, templates,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: invoice-splitter-cli [--json] <command>
+
+Commands:
+  config list                     Show all invoice configuration values
+  config get <key>                Print one configuration value
+  config set <key> <value>        Set a configuration value (use - to read it from stdin)
+  preview [<run options>]         Show who the next run would bill and a hash to confirm it with
+  generate [--dry-run] [<run options>]
+                                  Generate invoices for all recipients
+  generate --confirm <hash> [<run options>]
+                                  Generate only if the configuration still matches the preview
+  invoices list                   List stored invoices
+  invoices export <id> [--out <path>]
+                                  Write the PDF of one invoice to a file
+  invoices export --all [--out <dir>]
+                                  Write the PDFs of all invoices to a directory
+  invoices regenerate <id> [--reason <text>] [--out <path>]
+                                  Render an invoice again as a new revision, keeping the original PDF.
+                                  Revisions always use the built-in renderer
+  invoices correct <id> <json> --reason <text> [--out <path>]
+                                  Render corrected invoice data as a new revision (use - for stdin)
+  invoices revisions <id>         List the revisions of an invoice
+  invoices diff <id> <from> <to>  Show the fields that differ between two revisions
+  invoices clone <id> [--months <n>] [--to draft|settings]
+                                  Copy an invoice into a new draft (default) or the configuration,
+                                  its dates moved on by n months (default 1)
+  templates list                  List saved invoice templates
+  templates create <name> [<json>]
+                                  Save the current configuration, or the given values, as a template
+  templates duplicate <name> <new name>
+                                  Copy a template under a new name
+  templates delete <name>         Delete a template
+  templates load <name>           Replace the current configuration values with the template's
+  templates preview <name> [<run options>]
+                                  Show what the template would bill and a hash to confirm it with
+  templates generate <name> --confirm <hash> [<run options>]
+                                  Generate from a template without changing the configuration
+  schedules list                  List recurring schedules
+  schedules add <json>            Add a schedule (use - to read it from stdin)
+  schedules delete <id>           Delete a schedule
+  schedules due                   List scheduled runs that are due
+  schedules preview <id> <date>   Show what a due run would bill and a hash to confirm it with
+  schedules run <id> <date> --confirm <hash>
+                                  Generate a due run if it still matches the preview
+  schedules skip <id> <date>      Mark a due run as done without generating it
+  batches list                    List recent generation runs with the invoices each stored
+  batches preview <id> [--months <n>]
+                                  Show what a re-run n months on (default 1) would bill
+  batches rerun <id> [--months <n>] --confirm <hash>
+                                  Repeat a batch with its configuration for its period moved on, dated today
+  batches void <id> [--reason <text>]
+                                  Void every invoice a batch stored
+  drafts list                     List draft invoices
+  drafts create [<run options>]   Create one draft per recipient from the current configuration
+  drafts save <json>              Create or update a draft (use - to read it from stdin)
+  drafts preview <id> [--out <path>]
+                                  Write the watermarked PDF of a draft to a file
+  drafts finalize <id>            Give a draft the next invoice number and store it as an invoice
+  drafts delete <id>              Delete a draft that was not finalized
+  expand <text> [--recipient <name>] [--invoice-date <YYYY-MM-DD>]
+                                  Show text with its placeholders expanded for one recipient
+  diagnostics                     Check the backend, database and output directory
+  runs [--limit <n>] [--verbose]  Show recent generation runs; --verbose includes their output
+  backup [--out <path>]           Back up the database
+  backup list                     List existing backups
+  serve [--port <port>]           Run the local HTTP API until interrupted
+
+Run options:
+  --invoice-date <YYYY-MM-DD>     Backdate the invoices within the open period
+  --payment-days <days>           Payment terms for every recipient in this run
+  --due-date <YYYY-MM-DD>         One due date for every invoice in this run
+  --force                         Generate even if recent invoices billed the same
+
+Configuration keys: sender, bankdetails, description, amount, currency, recipients, split, line_items, vat, terms";
+
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn print<T: Serialize>(&self, value: &T, text: impl FnOnce() -> String) {
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(value).unwrap_or_else(|_| "null".to_string())
+            );
+        } else {
+            println!("{}", text());
+        }
+    }
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|a| a == name) {
+        Some(index) => {
+            if index + 1 >= args.len() {
+                return Err(format!("{} requires a value", name));
+            }
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Ok(Some(value))
+        }
+        None => Ok(None),
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|a| a == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn take_run_options(args: &mut Vec<String>) -> Result<RunOptions, String> {
+    let payment_days = take_option(args, "--payment-days")?
+        .map(|days| {
+            days.parse()
+                .map_err(|_| format!("Invalid number of payment days '{}'", days))
+        })
+        .transpose()?;
+
+    Ok(RunOptions {
+        invoice_date: take_option(args, "--invoice-date")?,
+        payment_days,
+        due_date: take_option(args, "--due-date")?,
+        force: take_flag(args, "--force"),
+        ..RunOptions::default()
+    })
+}
+
+fn check_config_key(key: &str) -> Result<(), String> {
+    if CONFIG_KEYS.contains(&key) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown configuration key '{}'. Valid keys: {}",
+            key,
+            CONFIG_KEYS.join(", ")
+        ))
+    }
+}
+
+// A value given on the command line, or read from stdin for "-"
+fn read_value(value: &str) -> Result<String, String> {
+    if value != "-" {
+        return Ok(value.to_string());
+    }
+    let mut buffer = String::new();
+    std::io::stdin()
+        .read_to_string(&mut buffer)
+        .map_err(|e| format!("Failed to read value from stdin: {}", e))?;
+    Ok(buffer)
+}
+
+fn config_command(args: &[String], out: &Output) -> Result<(), String> {
+    match args {
+        [cmd] if cmd == "list" => {
+            let values = CONFIG_KEYS
+                .iter()
+                .map(|key| Ok((*key, settings::get_config_setting(key.to_string())?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            let object: serde_json::Map<_, _> = values
+                .iter()
+                .map(|(key, value)| (key.to_string(), json!(value)))
+                .collect();
+            out.print(&object, || {
+                values
+                    .iter()
+                    .map(|(key, value)| format!("[{}]\n{}\n", key, value))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        [cmd, key] if cmd == "get" => {
+            check_config_key(key)?;
+            let value = settings::get_config_setting(key.clone())?;
+            out.print(&json!({ "key": key, "value": value }), || value.clone());
+            Ok(())
+        }
+        [cmd, key, value] if cmd == "set" => {
+            check_config_key(key)?;
+            settings::set_config_setting(key.clone(), read_value(value)?)?;
+            out.print(&json!({ "key": key, "updated": true }), || {
+                format!("Updated {}", key)
+            });
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn preview_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let options = take_run_options(&mut args)?;
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let preview = preview::preview_split(Some(options))?;
+    print_preview(&preview, "generate", out);
+    Ok(())
+}
+
+// `confirm` is the command line that generates what was previewed, without the hash
+fn print_preview(preview: &SplitPreview, confirm: &str, out: &Output) {
+    out.print(preview, || {
+        let mut lines: Vec<String> = preview
+            .lines
+            .iter()
+            .map(|l| {
+                format!(
+                    "{:<12} {:>12}  due {}  {}",
+                    l.invoice_number, l.total, l.dates.due_date, l.recipient
+                )
+            })
+            .collect();
+        lines.push(format!("{:<12} {:>12}", "Total", preview.total));
+        lines.extend(preview.warnings.iter().map(|w| format!("Warning: {}", w)));
+        lines.push(format!(
+            "\nGenerate with: invoice-splitter-cli {} --confirm {}",
+            confirm, preview.config_hash
+        ));
+        lines.join("\n")
+    });
+}
+
+fn generate_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let dry_run = take_flag(&mut args, "--dry-run");
+    let confirm = take_option(&mut args, "--confirm")?;
+    let options = take_run_options(&mut args)?;
+    if !args.is_empty() || (dry_run && confirm.is_some()) {
+        return Err(USAGE.to_string());
+    }
+
+    let log = match confirm {
+        Some(config_hash) => preview::confirm_generation(config_hash, Some(options))?,
+        None => generation::generate_invoices(dry_run, Some(options))?,
+    };
+    out.print(&json!({ "dry_run": dry_run, "output": log }), || {
+        log.clone()
+    });
+    Ok(())
+}
+
+fn write_invoice_pdf(record: &invoices::InvoiceRecord, target: &PathBuf) -> Result<(), String> {
+    let bytes = general_purpose::STANDARD
+        .decode(&record.pdf_base64)
+        .map_err(|e| format!("Failed to decode PDF for {}: {}", record.invoice_number, e))?;
+
+    fs::write(target, bytes).map_err(|e| format!("Failed to write {}: {}", target.display(), e))
+}
+
+fn write_revision_pdf(
+    revision: &revisions::InvoiceRevision,
+    target: Option<String>,
+    out: &Output,
+) -> Result<(), String> {
+    let path = PathBuf::from(target.unwrap_or_else(|| {
+        format!(
+            "invoice-{}-r{}.pdf",
+            revision.invoice_number, revision.revision
+        )
+    }));
+    let bytes = general_purpose::STANDARD
+        .decode(&revision.pdf_base64)
+        .map_err(|e| format!("Failed to decode PDF: {}", e))?;
+    fs::write(&path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    let path = path.to_string_lossy().to_string();
+    out.print(
+        &json!({
+            "invoice_number": revision.invoice_number,
+            "revision": revision.revision,
+            "path": path,
+            "warnings": revision.warnings,
+        }),
+        || {
+            let mut lines: Vec<String> = revision
+                .warnings
+                .iter()
+                .map(|w| format!("Warning: {}", w))
+                .collect();
+            lines.push(format!(
+                "Revision {} written to {}",
+                revision.revision, path
+            ));
+            lines.join("\n")
+        },
+    );
+    Ok(())
+}
+
+fn invoices_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let target = take_option(&mut args, "--out")?;
+    let reason = take_option(&mut args, "--reason")?;
+    let months = take_option(&mut args, "--months")?
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid number of months '{}'", n))
+        })
+        .transpose()?;
+    let clone_target = take_option(&mut args, "--to")?;
+    let all = take_flag(&mut args, "--all");
+    let parse_id = |id: &str| {
+        id.parse::<i32>()
+            .map_err(|_| format!("Invalid invoice id '{}'", id))
+    };
+
+    match args.as_slice() {
+        [cmd] if cmd == "list" => {
+            let summaries = invoices::list_invoice_summaries()?;
+            out.print(&summaries, || {
+                summaries
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "{:>5}  {:<12} {:<10} {:<10} {}",
+                            r.id, r.invoice_number, r.invoice_date, r.due_date, r.service
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        [cmd] if cmd == "export" && all => {
+            let dir = PathBuf::from(target.unwrap_or_else(|| ".".to_string()));
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+            let mut written = Vec::new();
+            for record in invoices::get_all_invoices()? {
+                let path = dir.join(format!("invoice-{}.pdf", record.invoice_number));
+                write_invoice_pdf(&record, &path)?;
+                written.push(path.to_string_lossy().to_string());
+            }
+            out.print(&written, || written.join("\n"));
+            Ok(())
+        }
+        [cmd, id] if cmd == "export" => {
+            let id = parse_id(id)?;
+            let record = invoices::get_invoice_by_id(id)?;
+            let path = PathBuf::from(
+                target.unwrap_or_else(|| format!("invoice-{}.pdf", record.invoice_number)),
+            );
+            write_invoice_pdf(&record, &path)?;
+            let path = path.to_string_lossy().to_string();
+            out.print(&json!({ "id": id, "path": path }), || path.clone());
+            Ok(())
+        }
+        [cmd, id] if cmd == "regenerate" => {
+            let revision =
+                revisions::regenerate_invoice(parse_id(id)?, reason.unwrap_or_default())?;
+            write_revision_pdf(&revision, target, out)
+        }
+        [cmd, id, data] if cmd == "correct" => {
+            let data = serde_json::from_str(&read_value(data)?)
+                .map_err(|e| format!("Invalid invoice data: {}", e))?;
+            let revision =
+                revisions::correct_invoice(parse_id(id)?, data, reason.unwrap_or_default())?;
+            write_revision_pdf(&revision, target, out)
+        }
+        [cmd, id, from, to] if cmd == "diff" => {
+            let parse_revision = |r: &str| {
+                r.parse::<u32>()
+                    .map_err(|_| format!("Invalid revision '{}'", r))
+            };
+            let changes = revisions::diff_invoice_revisions(
+                parse_id(id)?,
+                parse_revision(from)?,
+                parse_revision(to)?,
+            )?;
+            out.print(&changes, || {
+                if changes.is_empty() {
+                    return "The revisions have the same data".to_string();
+                }
+                changes
+                    .iter()
+                    .map(|c| format!("{}: {:?} -> {:?}", c.field, c.before, c.after))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        [cmd, id] if cmd == "clone" => match clone_target.as_deref().unwrap_or("draft") {
+            "draft" => {
+                let draft = cloning::clone_invoice_to_draft(parse_id(id)?, months)?;
+                out.print(&draft, || {
+                    format!(
+                        "Created draft {} for {} dated {}",
+                        draft.id, draft.customer_name, draft.invoice_date
+                    )
+                });
+                Ok(())
+            }
+            "settings" => {
+                cloning::clone_invoice_to_settings(parse_id(id)?, months)?;
+                out.print(&json!({ "id": id, "copied_to": "settings" }), || {
+                    format!("Copied invoice {} into the configuration", id)
+                });
+                Ok(())
+            }
+            other => Err(format!(
+                "Unknown clone target '{}'; use draft or settings",
+                other
+            )),
+        },
+        [cmd, id] if cmd == "revisions" => {
+            let list = revisions::list_invoice_revisions(parse_id(id)?)?;
+            out.print(&list, || {
+                if list.is_empty() {
+                    return "The invoice was never rendered again or corrected".to_string();
+                }
+                list.iter()
+                    .map(|r| format!("{:>3}  {}  {}", r.revision, r.created_at, r.reason))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn templates_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let confirm = take_option(&mut args, "--confirm")?;
+    let options = take_run_options(&mut args)?;
+
+    match (args.as_slice(), confirm) {
+        ([cmd], None) if cmd == "list" => {
+            let list = templates::list_templates()?;
+            out.print(&list, || {
+                list.iter()
+                    .map(|t| {
+                        let keys: Vec<&str> = t.values.keys().map(String::as_str).collect();
+                        format!("{:<30} {}", t.name, keys.join(", "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        ([cmd, name], None) if cmd == "create" => {
+            let template = templates::create_template(name.clone(), None)?;
+            out.print(&template, || format!("Saved template '{}'", template.name));
+            Ok(())
+        }
+        ([cmd, name, values], None) if cmd == "create" => {
+            let values = serde_json::from_str(&read_value(values)?)
+                .map_err(|e| format!("Invalid template values: {}", e))?;
+            let template = templates::create_template(name.clone(), Some(values))?;
+            out.print(&template, || format!("Saved template '{}'", template.name));
+            Ok(())
+        }
+        ([cmd, name, new_name], None) if cmd == "duplicate" => {
+            let template = templates::duplicate_template(name.clone(), new_name.clone())?;
+            out.print(&template, || format!("Saved template '{}'", template.name));
+            Ok(())
+        }
+        ([cmd, name], None) if cmd == "delete" => {
+            templates::delete_template(name.clone())?;
+            out.print(&json!({ "name": name, "deleted": true }), || {
+                format!("Deleted template '{}'", name)
+            });
+            Ok(())
+        }
+        ([cmd, name], None) if cmd == "load" => {
+            templates::load_template(name.clone())?;
+            out.print(&json!({ "name": name, "loaded": true }), || {
+                format!("Loaded template '{}' into the configuration", name)
+            });
+            Ok(())
+        }
+        ([cmd, name], None) if cmd == "preview" => {
+            let preview = templates::preview_template(name.clone(), Some(options))?;
+            print_preview(&preview, &format!("templates generate '{}'", name), out);
+            Ok(())
+        }
+        ([cmd, name], Some(config_hash)) if cmd == "generate" => {
+            let log = templates::generate_from_template(name.clone(), config_hash, Some(options))?;
+            out.print(&json!({ "output": log }), || log.clone());
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_schedule_id(id: &str) -> Result<i64, String> {
+    id.parse()
+        .map_err(|_| format!("Invalid schedule id '{}'", id))
+}
+
+fn schedules_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let confirm = take_option(&mut args, "--confirm")?;
+
+    match (args.as_slice(), confirm) {
+        ([cmd], None) if cmd == "list" => {
+            let schedules = schedule::list_schedules()?;
+            out.print(&schedules, || {
+                schedules
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "{:>5}  {:<30} {:?} on day {} from {}",
+                            s.id, s.name, s.cadence, s.day_of_month, s.start_date
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        ([cmd, definition], None) if cmd == "add" => {
+            let definition: Schedule = serde_json::from_str(&read_value(definition)?)
+                .map_err(|e| format!("Invalid schedule: {}", e))?;
+            let saved = schedule::save_schedule(Schedule {
+                id: 0,
+                ..definition
+            })?;
+            out.print(&saved, || format!("Added schedule {}", saved.id));
+            Ok(())
+        }
+        ([cmd, id], None) if cmd == "delete" => {
+            let id = parse_schedule_id(id)?;
+            schedule::delete_schedule(id)?;
+            out.print(&json!({ "id": id, "deleted": true }), || {
+                format!("Deleted schedule {}", id)
+            });
+            Ok(())
+        }
+        ([cmd], None) if cmd == "due" => {
+            let due = schedule::due_scheduled_runs()?;
+            out.print(&due, || {
+                if due.is_empty() {
+                    return "No scheduled runs are due".to_string();
+                }
+                due.iter()
+                    .map(|r| format!("{:>5}  {}  {}", r.schedule_id, r.run_date, r.name))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        ([cmd, id, date], None) if cmd == "preview" => {
+            let preview = schedule::preview_scheduled_run(parse_schedule_id(id)?, date.clone())?;
+            print_preview(&preview, &format!("schedules run {} {}", id, date), out);
+            Ok(())
+        }
+        ([cmd, id, date], Some(config_hash)) if cmd == "run" => {
+            let log = schedule::run_scheduled(parse_schedule_id(id)?, date.clone(), config_hash)?;
+            out.print(&json!({ "output": log }), || log.clone());
+            Ok(())
+        }
+        ([cmd, id, date], None) if cmd == "skip" => {
+            schedule::skip_scheduled_run(parse_schedule_id(id)?, date.clone())?;
+            out.print(&json!({ "run_date": date, "skipped": true }), || {
+                format!("Skipped the run of {}", date)
+            });
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn batches_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let confirm = take_option(&mut args, "--confirm")?;
+    let months = take_option(&mut args, "--months")?
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid number of months '{}'", n))
+        })
+        .transpose()?;
+    let reason = take_option(&mut args, "--reason")?;
+    let parse_id = |id: &str| {
+        id.parse::<i64>()
+            .map_err(|_| format!("Invalid batch id '{}'", id))
+    };
+
+    match (args.as_slice(), confirm) {
+        ([cmd], None) if cmd == "list" => {
+            let batches = batches::list_batches(None)?;
+            out.print(&batches, || {
+                batches
+                    .iter()
+                    .map(|b| {
+                        let state = match (b.log.succeeded, b.voided_at.is_empty()) {
+                            (_, false) => "VOID",
+                            (true, true) => "ok",
+                            (false, true) => "FAIL",
+                        };
+                        format!(
+                            "{:>5}  {}  {:<4} {} for {}: {}",
+                            b.id,
+                            b.log.started_at,
+                            state,
+                            b.invoice_date,
+                            b.recipients.join(", "),
+                            b.invoice_numbers.join(", ")
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        ([cmd, id], None) if cmd == "preview" => {
+            let preview = batches::preview_batch_rerun(parse_id(id)?, months)?;
+            let rerun = match months {
+                Some(months) => format!("batches rerun {} --months {}", id, months),
+                None => format!("batches rerun {}", id),
+            };
+            print_preview(&preview, &rerun, out);
+            Ok(())
+        }
+        ([cmd, id], Some(config_hash)) if cmd == "rerun" => {
+            let log = batches::rerun_batch(parse_id(id)?, config_hash, months)?;
+            out.print(&json!({ "output": log }), || log.clone());
+            Ok(())
+        }
+        ([cmd, id], None) if cmd == "void" => {
+            let voided = batches::void_batch(parse_id(id)?, reason.unwrap_or_default())?;
+            out.print(&voided, || format!("Voided {}", voided.join(", ")));
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn drafts_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let target = take_option(&mut args, "--out")?;
+    let options = take_run_options(&mut args)?;
+    let parse_id = |id: &str| {
+        id.parse::<i64>()
+            .map_err(|_| format!("Invalid draft id '{}'", id))
+    };
+    let describe = |d: &drafts::Draft| {
+        let state = if d.invoice_number.is_empty() {
+            "draft".to_string()
+        } else {
+            format!("invoice {}", d.invoice_number)
+        };
+        format!(
+            "{:>5}  {:<10} {:<30} {} lines, {}",
+            d.id,
+            d.invoice_date,
+            d.customer_name,
+            d.lines.len(),
+            state
+        )
+    };
+
+    match args.as_slice() {
+        [cmd] if cmd == "list" => {
+            let list = drafts::list_drafts()?;
+            out.print(&list, || {
+                list.iter().map(describe).collect::<Vec<_>>().join("\n")
+            });
+            Ok(())
+        }
+        [cmd] if cmd == "create" => {
+            let created = drafts::create_drafts(Some(options))?;
+            out.print(&created, || {
+                created.iter().map(describe).collect::<Vec<_>>().join("\n")
+            });
+            Ok(())
+        }
+        [cmd, draft] if cmd == "save" => {
+            let draft: drafts::Draft = serde_json::from_str(&read_value(draft)?)
+                .map_err(|e| format!("Invalid draft: {}", e))?;
+            let saved = drafts::save_draft(draft)?;
+            out.print(&saved, || format!("Saved draft {}", saved.id));
+            Ok(())
+        }
+        [cmd, id] if cmd == "preview" => {
+            let id = parse_id(id)?;
+            let pdf = drafts::preview_draft(drafts::find_draft(id)?)?;
+            let bytes = general_purpose::STANDARD
+                .decode(pdf)
+                .map_err(|e| format!("Failed to decode PDF for draft {}: {}", id, e))?;
+            let path = PathBuf::from(target.unwrap_or_else(|| format!("draft-{}.pdf", id)));
+            fs::write(&path, bytes)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            let path = path.to_string_lossy().to_string();
+            out.print(&json!({ "id": id, "path": path }), || path.clone());
+            Ok(())
+        }
+        [cmd, id] if cmd == "finalize" => {
+            let draft = drafts::finalize_draft(parse_id(id)?)?;
+            out.print(&draft, || {
+                let mut lines: Vec<String> = draft
+                    .warnings
+                    .iter()
+                    .map(|w| format!("Warning: {}", w))
+                    .collect();
+                lines.push(format!(
+                    "Finalized draft {} as invoice {}",
+                    draft.id, draft.invoice_number
+                ));
+                lines.join("\n")
+            });
+            Ok(())
+        }
+        [cmd, id] if cmd == "delete" => {
+            let id = parse_id(id)?;
+            drafts::delete_draft(id)?;
+            out.print(&json!({ "id": id, "deleted": true }), || {
+                format!("Deleted draft {}", id)
+            });
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn expand_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let recipient = take_option(&mut args, "--recipient")?;
+    let invoice_date = take_option(&mut args, "--invoice-date")?;
+    let [text] = args.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let expanded = placeholders::expand_text(text.clone(), recipient, invoice_date)?;
+    out.print(&json!({ "text": expanded }), || expanded.clone());
+    Ok(())
+}
+
+fn diagnostics_command(args: Vec<String>, out: &Output) -> Result<(), String> {
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let report = diagnostics::run_diagnostics()?;
+    out.print(&report, || {
+        report
+            .checks
+            .iter()
+            .map(|check| {
+                let mark = match check.status {
+                    CheckStatus::Ok => "ok  ",
+                    CheckStatus::Warning => "warn",
+                    CheckStatus::Failed => "FAIL",
+                };
+                let mut line = format!("[{}] {}: {}", mark, check.name, check.detail);
+                if !check.fix.is_empty() {
+                    line.push_str(&format!("\n       Fix: {}", check.fix));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+
+    if report.ok {
+        Ok(())
+    } else {
+        Err("Some checks failed".to_string())
+    }
+}
+
+fn runs_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let limit = take_option(&mut args, "--limit")?
+        .map(|n| n.parse().map_err(|_| format!("Invalid limit '{}'", n)))
+        .transpose()?;
+    let verbose = take_flag(&mut args, "--verbose");
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let logs = runs::list_run_logs(limit)?;
+    out.print(&logs, || {
+        logs.iter()
+            .map(|log| {
+                let mut text = format!(
+                    "{:>5}  {}  {:<4} {}{}: {}",
+                    log.id,
+                    log.started_at,
+                    if log.succeeded { "ok" } else { "FAIL" },
+                    log.renderer,
+                    if log.dry_run { " (dry run)" } else { "" },
+                    log.detail
+                );
+                if verbose {
+                    for (name, output) in [("stdout", &log.stdout), ("stderr", &log.stderr)] {
+                        if !output.trim().is_empty() {
+                            text.push_str(&format!("\n  {}:\n{}", name, output.trim_end()));
+                        }
+                    }
+                }
+                text
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    Ok(())
+}
+
+fn backup_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let target = take_option(&mut args, "--out")?;
+
+    match args.as_slice() {
+        [] => {
+            let info = backup::backup_database(target)?;
+            out.print(&info, || format!("Backup written to {}", info.path));
+            Ok(())
+        }
+        [cmd] if cmd == "list" => {
+            let backups = backup::list_backups()?;
+            out.print(&backups, || {
+                backups
+                    .iter()
+                    .map(|b| format!("{}  ({} bytes)", b.path, b.size_bytes))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn serve_command(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    let port = take_option(&mut args, "--port")?;
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut app_settings = settings::get_app_settings()?;
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("Invalid port '{}'", port))?,
+        None => app_settings.api_port,
+    };
+
+    // Reuse the token from the app settings so clients work with either entry point
+    if app_settings.api_token.trim().is_empty() {
+        app_settings.api_token = api::generate_token()?;
+        settings::save_app_settings(app_settings.clone())?;
+    }
+
+    let server = api::start(port, app_settings.api_token)?;
+    let address = server.addr().to_string();
+    out.print(&json!({ "listening": address }), || {
+        format!(
+            "Local API listening on http://{} (token in the app settings)",
+            address
+        )
+    });
+    server.wait();
+    Ok(())
+}
+
+fn run(mut args: Vec<String>, out: &Output) -> Result<(), String> {
+    if args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let command = args.remove(0);
+    match command.as_str() {
+        "config" => config_command(&args, out),
+        "preview" => preview_command(args, out),
+        "generate" => generate_command(args, out),
+        "invoices" => invoices_command(args, out),
+        "templates" => templates_command(args, out),
+        "schedules" => schedules_command(args, out),
+        "expand" => expand_command(args, out),
+        "diagnostics" => diagnostics_command(args, out),
+        "runs" => runs_command(args, out),
+        "batches" => batches_command(args, out),
+        "drafts" => drafts_command(args, out),
+        "backup" => backup_command(args, out),
+        "serve" => serve_command(args, out),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("Unknown command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let out = Output {
+        json: take_flag(&mut args, "--json"),
+    };
+
+    match run(args, &out) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            if out.json {
+                println!("{}", json!({ "error": message }));
+            } else {
+                eprintln!("{}", message);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
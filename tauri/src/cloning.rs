@@ -0,0 +1,172 @@
+// Billing the same customer the same thing again: a stored invoice copied, with its latest
+// corrections, into a new draft or into the configuration, its dates moved on by whole months
+use crate::batches::{self, DEFAULT_RERUN_MONTHS};
+use crate::database::connect_database;
+use crate::drafts::{self, Draft, DraftLine};
+use crate::errors::Error;
+use crate::invoices;
+use crate::line_items::{LineItem, LINE_ITEMS_KEY};
+use crate::money::{Currency, Money};
+use crate::revisions::{self, InvoiceData};
+use crate::settings::{self, CURRENCY_KEY};
+use crate::split::SPLIT_KEY;
+use crate::terms::{parse_date, Terms, TERMS_KEY};
+use crate::vat::{VatCode, VatSettings, VatTreatment, VAT_KEY};
+use std::collections::BTreeMap;
+
+// The invoice's data with its dates moved on by `months`, and what it billed as
+fn shifted_invoice(id: i32, months: Option<u32>) -> Result<(InvoiceData, String), Error> {
+    let months = months.unwrap_or(DEFAULT_RERUN_MONTHS);
+    let data = revisions::current_invoice_data(&connect_database()?, id)?;
+    let service = invoices::get_invoice_by_id(id)?.service;
+
+    let shift = |date: &str| match parse_date(date) {
+        Ok(_) => batches::add_months(date, months),
+        // Line dates are free text and kept as they are when they are not dates
+        Err(_) => Ok(date.to_string()),
+    };
+    let mut lines = data.lines.clone();
+    for line in &mut lines {
+        line.date = shift(&line.date)?;
+    }
+
+    Ok((
+        InvoiceData {
+            invoice_date: batches::add_months(&data.invoice_date, months)?,
+            due_date: batches::add_months(&data.due_date, months)?,
+            lines,
+            ..data
+        },
+        service,
+    ))
+}
+
+// The VAT of the invoice's lines. Only the invoice's VAT summary tells the rates apart, so
+// an invoice with several rates is copied with its main rate on every line.
+fn vat_code(data: &InvoiceData) -> VatCode {
+    match data.vat_summary.as_slice() {
+        _ if !data.vat_enabled => VatCode::NONE,
+        [only] => VatCode {
+            rate: only.rate,
+            treatment: only.treatment,
+        },
+        _ => VatCode {
+            rate: data.vat_rate,
+            treatment: VatTreatment::Standard,
+        },
+    }
+}
+
+// A new draft billing the invoice's customer the same lines, `months` later (one by default)
+pub fn clone_invoice_to_draft(id: i32, months: Option<u32>) -> Result<Draft, Error> {
+    let (data, _) = shifted_invoice(id, months)?;
+    let code = vat_code(&data);
+
+    drafts::save_draft(Draft {
+        customer_name: data.parties.customer_name,
+        customer_address: data.parties.customer_address,
+        invoice_date: data.invoice_date,
+        due_date: data.due_date,
+        currency: data.parties.currency,
+        sender: data.parties.sender,
+        bank_details: data.parties.bank_details,
+        vat_enabled: data.vat_enabled,
+        lines: data
+            .lines
+            .into_iter()
+            .map(|line| DraftLine {
+                date: line.date,
+                description: line.description,
+                amount: line.price,
+                vat_rate: code.rate,
+                vat_treatment: code.treatment,
+            })
+            .collect(),
+        ..Draft::default()
+    })
+}
+
+// Replace the configuration with the invoice's sender, customer, lines and VAT, so the next run
+// bills that customer alone the same again. The invoice date, moved on by `months`, becomes
+// the fixed invoice date of the payment terms, with the invoice's payment days.
+pub fn clone_invoice_to_settings(id: i32, months: Option<u32>) -> Result<(), Error> {
+    let (data, service) = shifted_invoice(id, months)?;
+    let currency = Currency::from_code(&data.parties.currency)?;
+    let code = vat_code(&data);
+
+    let mut net = Money::zero(currency);
+    for line in &data.lines {
+        net.minor += Money::parse(&line.price, currency)?.minor;
+    }
+    let description = match data.lines.as_slice() {
+        [only] => only.description.clone(),
+        _ if !service.trim().is_empty() => service,
+        lines => lines
+            .first()
+            .map(|line| line.description.clone())
+            .unwrap_or_default(),
+    };
+    // A single line is billed with the description and amount settings
+    let line_items = if data.lines.len() > 1 {
+        let items: Vec<LineItem> = data
+            .lines
+            .iter()
+            .map(|line| LineItem {
+                description: line.description.clone(),
+                date: line.date.clone(),
+                quantity: "1".to_string(),
+                unit: String::new(),
+                unit_price: line.price.clone(),
+                vat_rate: None,
+                vat_treatment: None,
+            })
+            .collect();
+        serde_json::to_string(&items)
+            .map_err(|e| Error::invalid("Failed to serialize lines").details(e))?
+    } else {
+        String::new()
+    };
+    let vat = VatSettings {
+        enabled: data.vat_enabled,
+        rate: if data.vat_enabled {
+            code.rate
+        } else {
+            VatSettings::default().rate
+        },
+        treatment: code.treatment,
+    };
+    let payment_days = (parse_date(&data.due_date)? - parse_date(&data.invoice_date)?)
+        .num_days()
+        .max(0) as u32;
+    let terms = Terms {
+        invoice_date: data.invoice_date.clone(),
+        payment_days,
+        recipients: BTreeMap::new(),
+    };
+    let recipient = std::iter::once(data.parties.customer_name.clone())
+        .chain(data.parties.customer_address.iter().cloned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let vat = serde_json::to_string(&vat)
+        .map_err(|e| Error::invalid("Failed to serialize VAT settings").details(e))?;
+    let terms = serde_json::to_string(&terms)
+        .map_err(|e| Error::invalid("Failed to serialize payment terms").details(e))?;
+
+    let values: BTreeMap<String, String> = [
+        ("sender", data.parties.sender.join("\n")),
+        ("bankdetails", data.parties.bank_details.join("\n")),
+        ("recipients", recipient),
+        ("description", description),
+        ("amount", net.plain()),
+        (CURRENCY_KEY, currency.code.to_string()),
+        (SPLIT_KEY, String::new()),
+        (LINE_ITEMS_KEY, line_items),
+        (VAT_KEY, vat),
+        (TERMS_KEY, terms),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect();
+
+    settings::store_config_values(&values)
+}
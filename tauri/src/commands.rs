@@ -0,0 +1,373 @@
+// The commands the frontend invokes: the library's functions, with their errors turned into
+// structured command errors
+use crate::backup::{self, BackupInfo};
+use crate::batches::{self, Batch};
+use crate::diagnostics::{self, Diagnostics};
+use crate::drafts::{self, Draft};
+use crate::errors::CommandError;
+use crate::invoices::{self, InvoiceRecord};
+use crate::line_items::{self, LineItem, LineTotals};
+use crate::preview::{self, InvoicePreview, SplitPreview};
+use crate::revisions::{self, FieldChange, InvoiceData, InvoiceRevision};
+use crate::runs::{self, RunLog};
+use crate::schedule::{self, DueRun, Schedule, ScheduleRun};
+use crate::settings::{self, AppSettings, InvoiceFiles};
+use crate::split::{self, Allocation, SplitConfig};
+use crate::templates::{self, Template};
+use crate::terms::{self, RunOptions, Terms};
+use crate::vat::{self, VatSettings};
+use crate::{cloning, database, generation, placeholders};
+use std::collections::BTreeMap;
+
+#[tauri::command]
+pub fn read_file(file_path: String) -> Result<String, CommandError> {
+    settings::read_file(file_path).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn write_file(file_path: String, content: String) -> Result<(), CommandError> {
+    settings::write_file(file_path, content).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn read_all_files() -> Result<InvoiceFiles, CommandError> {
+    settings::read_all_files().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_invoice_details(description: String, amount: String) -> Result<(), CommandError> {
+    settings::save_invoice_details(description, amount).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn generate_invoices(
+    dry_run: bool,
+    options: Option<RunOptions>,
+) -> Result<String, CommandError> {
+    generation::generate_invoices(dry_run, options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_all_invoices() -> Result<Vec<InvoiceRecord>, CommandError> {
+    invoices::get_all_invoices().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_invoice_by_id(id: i32) -> Result<InvoiceRecord, CommandError> {
+    invoices::get_invoice_by_id(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn regenerate_invoice(id: i32, reason: String) -> Result<InvoiceRevision, CommandError> {
+    revisions::regenerate_invoice(id, reason).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_invoice_revisions(id: i32) -> Result<Vec<InvoiceRevision>, CommandError> {
+    revisions::list_invoice_revisions(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn correct_invoice(
+    id: i32,
+    data: InvoiceData,
+    reason: String,
+) -> Result<InvoiceRevision, CommandError> {
+    revisions::correct_invoice(id, data, reason).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn diff_invoice_revisions(
+    id: i32,
+    from: u32,
+    to: u32,
+) -> Result<Vec<FieldChange>, CommandError> {
+    revisions::diff_invoice_revisions(id, from, to).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_app_settings() -> Result<AppSettings, CommandError> {
+    settings::get_app_settings().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_app_settings(settings: AppSettings) -> Result<(), CommandError> {
+    settings::save_app_settings(settings).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_config_setting(key: String) -> Result<String, CommandError> {
+    settings::get_config_setting(key).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn set_config_setting(key: String, value: String) -> Result<(), CommandError> {
+    settings::set_config_setting(key, value).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn is_first_run() -> Result<bool, CommandError> {
+    settings::is_first_run().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn reset_database() -> Result<(), CommandError> {
+    database::reset_database().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn backup_database(destination: Option<String>) -> Result<BackupInfo, CommandError> {
+    backup::backup_database(destination).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_backups() -> Result<Vec<BackupInfo>, CommandError> {
+    backup::list_backups().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_split_config() -> Result<SplitConfig, CommandError> {
+    split::get_split_config().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_split_config(config: SplitConfig) -> Result<(), CommandError> {
+    split::save_split_config(config).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn calculate_split() -> Result<Vec<Allocation>, CommandError> {
+    split::calculate_split().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_line_items() -> Result<Vec<LineItem>, CommandError> {
+    line_items::get_line_items().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_line_items(items: Vec<LineItem>) -> Result<LineTotals, CommandError> {
+    line_items::save_line_items(items).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn calculate_line_totals() -> Result<LineTotals, CommandError> {
+    line_items::calculate_line_totals().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_vat_settings() -> Result<VatSettings, CommandError> {
+    vat::get_vat_settings().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_vat_settings(settings: VatSettings) -> Result<(), CommandError> {
+    vat::save_vat_settings(settings).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_terms() -> Result<Terms, CommandError> {
+    terms::get_terms().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_terms(terms: Terms) -> Result<(), CommandError> {
+    terms::save_terms(terms).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_split(options: Option<RunOptions>) -> Result<SplitPreview, CommandError> {
+    preview::preview_split(options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn confirm_generation(
+    config_hash: String,
+    options: Option<RunOptions>,
+) -> Result<String, CommandError> {
+    preview::confirm_generation(config_hash, options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_invoice(
+    values: BTreeMap<String, String>,
+    recipient: String,
+    options: Option<RunOptions>,
+) -> Result<InvoicePreview, CommandError> {
+    preview::preview_invoice(values, recipient, options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_schedules() -> Result<Vec<Schedule>, CommandError> {
+    schedule::list_schedules().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_schedule(schedule: Schedule) -> Result<Schedule, CommandError> {
+    schedule::save_schedule(schedule).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn delete_schedule(id: i64) -> Result<(), CommandError> {
+    schedule::delete_schedule(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_schedule_runs(schedule_id: i64) -> Result<Vec<ScheduleRun>, CommandError> {
+    schedule::list_schedule_runs(schedule_id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn due_scheduled_runs() -> Result<Vec<DueRun>, CommandError> {
+    schedule::due_scheduled_runs().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_scheduled_run(
+    schedule_id: i64,
+    run_date: String,
+) -> Result<SplitPreview, CommandError> {
+    schedule::preview_scheduled_run(schedule_id, run_date).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn run_scheduled(
+    schedule_id: i64,
+    run_date: String,
+    config_hash: String,
+) -> Result<String, CommandError> {
+    schedule::run_scheduled(schedule_id, run_date, config_hash).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn skip_scheduled_run(schedule_id: i64, run_date: String) -> Result<(), CommandError> {
+    schedule::skip_scheduled_run(schedule_id, run_date).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_templates() -> Result<Vec<Template>, CommandError> {
+    templates::list_templates().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn create_template(
+    name: String,
+    values: Option<BTreeMap<String, String>>,
+) -> Result<Template, CommandError> {
+    templates::create_template(name, values).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn duplicate_template(name: String, new_name: String) -> Result<Template, CommandError> {
+    templates::duplicate_template(name, new_name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn delete_template(name: String) -> Result<(), CommandError> {
+    templates::delete_template(name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn load_template(name: String) -> Result<(), CommandError> {
+    templates::load_template(name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_template(
+    name: String,
+    options: Option<RunOptions>,
+) -> Result<SplitPreview, CommandError> {
+    templates::preview_template(name, options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn generate_from_template(
+    name: String,
+    config_hash: String,
+    options: Option<RunOptions>,
+) -> Result<String, CommandError> {
+    templates::generate_from_template(name, config_hash, options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn expand_text(
+    text: String,
+    recipient: Option<String>,
+    invoice_date: Option<String>,
+) -> Result<String, CommandError> {
+    placeholders::expand_text(text, recipient, invoice_date).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn run_diagnostics() -> Result<Diagnostics, CommandError> {
+    diagnostics::run_diagnostics().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_run_logs(limit: Option<u32>) -> Result<Vec<RunLog>, CommandError> {
+    runs::list_run_logs(limit).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_batches(limit: Option<u32>) -> Result<Vec<Batch>, CommandError> {
+    batches::list_batches(limit).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_batch_rerun(id: i64, months: Option<u32>) -> Result<SplitPreview, CommandError> {
+    batches::preview_batch_rerun(id, months).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn rerun_batch(
+    id: i64,
+    config_hash: String,
+    months: Option<u32>,
+) -> Result<String, CommandError> {
+    batches::rerun_batch(id, config_hash, months).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn void_batch(id: i64, reason: String) -> Result<Vec<String>, CommandError> {
+    batches::void_batch(id, reason).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn list_drafts() -> Result<Vec<Draft>, CommandError> {
+    drafts::list_drafts().map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn save_draft(draft: Draft) -> Result<Draft, CommandError> {
+    drafts::save_draft(draft).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn delete_draft(id: i64) -> Result<(), CommandError> {
+    drafts::delete_draft(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn create_drafts(options: Option<RunOptions>) -> Result<Vec<Draft>, CommandError> {
+    drafts::create_drafts(options).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn preview_draft(draft: Draft) -> Result<String, CommandError> {
+    drafts::preview_draft(draft).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn finalize_draft(id: i64) -> Result<Draft, CommandError> {
+    drafts::finalize_draft(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn clone_invoice_to_draft(id: i32, months: Option<u32>) -> Result<Draft, CommandError> {
+    cloning::clone_invoice_to_draft(id, months).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn clone_invoice_to_settings(id: i32, months: Option<u32>) -> Result<(), CommandError> {
+    cloning::clone_invoice_to_settings(id, months).map_err(CommandError::from)
+}
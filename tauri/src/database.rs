@@ -0,0 +1,287 @@
+use crate::errors::Error;
+use crate::settings::{get_app_settings, get_settings_path};
+use rusqlite::Connection;
+use std::fs;
+use std::path::PathBuf;
+
+// Overrides the database location, e.g. for scripts and tests
+pub const DATABASE_PATH_ENV: &str = "INVOICE_SPLITTER_DB";
+
+// Database functions
+pub fn get_database_path() -> Result<PathBuf, Error> {
+    if let Some(path) = std::env::var_os(DATABASE_PATH_ENV).filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    // Use project root for shared database access
+    let current_exe = std::env::current_exe()
+        .map_err(|e| Error::filesystem("Failed to get current executable").details(e))?;
+    let exe_dir = current_exe
+        .parent()
+        .ok_or_else(|| Error::filesystem("Failed to get executable directory"))?;
+
+    // In development, go up to project root from tauri/target/debug/
+    // In production, database should be in the same directory as the executable
+    let possible_paths = [
+        exe_dir.join("../../../invoices.db"), // Development: tauri/target/debug -> project root
+        exe_dir.join("../../invoices.db"),    // Alternative development path
+        exe_dir.join("invoices.db"),          // Production: same directory as executable
+        exe_dir.join("../invoices.db"),       // Alternative production path
+    ];
+
+    for path in possible_paths {
+        if path.exists() {
+            return path
+                .canonicalize()
+                .map_err(|e| Error::filesystem("Failed to canonicalize database path").details(e));
+        }
+    }
+
+    // If database doesn't exist, create it in project root (development) or executable directory (production)
+    let default_path = if cfg!(debug_assertions) {
+        exe_dir.join("../../../invoices.db") // Development
+    } else {
+        exe_dir.join("invoices.db") // Production
+    };
+
+    Ok(default_path)
+}
+
+pub fn connect_database() -> Result<Connection, Error> {
+    let db_path = get_database_path()?;
+    let conn = Connection::open(db_path)
+        .map_err(|e| Error::database("Failed to open database").details(e))?;
+
+    // Initialize the database schema
+    init_database(&conn)?;
+
+    Ok(conn)
+}
+
+pub fn init_database(conn: &Connection) -> Result<(), Error> {
+    // Only create the settings table - let OCaml backend handle its own tables
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create settings table").details(e))?;
+
+    // VAT per rate for each generated invoice, keyed by invoice number since the
+    // invoices table itself belongs to the OCaml backend
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_vat_summary (
+            invoice_number TEXT NOT NULL,
+            rate INTEGER NOT NULL,
+            treatment TEXT NOT NULL,
+            net TEXT NOT NULL,
+            vat TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create VAT summary table").details(e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_terms (
+            invoice_number TEXT PRIMARY KEY,
+            payment_days INTEGER NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create invoice terms table").details(e))?;
+
+    // Who an invoice was from and to, and in which currency; the backend's tables only keep
+    // placeholder references for these. Multi-line values are joined with newlines.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_parties (
+            invoice_number TEXT PRIMARY KEY,
+            currency TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            customer_name TEXT NOT NULL,
+            customer_address TEXT NOT NULL,
+            bank_details TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create invoice parties table").details(e))?;
+
+    // Re-rendered versions of stored invoices, numbered from 1 per invoice. The invoice's own
+    // PDF is never replaced; revision 1 keeps a copy of it with the data it was rebuilt from.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            invoice_number TEXT NOT NULL,
+            revision INTEGER NOT NULL,
+            reason TEXT NOT NULL DEFAULT '',
+            data TEXT NOT NULL,
+            pdf_content BLOB NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (invoice_number, revision)
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create invoice revisions table").details(e))?;
+
+    // Recurring runs, each stored as JSON, and the runs made from them
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schedules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            definition TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create schedules table").details(e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schedule_runs (
+            schedule_id INTEGER NOT NULL,
+            run_date TEXT NOT NULL,
+            status TEXT NOT NULL,
+            invoice_numbers TEXT NOT NULL DEFAULT '',
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (schedule_id, run_date)
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create schedule runs table").details(e))?;
+
+    // Saved invoices, with their configuration values as JSON
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS templates (
+            name TEXT PRIMARY KEY,
+            template_values TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create templates table").details(e))?;
+
+    // What each generation run printed and how it ended
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS run_logs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            renderer TEXT NOT NULL,
+            dry_run BOOLEAN NOT NULL,
+            succeeded BOOLEAN NOT NULL,
+            detail TEXT NOT NULL,
+            stdout TEXT NOT NULL,
+            stderr TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create run log table").details(e))?;
+
+    // Each real generation run with the configuration it used, and the invoices voided
+    // along with a batch. Invoice numbers are comma separated.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS batches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_log_id INTEGER NOT NULL,
+            config TEXT NOT NULL,
+            options TEXT NOT NULL,
+            recipients TEXT NOT NULL,
+            invoice_date TEXT NOT NULL,
+            invoice_numbers TEXT NOT NULL DEFAULT '',
+            rerun_of INTEGER,
+            voided_at TEXT NOT NULL DEFAULT '',
+            void_reason TEXT NOT NULL DEFAULT ''
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create batches table").details(e))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS voided_invoices (
+            invoice_number TEXT PRIMARY KEY,
+            batch_id INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            voided_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create voided invoices table").details(e))?;
+
+    // What each stored invoice billed, to spot the same invoice generated twice
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS invoice_keys (
+            invoice_number TEXT PRIMARY KEY,
+            dedup_key TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create invoice keys table").details(e))?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS invoice_keys_dedup_key ON invoice_keys (dedup_key)",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create invoice keys index").details(e))?;
+
+    // Invoices being prepared; the content is the draft as JSON. The invoice number is set,
+    // and the draft locked, when it is finalized.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS drafts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            content TEXT NOT NULL,
+            invoice_number TEXT NOT NULL DEFAULT '',
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            finalized_at TEXT NOT NULL DEFAULT ''
+        )",
+        [],
+    )
+    .map_err(|e| Error::database("Failed to create drafts table").details(e))?;
+
+    // Initialize example settings for first-time users
+    let examples = [
+        ("sender", "Your Company Name\nYour Address\nCity, Postal Code\nCountry"),
+        ("bankdetails", "Bank Name: Your Bank\nAccount: 1234-56-78901\nIBAN: NO1234567890123456\nBIC: BANKNO22"),
+        ("description", "Consulting services\nWeb development\nProject management"),
+        ("amount", "5000.00"),
+        ("recipients", "Client Company\nclient@example.com\nClient Address\nCity, Postal Code\n\nAnother Client\nanother@example.com\nAnother Address\nCity, Postal Code"),
+        ("_app_initialized", "true"),
+    ];
+
+    for (key, example_value) in examples {
+        conn.execute(
+            "INSERT OR IGNORE INTO settings (key, value) VALUES (?1, ?2)",
+            [key, example_value],
+        )
+        .map_err(|e| {
+            Error::database(format!("Failed to insert example setting {}", key)).details(e)
+        })?;
+    }
+
+    Ok(())
+}
+
+pub fn reset_database() -> Result<(), Error> {
+    let db_path = get_database_path()?;
+
+    // Remove existing database file if it exists
+    if db_path.exists() {
+        fs::remove_file(&db_path)
+            .map_err(|e| Error::filesystem("Failed to remove database file").details(e))?;
+    }
+
+    // Create new database with fresh schema and example data
+    let conn = connect_database()?;
+    init_database(&conn)?;
+
+    // Also reset settings to defaults
+    let settings_path = get_settings_path()?;
+    if settings_path.exists() {
+        fs::remove_file(&settings_path)
+            .map_err(|e| Error::filesystem("Failed to remove settings file").details(e))?;
+    }
+
+    // Create fresh default settings
+    let _ = get_app_settings()?;
+
+    Ok(())
+}
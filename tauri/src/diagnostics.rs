@@ -0,0 +1,309 @@
+// Environment checks shown when generation fails: can the backend be found and run, and can
+// the database and output directory be written
+use crate::api;
+use crate::database::{connect_database, get_database_path};
+use crate::errors::Error;
+use crate::generation::{
+    backend_binary_path, backend_candidate_paths, get_bundled_ocaml_backend, get_preview_dir,
+    BACKEND_VERSION,
+};
+use crate::renderer::RendererKind;
+use crate::settings::get_app_settings;
+use serde::Serialize;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Below this much free space a run may fail halfway through writing PDFs
+pub const LOW_DISK_SPACE_BYTES: u64 = 100 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    // What the user can do about it; empty when the check passed
+    pub fix: String,
+}
+
+impl Check {
+    fn ok(name: &str, detail: String) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Ok,
+            detail,
+            fix: String::new(),
+        }
+    }
+
+    fn problem(name: &str, status: CheckStatus, detail: String, fix: &str) -> Self {
+        Check {
+            name: name.to_string(),
+            status,
+            detail,
+            fix: fix.to_string(),
+        }
+    }
+}
+
+// One place the backend is looked for
+#[derive(Serialize, Clone, Debug)]
+pub struct BackendCandidate {
+    pub path: String,
+    pub exists: bool,
+    pub has_sources: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Diagnostics {
+    // True when no check failed; warnings do not stop a run
+    pub ok: bool,
+    pub candidates: Vec<BackendCandidate>,
+    pub checks: Vec<Check>,
+}
+
+fn candidate(path: &Path) -> BackendCandidate {
+    BackendCandidate {
+        path: path.to_string_lossy().to_string(),
+        exists: path.exists(),
+        has_sources: path.join("src").exists(),
+    }
+}
+
+fn check_backend_directory(candidates: &[BackendCandidate]) -> Check {
+    match get_bundled_ocaml_backend() {
+        Ok(path) => Check::ok("Backend directory", path.to_string_lossy().to_string()),
+        Err(e) => Check::problem(
+            "Backend directory",
+            CheckStatus::Failed,
+            format!("{} Looked in {} places.", e, candidates.len()),
+            "Reinstall the application, or in development keep the ocaml-backend directory next to tauri/",
+        ),
+    }
+}
+
+// The compiled backend exists and answers the version handshake
+pub fn check_backend_binary(ocaml_backend: &Path) -> Check {
+    let binary = backend_binary_path(ocaml_backend);
+    if !binary.exists() {
+        return Check::problem(
+            "Backend binary",
+            CheckStatus::Failed,
+            format!("{} does not exist", binary.display()),
+            "Run `dune build` in the ocaml-backend directory, or reinstall the application",
+        );
+    }
+
+    let output = match Command::new(&binary)
+        .current_dir(ocaml_backend)
+        .arg("-version")
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            return Check::problem(
+                "Backend binary",
+                CheckStatus::Failed,
+                format!("{} could not be started: {}", binary.display(), e),
+                "Check that the file is executable and built for this system, then run `dune build` again",
+            )
+        }
+    };
+
+    let answer = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && answer == BACKEND_VERSION {
+        Check::ok(
+            "Backend binary",
+            format!("{} ({})", binary.display(), answer),
+        )
+    } else if output.status.success() {
+        Check::problem(
+            "Backend binary",
+            CheckStatus::Warning,
+            format!(
+                "Expected '{}', the backend answered '{}'",
+                BACKEND_VERSION, answer
+            ),
+            "The backend was built from other sources than the app; run `dune build` to rebuild it",
+        )
+    } else {
+        Check::problem(
+            "Backend binary",
+            CheckStatus::Failed,
+            format!(
+                "{} did not answer the version check: {}",
+                binary.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "The backend is older than the app; run `dune build` to rebuild it",
+        )
+    }
+}
+
+// A directory can be created and written to, checked by writing and removing a probe file
+pub fn check_writable(name: &str, dir: &Path) -> Check {
+    let probe = dir.join(".invoice-splitter-write-check");
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => Check::ok(name, format!("{} is writable", dir.display())),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Failed,
+            format!("Cannot write to {}: {}", dir.display(), e),
+            "Choose another directory in Settings, or fix the directory's permissions",
+        ),
+    }
+}
+
+fn check_database() -> Check {
+    let path = match get_database_path() {
+        Ok(path) => path,
+        Err(e) => {
+            return Check::problem(
+                "Database",
+                CheckStatus::Failed,
+                e.to_string(),
+                "Reinstall the application so the database location can be found",
+            )
+        }
+    };
+
+    // Taking the write lock proves the file is writable without changing anything
+    let result = connect_database().and_then(|conn| {
+        conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")
+            .map_err(|e| Error::database("Failed to lock database").details(e))
+    });
+
+    match result {
+        Ok(()) => Check::ok("Database", format!("{} is writable", path.display())),
+        Err(e) => Check::problem(
+            "Database",
+            CheckStatus::Failed,
+            format!("{}: {}", path.display(), e),
+            "Close other programs using the database and check that the file and its directory are writable",
+        ),
+    }
+}
+
+fn format_megabytes(bytes: u64) -> String {
+    format!("{} MB", bytes / (1024 * 1024))
+}
+
+// Free space on the disk holding `dir`, or its nearest existing parent
+pub fn check_disk_space(name: &str, dir: &Path) -> Check {
+    let existing = dir.ancestors().find(|p| p.exists()).unwrap_or(dir);
+
+    match fs2::available_space(existing) {
+        Ok(free) if free < LOW_DISK_SPACE_BYTES => Check::problem(
+            name,
+            CheckStatus::Warning,
+            format!(
+                "Only {} free at {}",
+                format_megabytes(free),
+                existing.display()
+            ),
+            "Free up disk space before generating invoices",
+        ),
+        Ok(free) => Check::ok(
+            name,
+            format!("{} free at {}", format_megabytes(free), existing.display()),
+        ),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Warning,
+            format!("Could not read free space at {}: {}", existing.display(), e),
+            "Check that the disk is mounted",
+        ),
+    }
+}
+
+// Whether the local API started with the app; a warning, as runs do not need it
+pub fn check_local_api(status: &Result<SocketAddr, String>) -> Check {
+    match status {
+        Ok(addr) => Check::ok("Local API", format!("Listening on {}", addr)),
+        Err(e) => Check::problem(
+            "Local API",
+            CheckStatus::Warning,
+            format!("Not started: {}", e),
+            "Choose a free port and set a token in Settings, then restart the app",
+        ),
+    }
+}
+
+// Check everything a run needs and suggest a fix for each problem
+pub fn run_diagnostics() -> Result<Diagnostics, Error> {
+    let candidates: Vec<BackendCandidate> = backend_candidate_paths()?
+        .iter()
+        .map(|p| candidate(p))
+        .collect();
+
+    let mut checks = vec![check_backend_directory(&candidates)];
+    if let Ok(ocaml_backend) = get_bundled_ocaml_backend() {
+        checks.push(check_backend_binary(&ocaml_backend));
+        checks.push(check_writable(
+            "Backend working directory",
+            &ocaml_backend.join("out"),
+        ));
+    }
+
+    // The native renderer does not need the backend, so its problems do not stop a run
+    let native = matches!(get_app_settings(), Ok(s) if s.renderer == RendererKind::Native);
+    if native {
+        for check in checks
+            .iter_mut()
+            .filter(|c| c.status == CheckStatus::Failed)
+        {
+            check.status = CheckStatus::Warning;
+            check.detail = format!("{} (not needed by the native renderer)", check.detail);
+        }
+    }
+
+    checks.push(check_database());
+    if let Ok(db_path) = get_database_path() {
+        let db_dir = db_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        checks.push(check_disk_space("Disk space (database)", &db_dir));
+    }
+
+    match get_app_settings() {
+        Ok(settings) => {
+            let output_dir = PathBuf::from(&settings.output_directory);
+            checks.push(check_writable("Output directory", &output_dir));
+            checks.push(check_writable(
+                "Preview directory",
+                &get_preview_dir(&settings),
+            ));
+            checks.push(check_disk_space("Disk space (output)", &output_dir));
+        }
+        Err(e) => checks.push(Check::problem(
+            "Settings",
+            CheckStatus::Failed,
+            e.to_string(),
+            "Reset the settings in the Settings tab",
+        )),
+    }
+
+    if let Some(status) = api::launch_status() {
+        checks.push(check_local_api(&status));
+    }
+
+    Ok(Diagnostics {
+        ok: checks.iter().all(|c| c.status != CheckStatus::Failed),
+        candidates,
+        checks,
+    })
+}
@@ -0,0 +1,411 @@
+// Draft invoices: stored and editable without using up an invoice number. Finalizing a draft
+// gives it the next number, renders the final PDF and stores it as an invoice; the draft is
+// locked from then on.
+use crate::database::connect_database;
+use crate::duplicates;
+use crate::errors::Error;
+use crate::generation;
+use crate::invoices::{self, InvoiceParties};
+use crate::money::{Currency, Money};
+use crate::pdf::{self, DocumentLine, InvoiceDocument};
+use crate::recipients;
+use crate::renderer::{self, BatchMode, NativeRenderer, RendererKind};
+use crate::revisions::NATIVE_RENDER_WARNING;
+use crate::run_plan;
+use crate::runs;
+use crate::settings::{get_app_settings, load_config};
+use crate::terms::{self, parse_date, RunOptions};
+use crate::vat::{self, VatCode, VatSettings, VatTreatment};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// Printed across draft PDFs and in place of the invoice number
+pub const DRAFT_WATERMARK: &str = "UTKAST";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DraftLine {
+    #[serde(default)]
+    pub date: String,
+    pub description: String,
+    // Net amount as a decimal in the draft's currency
+    pub amount: String,
+    // Only used when the draft charges VAT
+    #[serde(default)]
+    pub vat_rate: u32,
+    #[serde(default)]
+    pub vat_treatment: VatTreatment,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Draft {
+    // Assigned when the draft is first saved
+    #[serde(default)]
+    pub id: i64,
+    pub customer_name: String,
+    #[serde(default)]
+    pub customer_address: Vec<String>,
+    // YYYY-MM-DD; both dates are needed before the draft can be previewed or finalized
+    #[serde(default)]
+    pub invoice_date: String,
+    #[serde(default)]
+    pub due_date: String,
+    // ISO code; empty means NOK
+    #[serde(default)]
+    pub currency: String,
+    #[serde(default)]
+    pub sender: Vec<String>,
+    #[serde(default)]
+    pub bank_details: Vec<String>,
+    // Whether VAT is charged on the lines
+    #[serde(default)]
+    pub vat_enabled: bool,
+    #[serde(default)]
+    pub lines: Vec<DraftLine>,
+    // Set when the draft is finalized and ignored when it is saved
+    #[serde(default)]
+    pub invoice_number: String,
+    #[serde(default)]
+    pub finalized_at: String,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+    // Said when the draft is finalized, e.g. that it was not rendered by the configured
+    // renderer; not stored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+fn line_code(draft: &Draft, line: &DraftLine) -> Result<VatCode, Error> {
+    if !draft.vat_enabled {
+        return Ok(VatCode::NONE);
+    }
+    VatSettings {
+        enabled: true,
+        rate: line.vat_rate,
+        treatment: line.vat_treatment,
+    }
+    .code_for(None, None)
+}
+
+// Catch what would make the draft impossible to finalize, except missing dates
+fn validate_draft(draft: &Draft) -> Result<(), Error> {
+    if draft.customer_name.trim().is_empty() {
+        return Err(Error::invalid("The draft needs a customer"));
+    }
+    let currency = Currency::from_code(&draft.currency)?;
+    for line in &draft.lines {
+        Money::parse(&line.amount, currency)?;
+        line_code(draft, line)?;
+    }
+    for date in [&draft.invoice_date, &draft.due_date] {
+        if !date.trim().is_empty() {
+            parse_date(date)?;
+        }
+    }
+    Ok(())
+}
+
+// The invoice a draft describes, numbered `invoice_number`
+pub fn draft_document(draft: &Draft, invoice_number: &str) -> Result<InvoiceDocument, Error> {
+    validate_draft(draft)?;
+    if draft.lines.is_empty() {
+        return Err(Error::invalid("The draft has no invoice lines"));
+    }
+    if parse_date(&draft.due_date)? < parse_date(&draft.invoice_date)? {
+        return Err(Error::invalid("The due date is before the invoice date"));
+    }
+
+    let currency = Currency::from_code(&draft.currency)?;
+    let mut lines = Vec::new();
+    let mut by_code = Vec::new();
+    for line in &draft.lines {
+        let amount = Money::parse(&line.amount, currency)?;
+        let code = line_code(draft, line)?;
+        by_code.push((code, amount.minor, code.vat_on(amount.minor)));
+        lines.push(DocumentLine {
+            date: line.date.clone(),
+            description: line.description.clone(),
+            amount,
+        });
+    }
+
+    Ok(InvoiceDocument {
+        invoice_number: invoice_number.to_string(),
+        invoice_date: draft.invoice_date.trim().to_string(),
+        due_date: draft.due_date.trim().to_string(),
+        sender: draft.sender.clone(),
+        customer_name: draft.customer_name.trim().to_string(),
+        customer_address: draft.customer_address.clone(),
+        service: draft
+            .lines
+            .first()
+            .map(|line| line.description.clone())
+            .unwrap_or_default(),
+        lines,
+        vat_summary: if draft.vat_enabled {
+            vat::summarize(&by_code, currency)
+        } else {
+            Vec::new()
+        },
+        bank_details: draft.bank_details.clone(),
+    })
+}
+
+// The draft as stored, without the columns kept beside it
+fn encode_draft(draft: &Draft) -> Result<String, Error> {
+    serde_json::to_string(&Draft {
+        id: 0,
+        invoice_number: String::new(),
+        finalized_at: String::new(),
+        created_at: String::new(),
+        updated_at: String::new(),
+        warnings: Vec::new(),
+        ..draft.clone()
+    })
+    .map_err(|e| Error::invalid("Failed to serialize draft").details(e))
+}
+
+// The draft with the given id, or all drafts, newest first
+fn query_drafts(id: Option<i64>) -> Result<Vec<Draft>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, content, invoice_number, finalized_at, created_at, updated_at
+             FROM drafts WHERE ?1 IS NULL OR id = ?1 ORDER BY id DESC",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                Draft {
+                    invoice_number: row.get(2)?,
+                    finalized_at: row.get(3)?,
+                    created_at: row.get(4)?,
+                    updated_at: row.get(5)?,
+                    ..Draft::default()
+                },
+            ))
+        })
+        .map_err(|e| Error::database("Failed to get drafts").details(e))?;
+
+    rows.map(|row| {
+        let (id, content, columns) =
+            row.map_err(|e| Error::database("Failed to read draft").details(e))?;
+        let draft: Draft = serde_json::from_str(&content)
+            .map_err(|e| Error::database(format!("Invalid draft {}", id)).details(e))?;
+        Ok(Draft {
+            id,
+            customer_name: draft.customer_name,
+            customer_address: draft.customer_address,
+            invoice_date: draft.invoice_date,
+            due_date: draft.due_date,
+            currency: draft.currency,
+            sender: draft.sender,
+            bank_details: draft.bank_details,
+            vat_enabled: draft.vat_enabled,
+            lines: draft.lines,
+            ..columns
+        })
+    })
+    .collect()
+}
+
+pub fn list_drafts() -> Result<Vec<Draft>, Error> {
+    query_drafts(None)
+}
+
+pub fn find_draft(id: i64) -> Result<Draft, Error> {
+    query_drafts(Some(id))?
+        .pop()
+        .ok_or_else(|| Error::not_found(format!("Draft {} not found", id)))
+}
+
+fn finalized_error(draft: &Draft) -> Error {
+    Error::invalid(format!(
+        "Draft {} was finalized as invoice {} and can no longer be changed",
+        draft.id, draft.invoice_number
+    ))
+}
+
+// Create a draft, or replace the one with the same id while it is not finalized
+pub fn save_draft(draft: Draft) -> Result<Draft, Error> {
+    validate_draft(&draft)?;
+    let content = encode_draft(&draft)?;
+
+    let conn = connect_database()?;
+    let id = if draft.id == 0 {
+        conn.execute("INSERT INTO drafts (content) VALUES (?)", [&content])
+            .map_err(|e| Error::database("Failed to save draft").details(e))?;
+        conn.last_insert_rowid()
+    } else {
+        let updated = conn
+            .execute(
+                "UPDATE drafts SET content = ?1, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?2 AND invoice_number = ''",
+                rusqlite::params![content, draft.id],
+            )
+            .map_err(|e| Error::database("Failed to save draft").details(e))?;
+        if updated == 0 {
+            return Err(finalized_error(&find_draft(draft.id)?));
+        }
+        draft.id
+    };
+
+    find_draft(id)
+}
+
+// Finalized drafts are kept with the invoice they became
+pub fn delete_draft(id: i64) -> Result<(), Error> {
+    let conn = connect_database()?;
+    let deleted = conn
+        .execute(
+            "DELETE FROM drafts WHERE id = ? AND invoice_number = ''",
+            [id],
+        )
+        .map_err(|e| Error::database("Failed to delete draft").details(e))?;
+    if deleted == 0 {
+        return Err(finalized_error(&find_draft(id)?));
+    }
+    Ok(())
+}
+
+// One draft per recipient of the current configuration, billing what a run would
+pub fn create_drafts(options: Option<RunOptions>) -> Result<Vec<Draft>, Error> {
+    let config = load_config()?;
+    let vat_settings = config.vat()?;
+    let plan = run_plan::build_run_plan(&config, &options.unwrap_or_default())?;
+    let recipients = recipients::parse_recipients(plan.setting("recipients"));
+    let single_code = vat_settings.code_for(None, None)?;
+
+    let mut drafts = Vec::new();
+    for (index, recipient) in recipients.iter().enumerate() {
+        let lines = match plan.lines.get(index) {
+            Some(lines) if !lines.is_empty() => lines
+                .iter()
+                .map(|line| DraftLine {
+                    date: line.date.clone(),
+                    description: line.description.clone(),
+                    amount: line.amount.plain(),
+                    vat_rate: line.vat_code.rate,
+                    vat_treatment: line.vat_code.treatment,
+                })
+                .collect(),
+            _ => vec![DraftLine {
+                date: String::new(),
+                description: plan.descriptions[index].clone(),
+                amount: plan.amounts[index].plain(),
+                vat_rate: single_code.rate,
+                vat_treatment: single_code.treatment,
+            }],
+        };
+
+        drafts.push(save_draft(Draft {
+            customer_name: recipient.name.clone(),
+            customer_address: recipient.address.clone(),
+            invoice_date: plan.dates[index].invoice_date.clone(),
+            due_date: plan.dates[index].due_date.clone(),
+            currency: plan.currency.code.to_string(),
+            sender: renderer::non_empty_lines(plan.setting("sender")),
+            bank_details: renderer::non_empty_lines(plan.setting("bankdetails")),
+            vat_enabled: vat_settings.enabled,
+            lines,
+            ..Draft::default()
+        })?);
+    }
+
+    Ok(drafts)
+}
+
+// The PDF of a draft, saved or not, watermarked and without an invoice number; base64 encoded
+pub fn preview_draft(draft: Draft) -> Result<String, Error> {
+    let document = draft_document(&draft, DRAFT_WATERMARK)?;
+    let pdf = pdf::render_watermarked(&document, DRAFT_WATERMARK)?;
+    Ok(general_purpose::STANDARD.encode(pdf))
+}
+
+// Give a draft the next invoice number and store it as an invoice, with its VAT summary, terms,
+// parties and duplicate check key, in one transaction, then export its PDF like a generated
+// invoice. The draft cannot be changed afterwards. Always rendered natively, like a revision.
+pub fn finalize_draft(id: i64) -> Result<Draft, Error> {
+    // Held so no generation run takes a number at the same time
+    let _lock = runs::lock_database()?;
+    let draft = find_draft(id)?;
+    if !draft.invoice_number.is_empty() {
+        return Err(finalized_error(&draft));
+    }
+    let mut document = draft_document(&draft, "")?;
+    let net = document.lines.iter().map(|line| line.amount.minor).sum();
+    let key = duplicates::key(
+        &document.customer_name,
+        &document.invoice_date,
+        &draft
+            .lines
+            .iter()
+            .map(|line| line.description.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        &Money::new(net, Currency::from_code(&draft.currency)?),
+    );
+    // Dated like a generation run would be, so invoice numbers stay in date order
+    terms::check_invoice_date(
+        parse_date(&draft.invoice_date)?,
+        Local::now().date_naive(),
+        terms::current_open_period_start()?,
+    )?;
+    // Stored beside the invoice, as for generated invoices
+    let payment_days = (parse_date(&draft.due_date)? - parse_date(&draft.invoice_date)?)
+        .num_days()
+        .max(0) as u32;
+    let parties = InvoiceParties {
+        currency: Currency::from_code(&draft.currency)?.code.to_string(),
+        sender: document.sender.clone(),
+        customer_name: document.customer_name.clone(),
+        customer_address: document.customer_address.clone(),
+        bank_details: document.bank_details.clone(),
+    };
+
+    let mut conn = connect_database()?;
+    invoices::create_backend_tables(&conn)?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    let pdf = renderer::store_document(&tx, &mut document)?;
+    let updated = tx
+        .execute(
+            "UPDATE drafts SET invoice_number = ?1, finalized_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND invoice_number = ''",
+            rusqlite::params![document.invoice_number, id],
+        )
+        .map_err(|e| Error::database("Failed to finalize draft").details(e))?;
+    if updated == 0 {
+        return Err(finalized_error(&find_draft(id)?));
+    }
+    invoices::insert_vat_summary(&tx, &document.invoice_number, &document.vat_summary)?;
+    invoices::insert_payment_days(&tx, &document.invoice_number, payment_days)?;
+    invoices::insert_invoice_parties(&tx, &document.invoice_number, &parties)?;
+    if let Some(key) = &key {
+        duplicates::insert_invoice_key(&tx, &document.invoice_number, key)?;
+    }
+    tx.commit()
+        .map_err(|e| Error::database("Failed to finalize draft").details(e))?;
+
+    let renderer = NativeRenderer::new(BatchMode::default())?;
+    fs::create_dir_all(&renderer.out_dir)
+        .map_err(|e| Error::filesystem("Failed to create output directory").details(e))?;
+    let entry = renderer.file_for(&document);
+    fs::write(&entry.path, &pdf)
+        .map_err(|e| Error::filesystem("Failed to write PDF").details(e))?;
+    generation::copy_generated_pdfs(&[entry], false)?;
+
+    let mut draft = find_draft(id)?;
+    if get_app_settings()?.renderer != RendererKind::Native {
+        draft.warnings.push(NATIVE_RENDER_WARNING.to_string());
+    }
+    Ok(draft)
+}
@@ -0,0 +1,148 @@
+// Guarding against billing the same thing twice. Every stored invoice is keyed by its
+// recipient, period, service and amount, and a run is checked against recent keys before
+// any of its invoices are created.
+use crate::database::connect_database;
+use crate::errors::{Error, ErrorCode};
+use crate::money::Money;
+use crate::run_plan::RunPlan;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+// What a run that would repeat recent invoices does
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    // No check
+    Off,
+    // Generate, with a warning in the output
+    Warn,
+    // Refuse unless the run is forced
+    #[default]
+    Block,
+}
+
+// Message of the error a blocked run fails with; the duplicates are its details
+pub const DUPLICATES_BLOCKED: &str =
+    "Refusing to bill the same recipient, period, service and amount twice";
+
+// A recipient of a run already billed the same on a recent invoice
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Duplicate {
+    pub recipient: String,
+    pub invoice_number: String,
+    pub generated_at: String,
+}
+
+impl Duplicate {
+    pub fn describe(&self) -> String {
+        format!(
+            "'{}' was already billed for this period, service and amount on invoice {} ({})",
+            self.recipient, self.invoice_number, self.generated_at
+        )
+    }
+}
+
+// Case and spacing differences do not make a service different
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+// The key of the invoice for the recipient at `index`: their name, the month billed for,
+// what is billed and the net amount
+pub fn invoice_key(plan: &RunPlan, index: usize) -> Option<String> {
+    let service = match plan.lines.get(index) {
+        Some(lines) if !lines.is_empty() => lines
+            .iter()
+            .map(|line| line.description.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => plan.descriptions.get(index)?.clone(),
+    };
+    key(
+        plan.recipients.get(index)?,
+        &plan.dates.get(index)?.period_date,
+        &service,
+        plan.amounts.get(index)?,
+    )
+}
+
+// The key of an invoice to `recipient` dated `period_date` (YYYY-MM-DD), billing `service`
+// (its line descriptions, one per line) for the net `amount`
+pub fn key(recipient: &str, period_date: &str, service: &str, amount: &Money) -> Option<String> {
+    Some(format!(
+        "{}\t{}\t{}\t{} {}",
+        normalize(recipient),
+        period_date.get(..7)?,
+        normalize(service),
+        amount.plain(),
+        amount.currency.code
+    ))
+}
+
+pub fn record_invoice_key(invoice_number: &str, key: &str) -> Result<(), Error> {
+    insert_invoice_key(&connect_database()?, invoice_number, key)
+}
+
+// Record the key on `conn`, e.g. in the transaction storing the invoice
+pub fn insert_invoice_key(conn: &Connection, invoice_number: &str, key: &str) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO invoice_keys (invoice_number, dedup_key) VALUES (?1, ?2)",
+        [invoice_number, key],
+    )
+    .map_err(|e| Error::database("Failed to store duplicate check key").details(e))?;
+
+    Ok(())
+}
+
+// Recipients of the plan billed the same on an invoice stored in the last `window_days`
+// days, or at any time if `window_days` is 0. Voided invoices do not count.
+pub fn find_duplicates(plan: &RunPlan, window_days: u32) -> Result<Vec<Duplicate>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT invoice_number, created_at FROM invoice_keys
+             WHERE dedup_key = ?1 AND (?2 = 0 OR created_at >= datetime('now', '-' || ?2 || ' days'))
+             AND invoice_number NOT IN (SELECT invoice_number FROM voided_invoices)
+             ORDER BY created_at DESC LIMIT 1",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let mut duplicates = Vec::new();
+    for (index, recipient) in plan.recipients.iter().enumerate() {
+        let Some(key) = invoice_key(plan, index) else {
+            continue;
+        };
+        let found = stmt.query_row(rusqlite::params![key, window_days], |row| {
+            Ok(Duplicate {
+                recipient: recipient.clone(),
+                invoice_number: row.get(0)?,
+                generated_at: row.get(1)?,
+            })
+        });
+        match found {
+            Ok(duplicate) => duplicates.push(duplicate),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => {
+                return Err(Error::database("Failed to check for duplicate invoices").details(e))
+            }
+        }
+    }
+
+    Ok(duplicates)
+}
+
+// The error a run blocked by `duplicates` fails with
+pub fn blocked_error(duplicates: &[Duplicate]) -> Error {
+    let error = Error::new(ErrorCode::DuplicatesBlocked, DUPLICATES_BLOCKED);
+    if duplicates.is_empty() {
+        return error;
+    }
+    let lines: Vec<String> = duplicates
+        .iter()
+        .map(|duplicate| format!("- {}", duplicate.describe()))
+        .collect();
+    error.details(lines.join("\n"))
+}
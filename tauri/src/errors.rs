@@ -0,0 +1,188 @@
+// Errors as the library raises them, with a code saying what kind of failure each is, and as the
+// frontend receives them from a command: the code to branch on, the part of the app that failed,
+// and what the user can do about it.
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Database,
+    Backend,
+    Filesystem,
+    Validation,
+}
+
+// Serialized as snake_case ("not_found"); the frontend matches on these, so they do not change
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidInput,
+    NotFound,
+    StalePreview,
+    DuplicatesBlocked,
+    RunInProgress,
+    DatabaseError,
+    BackendFailed,
+    BackendTimeout,
+    FilesystemError,
+}
+
+impl ErrorCode {
+    pub fn category(self) -> ErrorCategory {
+        match self {
+            ErrorCode::InvalidInput
+            | ErrorCode::NotFound
+            | ErrorCode::StalePreview
+            | ErrorCode::DuplicatesBlocked => ErrorCategory::Validation,
+            ErrorCode::RunInProgress | ErrorCode::BackendFailed | ErrorCode::BackendTimeout => {
+                ErrorCategory::Backend
+            }
+            ErrorCode::DatabaseError => ErrorCategory::Database,
+            ErrorCode::FilesystemError => ErrorCategory::Filesystem,
+        }
+    }
+
+    pub fn action(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidInput => "Check the values entered and try again.",
+            ErrorCode::NotFound => "Refresh the list; it may have been deleted in the meantime.",
+            ErrorCode::StalePreview => "Preview again and confirm the new preview.",
+            ErrorCode::DuplicatesBlocked => "Generate with force to bill them again.",
+            ErrorCode::RunInProgress => "Wait for the running generation to finish and try again.",
+            ErrorCode::DatabaseError => {
+                "Run the diagnostics, and restore a backup if the database is damaged."
+            }
+            ErrorCode::BackendFailed => {
+                "Check the run log for the backend's output, or switch to the built-in renderer in Settings."
+            }
+            ErrorCode::BackendTimeout => {
+                "Raise the time limit in Settings if large runs need longer."
+            }
+            ErrorCode::FilesystemError => {
+                "Check that the folder exists and can be written to, and that the disk is not full."
+            }
+        }
+    }
+}
+
+// An error from the library, with its code chosen where it is raised. It reads as its message
+// followed by its details, which the CLI and the local API print; commands return it as a
+// CommandError.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    // The underlying cause, or the items concerned
+    pub details: Option<String>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    // The same error with `details`, e.g. the database error that caused it
+    pub fn details(self, details: impl fmt::Display) -> Self {
+        Error {
+            details: Some(details.to_string()),
+            ..self
+        }
+    }
+
+    // Something entered or chosen cannot be used, e.g. an amount, a date or a setting
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Error::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Error::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn database(message: impl Into<String>) -> Self {
+        Error::new(ErrorCode::DatabaseError, message)
+    }
+
+    pub fn filesystem(message: impl Into<String>) -> Self {
+        Error::new(ErrorCode::FilesystemError, message)
+    }
+
+    // The OCaml backend, or a renderer standing in for it, failed to produce the invoices
+    pub fn backend(message: impl Into<String>) -> Self {
+        Error::new(ErrorCode::BackendFailed, message)
+    }
+
+    // The same error with `context`, e.g. the recipient it concerns, in front of the message
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        Error {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.details {
+            // A list starts on its own line
+            Some(details) if details.contains('\n') => write!(f, "{}:\n{}", self.message, details),
+            Some(details) => write!(f, "{}: {}", self.message, details),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The CLI and the local API report errors as text
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
+
+// What a command returns to the frontend when it fails
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub category: ErrorCategory,
+    // What went wrong, for the user
+    pub message: String,
+    // The underlying cause, or the items concerned; empty when there is nothing to add
+    pub details: String,
+    pub action: String,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>, details: impl Into<String>) -> Self {
+        CommandError {
+            code,
+            category: code.category(),
+            message: message.into(),
+            details: details.into(),
+            action: code.action().to_string(),
+        }
+    }
+}
+
+impl From<Error> for CommandError {
+    fn from(error: Error) -> Self {
+        CommandError::new(error.code, error.message, error.details.unwrap_or_default())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.details.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.message, self.details)
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
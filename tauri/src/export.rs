@@ -0,0 +1,222 @@
+use crate::errors::Error;
+use crate::placeholders::{self, Context};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// What to do when an exported PDF already exists in the target directory
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    Skip,
+    #[default]
+    Suffix,
+    Overwrite,
+}
+
+// Placeholders accepted in the filename and subfolder templates, besides the date placeholders
+pub const NAMING_PLACEHOLDERS: [&str; 4] = ["number", "customer", "recipient_name", "service"];
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "invoice-{number}";
+
+// One PDF produced by a backend run, as recorded in the run manifest
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub invoice_number: String,
+    pub customer: String,
+    pub service: String,
+    pub invoice_date: String,
+}
+
+pub struct NamingTemplates<'a> {
+    pub filename: &'a str,
+    pub subfolder: &'a str,
+}
+
+#[derive(Default)]
+pub struct ExportReport {
+    pub copied: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+// Create a fresh path for a file exchanged with one backend run, e.g. "manifest" or "plan"
+pub fn new_run_file_path(ocaml_backend: &Path, kind: &str) -> Result<PathBuf, Error> {
+    let out_dir = ocaml_backend.join("out");
+    fs::create_dir_all(&out_dir)
+        .map_err(|e| Error::filesystem("Failed to create OCaml out directory").details(e))?;
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    Ok(out_dir.join(format!("{}-{}-{}.txt", kind, std::process::id(), stamp)))
+}
+
+// Read the PDFs listed in a run manifest, resolved against the backend directory
+pub fn read_manifest(ocaml_backend: &Path, manifest: &Path) -> Result<Vec<ManifestEntry>, Error> {
+    if !manifest.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(manifest)
+        .map_err(|e| Error::filesystem("Failed to read run manifest").details(e))?;
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split('\t').map(str::trim);
+            let mut next = || fields.next().unwrap_or_default().to_string();
+
+            ManifestEntry {
+                path: ocaml_backend.join(next()),
+                invoice_number: next(),
+                customer: next(),
+                service: next(),
+                invoice_date: next(),
+            }
+        })
+        .collect())
+}
+
+pub fn remove_run_file(path: &Path) {
+    let _ = fs::remove_file(path);
+}
+
+// Check that a template only uses known placeholders and has no unbalanced braces
+pub fn validate_template(template: &str) -> Result<(), Error> {
+    placeholders::validate(template, &NAMING_PLACEHOLDERS)
+}
+
+// Templates are checked when they are saved; one that still fails is used as it is
+fn expand_template(template: &str, entry: &ManifestEntry) -> String {
+    let date = NaiveDate::parse_from_str(&entry.invoice_date, "%Y-%m-%d")
+        .unwrap_or_else(|_| Local::now().date_naive());
+    let context = Context::new(date)
+        .with("number", &entry.invoice_number)
+        .with("customer", &entry.customer)
+        .with("recipient_name", &entry.customer)
+        .with("service", &entry.service);
+
+    placeholders::expand_with(template, &context, sanitize_component)
+        .unwrap_or_else(|_| template.to_string())
+}
+
+// Make a single path component safe on Windows, macOS and Linux
+pub fn sanitize_component(value: &str) -> String {
+    const RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let replaced: String = value
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Windows silently drops trailing dots and spaces
+    let trimmed = replaced.trim().trim_end_matches('.').trim_end();
+
+    let stem = trimmed.split('.').next().unwrap_or_default();
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        format!("_{}", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+// Expand the naming templates for one file: (relative subfolder, filename)
+fn export_location(entry: &ManifestEntry, naming: &NamingTemplates) -> (PathBuf, String) {
+    let subfolder: PathBuf = expand_template(naming.subfolder, entry)
+        .split(['/', '\\'])
+        .map(sanitize_component)
+        .filter(|part| !part.is_empty() && part != "." && part != "..")
+        .collect();
+
+    let template = if naming.filename.trim().is_empty() {
+        DEFAULT_FILENAME_TEMPLATE
+    } else {
+        naming.filename
+    };
+    let mut stem = sanitize_component(&expand_template(template, entry));
+    if stem.is_empty() {
+        stem = sanitize_component(&format!("invoice-{}", entry.invoice_number));
+    }
+
+    (subfolder, format!("{}.pdf", stem))
+}
+
+// Pick the path a file should be written to, or None if it should be skipped
+fn resolve_target(target_dir: &Path, filename: &str, policy: CollisionPolicy) -> Option<PathBuf> {
+    let target = target_dir.join(filename);
+    if !target.exists() {
+        return Some(target);
+    }
+
+    match policy {
+        CollisionPolicy::Skip => None,
+        CollisionPolicy::Overwrite => Some(target),
+        CollisionPolicy::Suffix => {
+            let path = Path::new(filename);
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or(filename);
+            let extension = path.extension().and_then(|s| s.to_str());
+
+            (1..)
+                .map(|n| match extension {
+                    Some(ext) => target_dir.join(format!("{} ({}).{}", stem, n, ext)),
+                    None => target_dir.join(format!("{} ({})", stem, n)),
+                })
+                .find(|candidate| !candidate.exists())
+        }
+    }
+}
+
+// Copy the files produced by a run into the target directory, named by the templates
+pub fn export_files(
+    entries: &[ManifestEntry],
+    target_dir: &Path,
+    naming: &NamingTemplates,
+    policy: CollisionPolicy,
+) -> Result<ExportReport, Error> {
+    let mut report = ExportReport::default();
+
+    for entry in entries {
+        let (subfolder, filename) = export_location(entry, naming);
+        let dir = target_dir.join(&subfolder);
+
+        fs::create_dir_all(&dir)
+            .map_err(|e| Error::filesystem("Failed to create output directory").details(e))?;
+
+        let display_name = |path: &Path| {
+            path.strip_prefix(target_dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .to_string()
+        };
+
+        match resolve_target(&dir, &filename, policy) {
+            Some(target_path) => {
+                fs::copy(&entry.path, &target_path).map_err(|e| {
+                    Error::filesystem(format!("Failed to copy {} to output directory", filename))
+                        .details(e)
+                })?;
+
+                report.copied.push(display_name(&target_path));
+            }
+            None => report.skipped.push(display_name(&dir.join(&filename))),
+        }
+    }
+
+    Ok(report)
+}
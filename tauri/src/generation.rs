@@ -0,0 +1,488 @@
+use crate::batches::{self, NewBatch};
+use crate::database::{connect_database, get_database_path};
+use crate::duplicates::{self, DuplicatePolicy};
+use crate::errors::Error;
+use crate::export::{self, CollisionPolicy, ExportReport, ManifestEntry, NamingTemplates};
+use crate::invoices::{self, InvoiceParties};
+use crate::preview;
+use crate::recipients;
+use crate::renderer::{self, BatchMode};
+use crate::run_plan::{self, RunPlan};
+use crate::runs::{self, RunLock, RunLog};
+use crate::settings::{get_app_settings, load_config, AppSettings, InvoiceConfig};
+use crate::terms::{self, RunOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+// Printed by `main.exe -version`; a backend answering anything else was built from other sources
+pub const BACKEND_VERSION: &str = "invoice-splitter-backend 1";
+
+// Where the OCaml backend may be installed, in the order they are tried
+pub fn backend_candidate_paths() -> Result<Vec<PathBuf>, Error> {
+    let current_exe = std::env::current_exe()
+        .map_err(|e| Error::backend("Failed to get current executable").details(e))?;
+
+    let exe_dir = current_exe
+        .parent()
+        .ok_or_else(|| Error::backend("Failed to get executable directory"))?;
+
+    // Try different possible locations
+    Ok(vec![
+        // Development mode (from project root/tauri)
+        exe_dir.join("../../../ocaml-backend"), // From tauri/target/debug
+        exe_dir.join("../../ocaml-backend"),    // From tauri/target
+        exe_dir.join("../ocaml-backend"),       // From tauri/
+        // Production mode (bundled)
+        exe_dir.join("ocaml-backend"),
+        // macOS app bundle - the actual path based on bundle structure
+        exe_dir.join("../Resources/_up_/ocaml-backend"),
+        // Alternative macOS app bundle paths
+        exe_dir.join("../Resources/ocaml-backend"),
+        // Linux/Windows production paths
+        exe_dir.join("../ocaml-backend"),
+    ])
+}
+
+// Get the bundled OCaml backend path
+pub fn get_bundled_ocaml_backend() -> Result<PathBuf, Error> {
+    for path in backend_candidate_paths()? {
+        if path.exists() && path.join("src").exists() {
+            return path
+                .canonicalize()
+                .map_err(|e| Error::backend("Failed to canonicalize path").details(e));
+        }
+    }
+
+    Err(Error::backend(
+        "Could not find OCaml backend directory. Make sure the application is properly installed.",
+    ))
+}
+
+pub fn backend_binary_path(ocaml_backend: &Path) -> PathBuf {
+    ocaml_backend.join("_build/default/src/main.exe")
+}
+
+// Setup OCaml environment - ensure shared database access
+fn setup_ocaml_environment() -> Result<PathBuf, Error> {
+    let ocaml_backend = get_bundled_ocaml_backend()?;
+    let shared_db_path = get_database_path()?;
+    let ocaml_db_path = ocaml_backend.join("invoices.db");
+
+    // Always ensure OCaml backend uses the same database as Rust
+    // Create a symlink if possible, otherwise copy
+    if ocaml_db_path.exists() {
+        fs::remove_file(&ocaml_db_path)
+            .map_err(|e| Error::backend("Failed to remove old OCaml database").details(e))?;
+    }
+
+    // Try to create a symlink first (more efficient), fallback to copy
+    #[cfg(unix)]
+    let symlink_result = std::os::unix::fs::symlink(&shared_db_path, &ocaml_db_path);
+    #[cfg(windows)]
+    let symlink_result: Result<(), std::io::Error> = Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Symlinks not supported on Windows",
+    ));
+
+    match symlink_result {
+        Ok(_) => {}
+        Err(_) => {
+            // Symlink failed, copy the database instead
+            if shared_db_path.exists() {
+                fs::copy(&shared_db_path, &ocaml_db_path).map_err(|e| {
+                    Error::backend("Failed to copy database for OCaml backend").details(e)
+                })?;
+            } else {
+                // Create empty database file if shared database doesn't exist yet
+                fs::File::create(&ocaml_db_path)
+                    .map_err(|e| Error::backend("Failed to create OCaml database").details(e))?;
+            }
+        }
+    }
+
+    Ok(ocaml_backend)
+}
+
+// Sync database from OCaml backend to main app database (no longer needed with shared DB)
+fn sync_ocaml_database(_ocaml_backend: &Path) -> Result<(), Error> {
+    // No sync needed since both use the same database file
+    Ok(())
+}
+
+pub fn get_preview_dir(settings: &AppSettings) -> PathBuf {
+    if settings.preview_directory.trim().is_empty() {
+        Path::new(&settings.output_directory).join("previews")
+    } else {
+        PathBuf::from(&settings.preview_directory)
+    }
+}
+
+// Copy the PDFs produced by a run to the user output directory, or the preview directory for dry runs
+pub fn copy_generated_pdfs(files: &[ManifestEntry], dry_run: bool) -> Result<ExportReport, Error> {
+    let settings = get_app_settings()?;
+
+    if files.is_empty() {
+        return Ok(ExportReport::default());
+    }
+
+    // Previews are throwaway, so they always replace the previous preview of the same name
+    let (target_dir, policy) = if dry_run {
+        (get_preview_dir(&settings), CollisionPolicy::Overwrite)
+    } else {
+        (
+            PathBuf::from(&settings.output_directory),
+            settings.collision_policy,
+        )
+    };
+
+    let naming = NamingTemplates {
+        filename: &settings.filename_template,
+        subfolder: &settings.subfolder_template,
+    };
+
+    export::export_files(files, &target_dir, &naming, policy)
+}
+
+// Store the VAT summary, payment terms, parties and duplicate check key of each generated invoice,
+// in one transaction. The backend generates invoices in recipient order, so each PDF belongs to
+// the next recipient with the same name.
+fn store_run_details(plan: &RunPlan, files: &[ManifestEntry]) -> Result<(), Error> {
+    let recipients = recipients::parse_recipients(plan.setting("recipients"));
+    let mut conn = connect_database()?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    let mut next = 0;
+    for entry in files {
+        let Some(offset) = plan.recipients[next..]
+            .iter()
+            .position(|name| *name == entry.customer)
+        else {
+            continue;
+        };
+        let index = next + offset;
+        if let Some(summary) = plan.vat.get(index) {
+            invoices::insert_vat_summary(&tx, &entry.invoice_number, summary)?;
+        }
+        if let Some(dates) = plan.dates.get(index) {
+            invoices::insert_payment_days(&tx, &entry.invoice_number, dates.payment_days)?;
+        }
+        if let Some(recipient) = recipients.get(index) {
+            invoices::insert_invoice_parties(
+                &tx,
+                &entry.invoice_number,
+                &InvoiceParties {
+                    currency: plan.currency.code.to_string(),
+                    sender: renderer::non_empty_lines(plan.setting("sender")),
+                    customer_name: recipient.name.clone(),
+                    customer_address: recipient.address.clone(),
+                    bank_details: renderer::non_empty_lines(plan.setting("bankdetails")),
+                },
+            )?;
+        }
+        if let Some(key) = duplicates::invoice_key(plan, index) {
+            duplicates::insert_invoice_key(&tx, &entry.invoice_number, &key)?;
+        }
+        next = index + 1;
+    }
+
+    tx.commit()
+        .map_err(|e| Error::database("Failed to store invoice details").details(e))
+}
+
+// What a run produced
+pub struct RunResult {
+    // Backend output followed by the exported files
+    pub output: String,
+    // Numbers of the stored invoices; empty for dry runs
+    pub invoice_numbers: Vec<String>,
+    // The batch the run was recorded as; None for dry runs
+    pub batch_id: Option<i64>,
+}
+
+// Run invoice generation with proper environment setup
+pub fn generate_invoices(dry_run: bool, options: Option<RunOptions>) -> Result<String, Error> {
+    run_generation(&load_config()?, dry_run, &options.unwrap_or_default(), None).map(|r| r.output)
+}
+
+// Generate the invoices of a configuration, which need not be the stored one. With a
+// `config_hash` the run is refused unless it still matches the preview it came from.
+pub fn run_generation(
+    config: &InvoiceConfig,
+    dry_run: bool,
+    options: &RunOptions,
+    config_hash: Option<&str>,
+) -> Result<RunResult, Error> {
+    // Held until the run's invoices are stored and copied
+    let lock = runs::lock_database()?;
+    generate_locked(&lock, config, dry_run, options, config_hash)
+}
+
+// Generate while the caller holds the run lock, e.g. to record the run in the same hold
+pub fn generate_locked(
+    _lock: &RunLock,
+    config: &InvoiceConfig,
+    dry_run: bool,
+    options: &RunOptions,
+    config_hash: Option<&str>,
+) -> Result<RunResult, Error> {
+    // Checked with the lock held, so no other run takes the previewed invoice numbers first
+    if let Some(config_hash) = config_hash {
+        if preview::run_fingerprint(config, options)? != config_hash {
+            return Err(preview::stale_error());
+        }
+    }
+
+    // Split the amount in Rust so the shares always add up to the total
+    let plan = run_plan::build_run_plan(config, options)?;
+    let mut warnings = if dry_run {
+        Vec::new()
+    } else {
+        check_duplicates(&plan, options.force)?
+    };
+    let renderer = renderer::configured_renderer()?;
+    // With the lock held, every invoice stored after this one belongs to this run
+    let latest_id = invoices::latest_invoice_id()?;
+
+    let mut log = RunLog {
+        started_at: runs::timestamp(),
+        renderer: renderer.name().to_string(),
+        dry_run,
+        ..RunLog::default()
+    };
+    let rendered = renderer.render_run(&plan, dry_run, &mut log);
+    log.finished_at = runs::timestamp();
+    log.succeeded = rendered.is_ok();
+    // Errors before the backend ran have no exit to describe
+    if let (Err(e), true) = (&rendered, log.detail.is_empty()) {
+        log.detail = e.to_string();
+    }
+    // A log or batch that cannot be written must not hide the run's own outcome; it is said
+    // with the run's other warnings
+    let batch_id = match runs::store_run_log(&log) {
+        Ok(run_log_id) if !dry_run => {
+            match record_batch(run_log_id, config, options, &plan, latest_id) {
+                Ok(batch_id) => Some(batch_id),
+                Err(e) => {
+                    warnings.push(format!("Batch not recorded: {}", e));
+                    None
+                }
+            }
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warnings.push(format!("Run log not stored: {}", e));
+            None
+        }
+    };
+
+    // The invoices a failed run stored are completed and exported before its error is returned
+    let (stdout, produced_files) = match rendered {
+        Ok(rendered) => (rendered.output, rendered.files),
+        Err(e) => {
+            if !dry_run {
+                store_run_details(&plan, &log.stored)?;
+            }
+            copy_generated_pdfs(&log.stored, dry_run)?;
+            return Err(e);
+        }
+    };
+
+    let invoice_numbers = if dry_run {
+        Vec::new()
+    } else {
+        store_run_details(&plan, &produced_files)?;
+        if options.invoice_date.is_none() {
+            if let Err(e) = terms::clear_used_invoice_date(config.get(terms::TERMS_KEY)) {
+                warnings.push(format!("Fixed invoice date not cleared: {}", e));
+            }
+        }
+        produced_files
+            .iter()
+            .map(|f| f.invoice_number.clone())
+            .collect()
+    };
+
+    // Copy generated PDFs to user output directory
+    let report = copy_generated_pdfs(&produced_files, dry_run)?;
+
+    let mut result = String::new();
+    for warning in warnings {
+        result.push_str(&format!("Warning: {}\n", warning));
+    }
+    result.push_str(&stdout);
+    if !report.copied.is_empty() {
+        if dry_run {
+            result.push_str("\n\nPreview PDFs copied to preview directory:\n");
+        } else {
+            result.push_str("\n\nGenerated PDFs copied to output directory:\n");
+        }
+        for file in report.copied {
+            result.push_str(&format!("- {}\n", file));
+        }
+    }
+    if !report.skipped.is_empty() {
+        result.push_str("\nSkipped (a file with the same name already exists):\n");
+        for file in report.skipped {
+            result.push_str(&format!("- {}\n", file));
+        }
+    }
+
+    Ok(RunResult {
+        output: result,
+        invoice_numbers,
+        batch_id,
+    })
+}
+
+// Recipients the plan would bill the same as a recent invoice, as warnings; fails instead if
+// the settings block such runs and the run is not forced
+fn check_duplicates(plan: &RunPlan, force: bool) -> Result<Vec<String>, Error> {
+    let settings = get_app_settings()?;
+    if settings.duplicate_policy == DuplicatePolicy::Off {
+        return Ok(Vec::new());
+    }
+
+    let found = duplicates::find_duplicates(plan, settings.duplicate_window_days)?;
+    if !found.is_empty() && !force && settings.duplicate_policy == DuplicatePolicy::Block {
+        return Err(duplicates::blocked_error(&found));
+    }
+    Ok(found.iter().map(|d| d.describe()).collect())
+}
+
+// Record a real run as a batch, with the invoices it stored even if it failed partway
+fn record_batch(
+    run_log_id: i64,
+    config: &InvoiceConfig,
+    options: &RunOptions,
+    plan: &RunPlan,
+    latest_id: i64,
+) -> Result<i64, Error> {
+    let invoice_date = plan
+        .dates
+        .first()
+        .map_or("", |dates| dates.invoice_date.as_str());
+
+    batches::record_batch(&NewBatch {
+        run_log_id,
+        config,
+        options,
+        recipients: &plan.recipients,
+        invoice_date,
+        invoice_numbers: &invoices::invoice_numbers_after(latest_id)?,
+    })
+}
+
+// Render the first invoice of a plan without storing it or numbering it, and without
+// copying it anywhere; the PDF is removed from the renderer's out directory once read
+pub fn render_preview_pdf(plan: &RunPlan) -> Result<Vec<u8>, Error> {
+    let _lock = runs::lock_database()?;
+    let produced_files = renderer::configured_renderer()?
+        .render_run(plan, true, &mut RunLog::default())?
+        .files;
+
+    let mut pdf = None;
+    for entry in &produced_files {
+        if pdf.is_none() {
+            pdf = Some(
+                fs::read(&entry.path)
+                    .map_err(|e| Error::filesystem("Failed to read preview PDF").details(e)),
+            );
+        }
+        export::remove_run_file(&entry.path);
+    }
+
+    pdf.unwrap_or_else(|| Err(Error::backend("The renderer produced no preview")))
+}
+
+// Run the OCaml backend on a plan; returns its output and the PDFs it produced. The caller
+// holds the run lock.
+pub fn run_backend(
+    plan: &RunPlan,
+    dry_run: bool,
+    batch_mode: BatchMode,
+    log: &mut RunLog,
+) -> Result<(String, Vec<ManifestEntry>), Error> {
+    let timeout_secs = get_app_settings()?.backend_timeout_secs;
+
+    // Setup OCaml environment and copy config files
+    let ocaml_backend = setup_ocaml_environment()?;
+
+    // Find the compiled OCaml binary
+    let binary_path = backend_binary_path(&ocaml_backend);
+
+    // Check if the binary exists (should be bundled pre-compiled)
+    if !binary_path.exists() {
+        // Fallback: try to build if in development mode
+        if cfg!(debug_assertions) {
+            let mut build_cmd = Command::new("dune");
+            build_cmd.current_dir(&ocaml_backend);
+            build_cmd.arg("build");
+
+            let build_output = build_cmd
+                .output()
+                .map_err(|e| Error::backend("Failed to execute dune build").details(e))?;
+
+            if !build_output.status.success() {
+                return Err(Error::backend("Dune build failed")
+                    .details(String::from_utf8_lossy(&build_output.stderr)));
+            }
+        } else {
+            return Err(Error::backend(
+                "OCaml binary not found in bundle. The application may not be properly built.",
+            ));
+        }
+    }
+
+    let plan_path = export::new_run_file_path(&ocaml_backend, "plan")?;
+    plan.write(&plan_path)?;
+
+    // Run the invoice generation using the compiled binary directly
+    let manifest = export::new_run_file_path(&ocaml_backend, "manifest")?;
+    let mut cmd = Command::new(&binary_path);
+    cmd.current_dir(&ocaml_backend);
+    cmd.arg("-manifest").arg(&manifest);
+    cmd.arg("-plan").arg(&plan_path);
+
+    if dry_run {
+        cmd.arg("-dry");
+    } else if batch_mode == BatchMode::AllOrNothing {
+        cmd.arg("-all-or-nothing");
+    }
+
+    let timeout = (timeout_secs > 0).then(|| Duration::from_secs(timeout_secs));
+    let output = runs::output_with_timeout(&mut cmd, timeout);
+
+    // Only the files listed in this run's manifest are exported
+    let produced_files = export::read_manifest(&ocaml_backend, &manifest);
+    export::remove_run_file(&manifest);
+    export::remove_run_file(&plan_path);
+    let output = output?;
+    log.stdout = output.stdout.clone();
+    log.stderr = output.stderr.clone();
+
+    match output.status {
+        Some(status) if status.success() => {
+            log.detail = runs::describe_exit(&status);
+            let produced_files = produced_files?;
+
+            // In production, sync the database after invoice generation
+            if !cfg!(debug_assertions) {
+                sync_ocaml_database(&ocaml_backend)?;
+            }
+
+            Ok((output.stdout, produced_files))
+        }
+        status => {
+            log.detail = match status {
+                Some(status) => runs::describe_exit(&status),
+                None => format!("stopped after {} seconds", timeout_secs),
+            };
+            // In best effort the invoices stored before the failure are kept
+            log.stored = produced_files.unwrap_or_default();
+            Err(runs::process_error(&output, timeout_secs))
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::vat::{VatSummaryLine, VatTreatment};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct InvoiceRecord {
+    pub id: i32,
+    pub invoice_number: String,
+    pub service: String,
+    pub invoice_date: String,
+    pub due_date: String,
+    pub vat_enabled: bool,
+    pub vat_rate: i32,
+    pub created_at: String,
+    pub pdf_base64: String,
+    // Only filled in when a single invoice is fetched
+    #[serde(default)]
+    pub vat_summary: Vec<VatSummaryLine>,
+    #[serde(default)]
+    pub payment_days: Option<u32>,
+    // Voided invoices keep their number but are no longer owed
+    #[serde(default)]
+    pub voided: bool,
+}
+
+// Invoice metadata without the PDF, for listings
+#[derive(Serialize, Deserialize)]
+pub struct InvoiceSummary {
+    pub id: i32,
+    pub invoice_number: String,
+    pub service: String,
+    pub invoice_date: String,
+    pub due_date: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub voided: bool,
+}
+
+// Listed without reading the PDFs
+pub fn list_invoice_summaries() -> Result<Vec<InvoiceSummary>, Error> {
+    let conn = connect_database()?;
+    if !has_invoices_table(&conn) {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, invoice_number, service, invoice_date, due_date, created_at,
+                invoice_number IN (SELECT invoice_number FROM voided_invoices)
+             FROM invoices
+             ORDER BY created_at DESC",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+    let summaries = stmt
+        .query_map([], |row| {
+            Ok(InvoiceSummary {
+                id: row.get(0)?,
+                invoice_number: row.get(1)?,
+                service: row.get(2)?,
+                invoice_date: row.get(3)?,
+                due_date: row.get(4)?,
+                created_at: row.get(5)?,
+                voided: row.get(6)?,
+            })
+        })
+        .map_err(|e| Error::database("Failed to execute query").details(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::database("Failed to parse row").details(e))?;
+
+    Ok(summaries)
+}
+
+// The numbers the next invoices would get, without reserving them.
+// Mirrors Database.generate_invoice_number in the OCaml backend: "<year>-<counter>".
+pub fn peek_invoice_numbers(count: usize) -> Result<Vec<String>, Error> {
+    let conn = connect_database()?;
+
+    let year = current_year(&conn)?;
+
+    // The counters table is created by the OCaml backend on its first run
+    let counter: i64 = conn
+        .query_row(
+            "SELECT counter FROM invoice_counters WHERE year = ?",
+            [year],
+            |row| row.get(0),
+        )
+        .unwrap_or(0);
+
+    Ok((1..=count as i64)
+        .map(|n| format!("{}-{}", year, counter + n))
+        .collect())
+}
+
+// The tables the OCaml backend creates on its first run, for runs that do not use it.
+// Kept in step with Database.create_tables in ocaml-backend/src/database.ml.
+pub fn create_backend_tables(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS invoice_counters (
+            year INTEGER PRIMARY KEY,
+            counter INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS invoices (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            invoice_number TEXT NOT NULL UNIQUE,
+            locale TEXT NOT NULL,
+            currency_id INTEGER NOT NULL,
+            your_company_id INTEGER NOT NULL,
+            your_bank_id INTEGER NOT NULL,
+            customer_id INTEGER NOT NULL,
+            author TEXT NOT NULL,
+            service TEXT NOT NULL,
+            pdf_title TEXT NOT NULL,
+            invoice_date TEXT NOT NULL,
+            due_date TEXT NOT NULL,
+            vat_enabled BOOLEAN NOT NULL,
+            vat_rate INTEGER NOT NULL,
+            pdf_content BLOB NOT NULL,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS line_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            invoice_id INTEGER NOT NULL,
+            date TEXT NOT NULL,
+            description TEXT NOT NULL,
+            price TEXT NOT NULL,
+            FOREIGN KEY (invoice_id) REFERENCES invoices(id)
+        );",
+    )
+    .map_err(|e| Error::database("Failed to create invoice tables").details(e))
+}
+
+// Take the next invoice number, as Database.generate_invoice_number does. Call it inside the
+// transaction that stores the invoice so a failed run does not use up a number.
+pub fn next_invoice_number(conn: &Connection) -> Result<String, Error> {
+    let year = current_year(conn)?;
+    let counter: i64 = match conn.query_row(
+        "SELECT counter FROM invoice_counters WHERE year = ?",
+        [year],
+        |row| row.get(0),
+    ) {
+        Ok(counter) => counter,
+        Err(rusqlite::Error::QueryReturnedNoRows) => 0,
+        Err(e) => return Err(Error::database("Failed to get invoice counter").details(e)),
+    };
+
+    conn.execute(
+        "INSERT INTO invoice_counters (year, counter) VALUES (?1, ?2)
+         ON CONFLICT(year) DO UPDATE SET counter = excluded.counter",
+        [year, counter + 1],
+    )
+    .map_err(|e| Error::database("Failed to update invoice counter").details(e))?;
+
+    Ok(format!("{}-{}", year, counter + 1))
+}
+
+// One line of a stored invoice, as in the line_items table
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredLine {
+    pub date: String,
+    pub description: String,
+    pub price: String,
+}
+
+// An invoice produced without the OCaml backend, stored the way Database.store_invoice does
+pub struct NewInvoice<'a> {
+    pub invoice_number: &'a str,
+    pub invoice_date: &'a str,
+    pub due_date: &'a str,
+    pub service: &'a str,
+    pub vat_enabled: bool,
+    pub vat_rate: u32,
+    pub lines: Vec<StoredLine>,
+    pub pdf: &'a [u8],
+}
+
+pub fn insert_invoice(conn: &Connection, invoice: &NewInvoice) -> Result<(), Error> {
+    // The reference columns are placeholders in the OCaml backend too
+    conn.execute(
+        "INSERT INTO invoices (invoice_number, locale, currency_id, your_company_id, your_bank_id,
+         customer_id, author, service, pdf_title, invoice_date, due_date, vat_enabled, vat_rate,
+         pdf_content)
+         VALUES (?1, 'nb-NO', 1, 1, 1, 1, '', ?2, 'Invoice', ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            invoice.invoice_number,
+            invoice.service,
+            invoice.invoice_date,
+            invoice.due_date,
+            invoice.vat_enabled,
+            invoice.vat_rate,
+            invoice.pdf
+        ],
+    )
+    .map_err(|e| Error::database("Failed to store invoice").details(e))?;
+    let invoice_id = conn.last_insert_rowid();
+
+    for line in &invoice.lines {
+        conn.execute(
+            "INSERT INTO line_items (invoice_id, date, description, price) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![invoice_id, line.date, line.description, line.price],
+        )
+        .map_err(|e| Error::database("Failed to store line item").details(e))?;
+    }
+
+    Ok(())
+}
+
+fn current_year(conn: &Connection) -> Result<i64, Error> {
+    conn.query_row(
+        "SELECT CAST(strftime('%Y', 'now', 'localtime') AS INTEGER)",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| Error::database("Failed to get current year").details(e))
+}
+
+// Date of the most recent stored invoice, if any
+pub fn latest_invoice_date() -> Result<Option<String>, Error> {
+    let conn = connect_database()?;
+
+    // The invoices table is created by the OCaml backend on its first run
+    let latest: Option<String> = conn
+        .query_row("SELECT MAX(invoice_date) FROM invoices", [], |row| {
+            row.get(0)
+        })
+        .unwrap_or(None);
+
+    Ok(latest.map(|date| date.chars().take(10).collect()))
+}
+
+// Id of the most recent stored invoice, or 0 if there is none
+pub fn latest_invoice_id() -> Result<i64, Error> {
+    let conn = connect_database()?;
+
+    // No invoices table yet means no invoices
+    Ok(conn
+        .query_row("SELECT COALESCE(MAX(id), 0) FROM invoices", [], |row| {
+            row.get(0)
+        })
+        .unwrap_or(0))
+}
+
+// Numbers of the invoices stored after the one with the given id, in the order they were stored
+pub fn invoice_numbers_after(id: i64) -> Result<Vec<String>, Error> {
+    let conn = connect_database()?;
+
+    // No invoices table yet means no invoices
+    let Ok(mut stmt) = conn.prepare("SELECT invoice_number FROM invoices WHERE id > ? ORDER BY id")
+    else {
+        return Ok(Vec::new());
+    };
+    let numbers = stmt
+        .query_map([id], |row| row.get(0))
+        .map_err(|e| Error::database("Failed to get invoices").details(e))?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| Error::database("Failed to read invoice").details(e))?;
+
+    Ok(numbers)
+}
+
+// Whether the invoices table exists with the OCaml backend's schema
+fn has_invoices_table(conn: &Connection) -> bool {
+    // First check if the invoices table exists and has been properly initialized by OCaml backend
+    let table_exists = conn
+        .prepare("SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='invoices'")
+        .and_then(|mut stmt| stmt.query_row([], |row| row.get::<_, i32>(0)))
+        .unwrap_or(0)
+        > 0;
+
+    if !table_exists {
+        // No invoices table yet
+        return false;
+    }
+
+    // Check if table has the expected schema
+    conn.prepare("PRAGMA table_info(invoices)")
+        .and_then(|mut stmt| {
+            let rows = stmt.query_map([], |row| {
+                row.get::<_, String>(1) // column name
+            })?;
+            let columns: Vec<String> = rows.collect::<Result<Vec<_>, _>>()?;
+            Ok(columns.contains(&"locale".to_string()))
+        })
+        .unwrap_or(false)
+}
+
+// Get all invoices from database
+pub fn get_all_invoices() -> Result<Vec<InvoiceRecord>, Error> {
+    let conn = connect_database()?;
+
+    // No invoices table yet, or one without the expected schema, means no invoices
+    if !has_invoices_table(&conn) {
+        return Ok(Vec::new());
+    }
+
+    // Use the OCaml database schema
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, invoice_number, service, invoice_date, due_date, 
+                vat_enabled, vat_rate, created_at, pdf_content, 
+                invoice_number IN (SELECT invoice_number FROM voided_invoices) 
+         FROM invoices 
+         ORDER BY created_at DESC",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let invoice_iter = stmt
+        .query_map([], |row| {
+            let pdf_content: Vec<u8> = row.get(8)?;
+            let pdf_base64 = general_purpose::STANDARD.encode(&pdf_content);
+
+            Ok(InvoiceRecord {
+                id: row.get(0)?,
+                invoice_number: row.get(1)?,
+                service: row.get(2)?,
+                invoice_date: row.get(3)?,
+                due_date: row.get(4)?,
+                vat_enabled: row.get::<_, bool>(5)?, // OCaml uses BOOLEAN not INTEGER
+                vat_rate: row.get(6)?,
+                created_at: row.get(7)?,
+                pdf_base64,
+                vat_summary: Vec::new(),
+                payment_days: None,
+                voided: row.get(9)?,
+            })
+        })
+        .map_err(|e| Error::database("Failed to execute query").details(e))?;
+
+    let mut invoices = Vec::new();
+    for invoice in invoice_iter {
+        invoices.push(invoice.map_err(|e| Error::database("Failed to parse row").details(e))?);
+    }
+
+    Ok(invoices)
+}
+
+// Get a specific invoice by ID
+pub fn get_invoice_by_id(id: i32) -> Result<InvoiceRecord, Error> {
+    find_invoice(id)?.ok_or_else(|| Error::not_found(format!("Invoice {} not found", id)))
+}
+
+// Look up an invoice, returning None if it (or the invoices table) does not exist
+pub fn find_invoice(id: i32) -> Result<Option<InvoiceRecord>, Error> {
+    let conn = connect_database()?;
+
+    // Check if the invoices table exists and is properly initialized
+    let table_exists = conn
+        .prepare("SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='invoices'")
+        .and_then(|mut stmt| stmt.query_row([], |row| row.get::<_, i32>(0)))
+        .unwrap_or(0)
+        > 0;
+
+    if !table_exists {
+        return Ok(None);
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, invoice_number, service, invoice_date, due_date, 
+                vat_enabled, vat_rate, created_at, pdf_content, 
+                invoice_number IN (SELECT invoice_number FROM voided_invoices) 
+         FROM invoices 
+         WHERE id = ?",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let invoice = stmt.query_row([id], |row| {
+        let pdf_content: Vec<u8> = row.get(8)?;
+        let pdf_base64 = general_purpose::STANDARD.encode(&pdf_content);
+
+        Ok(InvoiceRecord {
+            id: row.get(0)?,
+            invoice_number: row.get(1)?,
+            service: row.get(2)?,
+            invoice_date: row.get(3)?,
+            due_date: row.get(4)?,
+            vat_enabled: row.get::<_, bool>(5)?, // OCaml uses BOOLEAN not INTEGER
+            vat_rate: row.get(6)?,
+            created_at: row.get(7)?,
+            pdf_base64,
+            vat_summary: Vec::new(),
+            payment_days: None,
+            voided: row.get(9)?,
+        })
+    });
+
+    match invoice {
+        Ok(mut invoice) => {
+            invoice.vat_summary = load_vat_summary(&conn, &invoice.invoice_number)?;
+            invoice.payment_days = load_payment_days(&conn, &invoice.invoice_number)?;
+            Ok(Some(invoice))
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::database("Failed to get invoice").details(e)),
+    }
+}
+
+// Record the VAT summary of a generated invoice, replacing any earlier one for the same number
+pub fn store_vat_summary(invoice_number: &str, summary: &[VatSummaryLine]) -> Result<(), Error> {
+    let mut conn = connect_database()?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    insert_vat_summary(&tx, invoice_number, summary)?;
+    tx.commit()
+        .map_err(|e| Error::database("Failed to store VAT summary").details(e))
+}
+
+// The same on an open connection, e.g. inside a transaction
+pub fn insert_vat_summary(
+    conn: &Connection,
+    invoice_number: &str,
+    summary: &[VatSummaryLine],
+) -> Result<(), Error> {
+    conn.execute(
+        "DELETE FROM invoice_vat_summary WHERE invoice_number = ?",
+        [invoice_number],
+    )
+    .map_err(|e| Error::database("Failed to clear VAT summary").details(e))?;
+
+    for line in summary {
+        conn.execute(
+            "INSERT INTO invoice_vat_summary (invoice_number, rate, treatment, net, vat)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                invoice_number,
+                line.rate,
+                line.treatment.code(),
+                line.net,
+                line.vat
+            ],
+        )
+        .map_err(|e| Error::database("Failed to store VAT summary").details(e))?;
+    }
+    Ok(())
+}
+
+pub fn load_vat_summary(
+    conn: &Connection,
+    invoice_number: &str,
+) -> Result<Vec<VatSummaryLine>, Error> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT rate, treatment, net, vat FROM invoice_vat_summary
+             WHERE invoice_number = ? ORDER BY rowid",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([invoice_number], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| Error::database("Failed to get VAT summary").details(e))?;
+
+    rows.map(|row| {
+        let (rate, treatment, net, vat) =
+            row.map_err(|e| Error::database("Failed to read VAT summary").details(e))?;
+        Ok(VatSummaryLine {
+            rate,
+            treatment: VatTreatment::from_code(&treatment)?,
+            net,
+            vat,
+        })
+    })
+    .collect()
+}
+
+// Record the payment terms an invoice was issued with
+pub fn store_payment_days(invoice_number: &str, payment_days: u32) -> Result<(), Error> {
+    insert_payment_days(&connect_database()?, invoice_number, payment_days)
+}
+
+pub fn insert_payment_days(
+    conn: &Connection,
+    invoice_number: &str,
+    payment_days: u32,
+) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO invoice_terms (invoice_number, payment_days) VALUES (?1, ?2)",
+        rusqlite::params![invoice_number, payment_days],
+    )
+    .map_err(|e| Error::database("Failed to store payment terms").details(e))?;
+    Ok(())
+}
+
+fn load_payment_days(conn: &Connection, invoice_number: &str) -> Result<Option<u32>, Error> {
+    match conn.query_row(
+        "SELECT payment_days FROM invoice_terms WHERE invoice_number = ?",
+        [invoice_number],
+        |row| row.get(0),
+    ) {
+        Ok(days) => Ok(Some(days)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::database("Failed to get payment terms").details(e)),
+    }
+}
+
+// Who an invoice was from and to, kept so it can be rendered again
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct InvoiceParties {
+    pub currency: String,
+    pub sender: Vec<String>,
+    pub customer_name: String,
+    pub customer_address: Vec<String>,
+    pub bank_details: Vec<String>,
+}
+
+pub fn store_invoice_parties(invoice_number: &str, parties: &InvoiceParties) -> Result<(), Error> {
+    insert_invoice_parties(&connect_database()?, invoice_number, parties)
+}
+
+pub fn insert_invoice_parties(
+    conn: &Connection,
+    invoice_number: &str,
+    parties: &InvoiceParties,
+) -> Result<(), Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO invoice_parties (invoice_number, currency, sender, customer_name,
+         customer_address, bank_details) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            invoice_number,
+            parties.currency,
+            parties.sender.join("\n"),
+            parties.customer_name,
+            parties.customer_address.join("\n"),
+            parties.bank_details.join("\n")
+        ],
+    )
+    .map_err(|e| Error::database("Failed to store invoice parties").details(e))?;
+    Ok(())
+}
+
+pub fn load_invoice_parties(
+    conn: &Connection,
+    invoice_number: &str,
+) -> Result<Option<InvoiceParties>, Error> {
+    let lines = |text: String| -> Vec<String> {
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    match conn.query_row(
+        "SELECT currency, sender, customer_name, customer_address, bank_details
+         FROM invoice_parties WHERE invoice_number = ?",
+        [invoice_number],
+        |row| {
+            Ok(InvoiceParties {
+                currency: row.get(0)?,
+                sender: lines(row.get(1)?),
+                customer_name: row.get(2)?,
+                customer_address: lines(row.get(3)?),
+                bank_details: lines(row.get(4)?),
+            })
+        },
+    ) {
+        Ok(parties) => Ok(Some(parties)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::database("Failed to get invoice parties").details(e)),
+    }
+}
+
+// The lines of a stored invoice, in the order they were stored
+pub fn load_line_items(conn: &Connection, invoice_id: i64) -> Result<Vec<StoredLine>, Error> {
+    let mut stmt = conn
+        .prepare("SELECT date, description, price FROM line_items WHERE invoice_id = ? ORDER BY id")
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([invoice_id], |row| {
+            Ok(StoredLine {
+                date: row.get(0)?,
+                description: row.get(1)?,
+                price: row.get(2)?,
+            })
+        })
+        .map_err(|e| Error::database("Failed to get line items").details(e))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::database("Failed to read line item").details(e))
+}
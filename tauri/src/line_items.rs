@@ -0,0 +1,201 @@
+use crate::errors::Error;
+use crate::money::{self, Currency, Money};
+use crate::placeholders;
+use crate::settings::{configured_currency, get_setting, set_setting};
+use crate::vat::{self, VatCode, VatSettings, VatSummaryLine, VatTreatment};
+use serde::{Deserialize, Serialize};
+
+// Setting key holding the invoice lines as JSON; when empty, invoices use the single
+// description and amount settings
+pub const LINE_ITEMS_KEY: &str = "line_items";
+
+// Quantities are kept in thousandths, e.g. 1.5 hours
+pub const QUANTITY_DIGITS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LineItem {
+    pub description: String,
+    #[serde(default)]
+    pub date: String,
+    #[serde(default = "default_quantity")]
+    pub quantity: String,
+    // e.g. "hours", "nights"; empty for plain amounts
+    #[serde(default)]
+    pub unit: String,
+    pub unit_price: String,
+    // Override the sender's VAT rate and treatment for this line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat_rate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat_treatment: Option<VatTreatment>,
+}
+
+fn default_quantity() -> String {
+    "1".to_string()
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LineTotal {
+    #[serde(flatten)]
+    pub item: LineItem,
+    pub vat_code: VatCode,
+    pub net: Money,
+    pub vat: Money,
+    pub total: Money,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LineTotals {
+    pub lines: Vec<LineTotal>,
+    pub subtotal: Money,
+    pub vat_total: Money,
+    pub total: Money,
+    pub vat_summary: Vec<VatSummaryLine>,
+}
+
+fn line_total(item: &LineItem, currency: Currency, vat: &VatSettings) -> Result<LineTotal, Error> {
+    if item.description.trim().is_empty() {
+        return Err(Error::invalid("Description is missing"));
+    }
+    placeholders::validate(&item.description, &placeholders::RECIPIENT_PLACEHOLDERS)?;
+
+    let quantity = money::parse_decimal(&item.quantity, QUANTITY_DIGITS)
+        .map_err(|e| e.context("Invalid quantity"))?;
+    if quantity <= 0 {
+        return Err(Error::invalid(format!(
+            "Quantity {} must be above 0",
+            item.quantity
+        )));
+    }
+
+    let unit_price = Money::parse(&item.unit_price, currency)?.minor;
+    if unit_price < 0 {
+        return Err(Error::invalid(format!(
+            "Unit price {} cannot be negative",
+            item.unit_price
+        )));
+    }
+
+    let vat_code = vat.code_for(item.vat_rate, item.vat_treatment)?;
+
+    // quantity * unit price, rounded to the currency's minor units once per line
+    let net = currency.round(
+        quantity as i128 * unit_price as i128,
+        QUANTITY_DIGITS + currency.minor_digits,
+    );
+    let net = i64::try_from(net).map_err(|_| Error::invalid("Line amount is too large"))?;
+    let vat = vat_code.vat_on(net);
+
+    Ok(LineTotal {
+        item: item.clone(),
+        vat_code,
+        net: Money::new(net, currency),
+        vat: Money::new(vat, currency),
+        total: Money::new(net + vat, currency),
+    })
+}
+
+// Compute exact line, VAT and invoice totals, rejecting invalid lines
+pub fn calculate_totals(
+    items: &[LineItem],
+    currency: Currency,
+    vat: &VatSettings,
+) -> Result<LineTotals, Error> {
+    let lines = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            line_total(item, currency, vat).map_err(|e| e.context(format!("Line {}", i + 1)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let subtotal: i64 = lines.iter().map(|l| l.net.minor).sum();
+    let vat_total: i64 = lines.iter().map(|l| l.vat.minor).sum();
+    let by_code: Vec<(VatCode, i64, i64)> = lines
+        .iter()
+        .map(|l| (l.vat_code, l.net.minor, l.vat.minor))
+        .collect();
+
+    Ok(LineTotals {
+        vat_summary: vat::summarize(&by_code, currency),
+        lines,
+        subtotal: Money::new(subtotal, currency),
+        vat_total: Money::new(vat_total, currency),
+        total: Money::new(subtotal + vat_total, currency),
+    })
+}
+
+// Text shown on the invoice for a line, with the quantity and unit price when they add anything
+pub fn describe(line: &LineTotal) -> String {
+    let item = &line.item;
+    let quantity = money::parse_decimal(&item.quantity, QUANTITY_DIGITS).unwrap_or_default();
+    let unit_price = Money::parse(&item.unit_price, line.net.currency)
+        .unwrap_or_else(|_| Money::zero(line.net.currency));
+
+    if quantity == 10i64.pow(QUANTITY_DIGITS) && item.unit.trim().is_empty() {
+        return item.description.trim().to_string();
+    }
+
+    let quantity = money::format_quantity(quantity, QUANTITY_DIGITS);
+    let amount = if item.unit.trim().is_empty() {
+        quantity
+    } else {
+        format!("{} {}", quantity, item.unit.trim())
+    };
+
+    format!(
+        "{} ({} × {})",
+        item.description.trim(),
+        amount,
+        unit_price.display_number()
+    )
+}
+
+pub fn decode_line_items(value: &str) -> Result<Vec<LineItem>, Error> {
+    if value.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(value).map_err(|e| Error::invalid("Invalid line items").details(e))
+}
+
+// Parse and validate line items before they are stored
+pub fn parse_line_items(
+    value: &str,
+    currency: Currency,
+    vat: &VatSettings,
+) -> Result<Vec<LineItem>, Error> {
+    let items = decode_line_items(value)?;
+    calculate_totals(&items, currency, vat)?;
+    Ok(items)
+}
+
+pub fn load_line_items() -> Result<Vec<LineItem>, Error> {
+    decode_line_items(&get_setting(LINE_ITEMS_KEY)?.unwrap_or_default())
+}
+
+pub fn get_line_items() -> Result<Vec<LineItem>, Error> {
+    load_line_items()
+}
+
+// Save the invoice lines; an empty list goes back to the single description and amount
+pub fn save_line_items(items: Vec<LineItem>) -> Result<LineTotals, Error> {
+    let totals = calculate_totals(&items, configured_currency()?, &vat::load_vat_settings()?)?;
+
+    let value = if items.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(&items)
+            .map_err(|e| Error::invalid("Failed to serialize line items").details(e))?
+    };
+    set_setting(LINE_ITEMS_KEY, &value)?;
+
+    Ok(totals)
+}
+
+pub fn calculate_line_totals() -> Result<LineTotals, Error> {
+    calculate_totals(
+        &load_line_items()?,
+        configured_currency()?,
+        &vat::load_vat_settings()?,
+    )
+}
@@ -0,0 +1,362 @@
+// Exact money handling: amounts are kept in minor units (øre, cents) of their currency,
+// so parsing, splitting and summing never loses or invents a cent
+use crate::errors::Error;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Currency {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub symbol: &'static str,
+    pub minor_digits: u32,
+}
+
+pub const NOK: Currency = Currency {
+    code: "NOK",
+    name: "Norwegian Krone",
+    symbol: "kr",
+    minor_digits: 2,
+};
+
+pub const CURRENCIES: [Currency; 8] = [
+    NOK,
+    Currency {
+        code: "SEK",
+        name: "Swedish Krona",
+        symbol: "kr",
+        minor_digits: 2,
+    },
+    Currency {
+        code: "DKK",
+        name: "Danish Krone",
+        symbol: "kr",
+        minor_digits: 2,
+    },
+    Currency {
+        code: "EUR",
+        name: "Euro",
+        symbol: "€",
+        minor_digits: 2,
+    },
+    Currency {
+        code: "USD",
+        name: "US Dollar",
+        symbol: "$",
+        minor_digits: 2,
+    },
+    Currency {
+        code: "GBP",
+        name: "Pound Sterling",
+        symbol: "£",
+        minor_digits: 2,
+    },
+    Currency {
+        code: "ISK",
+        name: "Icelandic Króna",
+        symbol: "kr",
+        minor_digits: 0,
+    },
+    Currency {
+        code: "JPY",
+        name: "Japanese Yen",
+        symbol: "¥",
+        minor_digits: 0,
+    },
+];
+
+impl Currency {
+    pub fn from_code(code: &str) -> Result<Currency, Error> {
+        let code = code.trim();
+        if code.is_empty() {
+            return Ok(NOK);
+        }
+
+        CURRENCIES
+            .iter()
+            .find(|c| c.code.eq_ignore_ascii_case(code))
+            .copied()
+            .ok_or_else(|| {
+                Error::invalid(format!("Unknown currency '{}'", code)).details(format!(
+                    "Supported: {}",
+                    CURRENCIES
+                        .iter()
+                        .map(|c| c.code)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+
+    // Round a value with `digits` decimals to this currency's minor units, halves away from zero
+    pub fn round(&self, value: i128, digits: u32) -> i128 {
+        if digits <= self.minor_digits {
+            value * 10i128.pow(self.minor_digits - digits)
+        } else {
+            div_round(value, 10i128.pow(digits - self.minor_digits))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Money {
+    pub minor: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(minor: i64, currency: Currency) -> Self {
+        Money { minor, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+
+    // Parse user input such as "5000.00", "5 000,00", "5.000,50" or "kr 5 000"
+    pub fn parse(input: &str, currency: Currency) -> Result<Money, Error> {
+        let normalized = normalize_amount(input, currency)
+            .map_err(|e| Error::invalid(format!("Invalid amount '{}'", input.trim())).details(e))?;
+
+        parse_decimal(&normalized, currency.minor_digits)
+            .map(|minor| Money::new(minor, currency))
+            .map_err(|e| e.context(format!("Invalid amount '{}'", input.trim())))
+    }
+
+    // Plain decimal with a dot, as stored in settings and passed to the backend, e.g. "1250.50"
+    pub fn plain(&self) -> String {
+        let digits = self.currency.minor_digits;
+        let scale = 10u64.pow(digits);
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let abs = self.minor.unsigned_abs();
+
+        if digits == 0 {
+            format!("{}{}", sign, abs)
+        } else {
+            format!(
+                "{}{}.{:0width$}",
+                sign,
+                abs / scale,
+                abs % scale,
+                width = digits as usize
+            )
+        }
+    }
+
+    // Number as shown on invoices, e.g. "1 250,50"
+    pub fn display_number(&self) -> String {
+        let plain = self.plain();
+        let (sign, plain) = match plain.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", plain.as_str()),
+        };
+        let (whole, fraction) = match plain.split_once('.') {
+            Some((whole, fraction)) => (whole, format!(",{}", fraction)),
+            None => (plain, String::new()),
+        };
+
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(' ');
+            }
+            grouped.push(digit);
+        }
+
+        format!("{}{}{}", sign, grouped, fraction)
+    }
+
+    // Amount with its currency symbol, e.g. "1 250,50 kr"
+    pub fn display(&self) -> String {
+        format!("{} {}", self.display_number(), self.currency.symbol)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+// Serialized as the plain decimal string so JSON consumers never see binary floats
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.plain())
+    }
+}
+
+fn strip_currency(input: &str, currency: Currency) -> &str {
+    let abbreviated = format!("{}.", currency.symbol);
+    let markers = [currency.code, abbreviated.as_str(), currency.symbol];
+
+    for marker in markers {
+        let len = marker.len();
+        if input.len() < len {
+            continue;
+        }
+        if input
+            .get(..len)
+            .is_some_and(|p| p.eq_ignore_ascii_case(marker))
+        {
+            return input[len..].trim();
+        }
+        if input
+            .get(input.len() - len..)
+            .is_some_and(|p| p.eq_ignore_ascii_case(marker))
+        {
+            return input[..input.len() - len].trim();
+        }
+    }
+    input
+}
+
+// Turn locale-formatted input into a plain decimal: currency markers and digit grouping are
+// removed and the decimal separator becomes a dot. A single separator followed by exactly
+// three digits is read as grouping ("5.000" is five thousand) unless the currency uses three decimals.
+fn normalize_amount(input: &str, currency: Currency) -> Result<String, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err("the amount is empty".to_string());
+    }
+
+    let text = strip_currency(trimmed, currency);
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, text),
+    };
+
+    // Spaces (including non-breaking ones) and apostrophes are only ever used for grouping
+    let compact: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\u{202f}' && *c != '\'')
+        .collect();
+
+    if let Some(c) = compact
+        .chars()
+        .find(|c| !c.is_ascii_digit() && *c != '.' && *c != ',')
+    {
+        return Err(format!("unexpected character '{}'", c));
+    }
+
+    let last_dot = compact.rfind('.');
+    let last_comma = compact.rfind(',');
+    let separator_count = |sep: char| compact.matches(sep).count();
+
+    let decimal_at = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(dot.max(comma)),
+        (Some(pos), None) | (None, Some(pos)) => {
+            let sep = compact[pos..].chars().next().unwrap_or('.');
+            let digits_after = compact.len() - pos - 1;
+            let before = &compact[..pos];
+            let looks_grouped = digits_after == 3
+                && currency.minor_digits != 3
+                && !before.is_empty()
+                && !before.starts_with('0');
+            if separator_count(sep) > 1 || looks_grouped {
+                None
+            } else {
+                Some(pos)
+            }
+        }
+        (None, None) => None,
+    };
+
+    let (whole, fraction) = match decimal_at {
+        Some(pos) => (&compact[..pos], &compact[pos + 1..]),
+        None => (compact.as_str(), ""),
+    };
+
+    // Whatever separators are left in the whole part must group digits in threes
+    let groups: Vec<&str> = whole.split(['.', ',']).collect();
+    if groups.len() > 1
+        && (groups[0].is_empty()
+            || groups[0].len() > 3
+            || groups[0].starts_with('0')
+            || groups[1..].iter().any(|g| g.len() != 3))
+    {
+        return Err("digit grouping is not in groups of three".to_string());
+    }
+    if fraction.contains(['.', ',']) {
+        return Err("more than one decimal separator".to_string());
+    }
+
+    let whole: String = groups.concat();
+    let sign = if negative { "-" } else { "" };
+    if fraction.is_empty() {
+        Ok(format!("{}{}", sign, whole))
+    } else {
+        Ok(format!("{}{}.{}", sign, whole, fraction))
+    }
+}
+
+// Parse a plain decimal such as "1250", "1250.5" or "1250,50" into an integer scaled by 10^decimals
+pub fn parse_decimal(value: &str, decimals: u32) -> Result<i64, Error> {
+    let trimmed = value.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed),
+    };
+
+    let (whole, fraction) = match digits.find(['.', ',']) {
+        Some(pos) => (&digits[..pos], &digits[pos + 1..]),
+        None => (digits, ""),
+    };
+
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(Error::invalid(format!("Invalid number '{}'", value.trim())));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(Error::invalid(match decimals {
+            0 => format!("'{}' cannot have decimals", value.trim()),
+            _ => format!(
+                "'{}' has more than {} decimal places",
+                value.trim(),
+                decimals
+            ),
+        }));
+    }
+
+    let too_large = || Error::invalid(format!("'{}' is too large", value.trim()));
+    let whole_value: i64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| too_large())?
+    };
+    let fraction_value: i64 = format!("{:0<width$}", fraction, width = decimals as usize)
+        .parse()
+        .unwrap_or(0);
+
+    let parsed = whole_value
+        .checked_mul(10i64.pow(decimals))
+        .and_then(|w| w.checked_add(fraction_value))
+        .ok_or_else(too_large)?;
+
+    Ok(if negative { -parsed } else { parsed })
+}
+
+// Divide, rounding halves away from zero
+pub fn div_round(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.abs() * 2 >= denominator.abs() {
+        quotient + numerator.signum() * denominator.signum()
+    } else {
+        quotient
+    }
+}
+
+// Format a scaled integer for display in invoices, trimming trailing zeros, e.g. 1500 (3 decimals) -> "1,5"
+pub fn format_quantity(value: i64, decimals: u32) -> String {
+    let scale = 10u64.pow(decimals);
+    let abs = value.unsigned_abs();
+    let sign = if value < 0 { "-" } else { "" };
+    let fraction = format!("{:0width$}", abs % scale, width = decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        format!("{}{}", sign, abs / scale)
+    } else {
+        format!("{}{},{}", sign, abs / scale, fraction)
+    }
+}
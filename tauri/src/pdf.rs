@@ -0,0 +1,397 @@
+// Native invoice PDF, laid out like ocaml-backend/src/invoice_layout.ml so either renderer
+// produces the same document. Text is set in the standard Helvetica font with WinAnsi
+// encoding, so no font has to be embedded.
+use crate::errors::Error;
+use crate::money::{Currency, Money};
+use crate::vat::{VatSummaryLine, VatTreatment};
+
+// Page geometry and font sizes, as in Pdf_text.default_config
+const WIDTH: f64 = 595.0;
+const MARGIN: f64 = 50.0;
+const FONT_SIZE_TITLE: f64 = 20.0;
+const FONT_SIZE_HEADING: f64 = 14.0;
+const FONT_SIZE_NORMAL: f64 = 11.0;
+const FONT_SIZE_SMALL: f64 = 9.0;
+const LINE_HEIGHT: f64 = 14.0;
+const TOP: f64 = 750.0;
+const CUSTOMER_X: f64 = 350.0;
+// Room kept free for the price column next to wrapped descriptions
+const PRICE_COLUMN_WIDTH: f64 = 100.0;
+const PRICE_COLUMN_PADDING: f64 = 20.0;
+
+// One line of the line table: its description and net amount. The date is stored with the
+// invoice but, as in the OCaml layout, not printed.
+pub struct DocumentLine {
+    pub date: String,
+    pub description: String,
+    pub amount: Money,
+}
+
+// Everything printed on one invoice
+pub struct InvoiceDocument {
+    pub invoice_number: String,
+    // YYYY-MM-DD
+    pub invoice_date: String,
+    pub due_date: String,
+    pub sender: Vec<String>,
+    pub customer_name: String,
+    pub customer_address: Vec<String>,
+    // What is billed, the invoice's description; stored and used in file names, not printed
+    pub service: String,
+    pub lines: Vec<DocumentLine>,
+    // Empty when the sender is not VAT registered
+    pub vat_summary: Vec<VatSummaryLine>,
+    pub bank_details: Vec<String>,
+}
+
+// Helvetica advance widths in thousandths of the font size, for WinAnsi codes 32 to 126
+const HELVETICA_ASCII: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+// ... and for codes 160 to 255, which match Latin-1
+const HELVETICA_LATIN1: [u16; 96] = [
+    278, 333, 556, 556, 556, 556, 260, 556, 333, 737, 370, 556, 584, 333, 737, 333, 400, 584, 333,
+    333, 333, 556, 537, 278, 333, 333, 365, 556, 834, 834, 834, 611, 667, 667, 667, 667, 667, 667,
+    1000, 722, 667, 667, 667, 667, 278, 278, 278, 278, 722, 722, 778, 778, 778, 778, 778, 584, 778,
+    722, 722, 722, 722, 667, 667, 611, 556, 556, 556, 556, 556, 556, 889, 500, 556, 556, 556, 556,
+    278, 278, 278, 278, 556, 556, 556, 556, 556, 556, 556, 584, 611, 556, 556, 556, 556, 500, 556,
+    500,
+];
+
+// WinAnsi code for a character; characters the font cannot show become '?'
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+fn char_width(code: u8) -> u16 {
+    match code {
+        32..=126 => HELVETICA_ASCII[(code - 32) as usize],
+        160..=255 => HELVETICA_LATIN1[(code - 160) as usize],
+        0x85 | 0x97 => 1000,
+        0x91 | 0x92 => 222,
+        0x93 | 0x94 => 333,
+        0x95 => 350,
+        _ => 556,
+    }
+}
+
+pub fn text_width(text: &str, size: f64) -> f64 {
+    let thousandths: u32 = text.chars().map(|c| char_width(win_ansi(c)) as u32).sum();
+    thousandths as f64 / 1000.0 * size
+}
+
+// Break text into lines no wider than `max_width`, as Pdf_text.wrap_text does; a word wider
+// than the line gets a line of its own. Line breaks in the text are kept.
+pub fn wrap_text(text: &str, max_width: f64, size: f64) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        let mut current_width = 0.0;
+
+        for word in paragraph.split(' ') {
+            let word_width = text_width(&format!("{} ", word), size);
+            if current.is_empty() || current_width + word_width <= max_width {
+                if !current.is_empty() {
+                    current.push(' ');
+                }
+                current.push_str(word);
+                current_width += word_width;
+            } else {
+                lines.push(current.trim().to_string());
+                current = word.to_string();
+                current_width = word_width;
+            }
+        }
+        if !current.trim().is_empty() {
+            lines.push(current.trim().to_string());
+        }
+    }
+
+    lines
+}
+
+// PDF string literal with WinAnsi bytes, escaping what PDF syntax requires
+fn pdf_string(text: &str) -> String {
+    let mut escaped = String::from("(");
+    for code in text.chars().map(win_ansi) {
+        match code {
+            b'(' | b')' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(code as char);
+            }
+            32..=126 => escaped.push(code as char),
+            _ => escaped.push_str(&format!("\\{:03o}", code)),
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+// "2025-01-31" as "31/01/2025", like Formatting_utils.format_date
+pub fn format_date(date: &str) -> String {
+    let date = date.split('T').next().unwrap_or(date);
+    match date.split('-').collect::<Vec<_>>().as_slice() {
+        [year, month, day] => format!("{}/{}/{}", day, month, year),
+        _ => date.to_string(),
+    }
+}
+
+// Content stream operators, with the current position moving down the page
+struct Layout {
+    ops: Vec<String>,
+    y: f64,
+}
+
+impl Layout {
+    fn text(&mut self, text: &str, x: f64, y: f64, size: f64) {
+        self.ops.push("BT".to_string());
+        self.ops.push(format!("/F1 {:.1} Tf", size));
+        self.ops
+            .push(format!("1.0 0.0 0.0 1.0 {:.1} {:.1} Tm", x, y));
+        self.ops.push(format!("{} Tj", pdf_string(text)));
+        self.ops.push("ET".to_string());
+    }
+
+    fn right_aligned(&mut self, text: &str, right_edge: f64, y: f64, size: f64) {
+        self.text(text, right_edge - text_width(text, size), y, size);
+    }
+
+    fn horizontal_line(&mut self, x1: f64, x2: f64, y: f64) {
+        self.ops.extend([
+            "q".to_string(),
+            "0.5 w".to_string(),
+            format!("{:.1} {:.1} m", x1, y),
+            format!("{:.1} {:.1} l", x2, y),
+            "S".to_string(),
+            "Q".to_string(),
+        ]);
+    }
+
+    // Lines of text from `y` down; returns the height used
+    fn text_block(&mut self, lines: &[String], x: f64, y: f64, size: f64) -> f64 {
+        for (i, line) in lines.iter().enumerate() {
+            self.text(line, x, y - i as f64 * (size + 3.0), size);
+        }
+        lines.len() as f64 * (size + 3.0)
+    }
+}
+
+fn vat_line_text(line: &VatSummaryLine, currency: Currency) -> String {
+    let amount = |value: &str| {
+        Money::parse(value, currency)
+            .map(|m| m.display())
+            .unwrap_or_else(|_| value.to_string())
+    };
+    match line.treatment {
+        VatTreatment::Exempt => format!(
+            "Fritatt for mva ({}): {}",
+            amount(&line.net),
+            amount(&line.vat)
+        ),
+        VatTreatment::ReverseCharge => format!(
+            "Omvendt avgiftsplikt ({}): {}",
+            amount(&line.net),
+            amount(&line.vat)
+        ),
+        VatTreatment::Standard => format!("Moms ({}%): {}", line.rate, amount(&line.vat)),
+    }
+}
+
+fn layout(document: &InvoiceDocument) -> Result<Vec<String>, Error> {
+    let mut page = Layout {
+        ops: Vec::new(),
+        y: TOP,
+    };
+    let right_edge = WIDTH - MARGIN;
+
+    // Title
+    page.text("FAKTURA", MARGIN, page.y, FONT_SIZE_TITLE);
+    page.y -= FONT_SIZE_TITLE + 23.0;
+
+    // Sender on the left, customer on the right
+    let sender_height = page.text_block(&document.sender, MARGIN, page.y, FONT_SIZE_NORMAL);
+    page.text(
+        &document.customer_name,
+        CUSTOMER_X,
+        page.y,
+        FONT_SIZE_NORMAL,
+    );
+    let address_height = page.text_block(
+        &document.customer_address,
+        CUSTOMER_X,
+        page.y - FONT_SIZE_NORMAL - 3.0,
+        FONT_SIZE_NORMAL,
+    );
+    let customer_height = FONT_SIZE_NORMAL + address_height + 3.0;
+    page.y -= sender_height.max(customer_height) + 25.0;
+
+    // Dates and invoice number
+    let metadata = [
+        format!("Fakturadato: {}", format_date(&document.invoice_date)),
+        format!("Forfallsdato: {}", format_date(&document.due_date)),
+        format!("Fakturanummer: {}", document.invoice_number),
+    ];
+    let metadata_height = page.text_block(&metadata, MARGIN, page.y, FONT_SIZE_NORMAL);
+    page.y -= metadata_height + 30.0;
+
+    // Line table header
+    page.text("Beskrivelse", MARGIN, page.y, FONT_SIZE_NORMAL);
+    page.right_aligned("Beløp", right_edge, page.y, FONT_SIZE_NORMAL);
+    page.horizontal_line(MARGIN, right_edge, page.y - 4.0);
+    page.y -= 16.0;
+
+    // Lines, descriptions wrapped beside the price column
+    let max_description_width = right_edge - MARGIN - PRICE_COLUMN_WIDTH - PRICE_COLUMN_PADDING;
+    for line in &document.lines {
+        let wrapped = wrap_text(&line.description, max_description_width, FONT_SIZE_NORMAL);
+        for (i, text) in wrapped.iter().enumerate() {
+            page.text(
+                text,
+                MARGIN,
+                page.y - i as f64 * LINE_HEIGHT,
+                FONT_SIZE_NORMAL,
+            );
+        }
+        page.right_aligned(&line.amount.display(), right_edge, page.y, FONT_SIZE_NORMAL);
+        page.y -= wrapped.len() as f64 * LINE_HEIGHT;
+    }
+
+    // Totals; the VAT per rate was computed exactly by the app, so it is summed as it is
+    let currency = match document.lines.first() {
+        Some(line) => line.amount.currency,
+        None => return Err(Error::invalid("The invoice has no lines")),
+    };
+    let subtotal: i64 = document.lines.iter().map(|l| l.amount.minor).sum();
+    let mut vat_total = 0;
+    for line in &document.vat_summary {
+        vat_total += Money::parse(&line.vat, currency)?.minor;
+    }
+
+    page.horizontal_line(MARGIN, right_edge, page.y + 3.0);
+    page.y -= 15.0;
+
+    if !document.vat_summary.is_empty() {
+        let row_height = FONT_SIZE_NORMAL + 3.0;
+        let texts: Vec<String> =
+            std::iter::once(format!("Subtotal: {}", Money::new(subtotal, currency)))
+                .chain(
+                    document
+                        .vat_summary
+                        .iter()
+                        .map(|line| vat_line_text(line, currency)),
+                )
+                .collect();
+        for (i, text) in texts.iter().enumerate() {
+            page.right_aligned(
+                text,
+                right_edge,
+                page.y - i as f64 * row_height,
+                FONT_SIZE_NORMAL,
+            );
+        }
+        page.y -= texts.len() as f64 * row_height + 5.0;
+    }
+
+    let total = Money::new(subtotal + vat_total, currency);
+    page.right_aligned(&total.display(), right_edge, page.y, FONT_SIZE_HEADING);
+    page.y -= FONT_SIZE_HEADING + 43.0;
+
+    // Payment details
+    page.text("Betalingsdetaljer", MARGIN, page.y, FONT_SIZE_HEADING);
+    let details_y = page.y - FONT_SIZE_HEADING - 8.0;
+    page.text_block(&document.bank_details, MARGIN, details_y, FONT_SIZE_SMALL);
+
+    Ok(page.ops)
+}
+
+// A single-page PDF with the given content stream, in the object layout of pdf_document.ml
+fn pdf_file(content: &str) -> Vec<u8> {
+    let objects = [
+        "<<\n/Type /Catalog\n/Pages 2 0 R\n>>".to_string(),
+        "<<\n/Type /Pages\n/Kids [3 0 R]\n/Count 1\n>>".to_string(),
+        // The OCaml backend uses a Letter media box for an A4 layout; kept so both match
+        "<<\n/Type /Page\n/Parent 2 0 R\n/MediaBox [0 0 612 792]\n/Resources 4 0 R\n/Contents 5 0 R\n>>"
+            .to_string(),
+        "<<\n/Font <<\n/F1 <<\n/Type /Font\n/Subtype /Type1\n/BaseFont /Helvetica\n/Encoding /WinAnsiEncoding\n>>\n>>\n>>"
+            .to_string(),
+        format!(
+            "<<\n/Length {}\n>>\nstream\n{}\nendstream",
+            content.len(),
+            content
+        ),
+    ];
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n\n", i + 1, object).as_bytes());
+    }
+
+    let xref = pdf.len();
+    let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        table.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    table.push_str(&format!(
+        "trailer\n<<\n/Size {}\n/Root 1 0 R\n>>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.extend_from_slice(table.as_bytes());
+    pdf
+}
+
+// The PDF of one invoice
+pub fn render_invoice(document: &InvoiceDocument) -> Result<Vec<u8>, Error> {
+    let ops = layout(document)?;
+    Ok(pdf_file(&ops.join("\n")))
+}
+
+// The PDF of one invoice with `watermark` printed large and light grey across the page,
+// beneath the invoice text
+pub fn render_watermarked(document: &InvoiceDocument, watermark: &str) -> Result<Vec<u8>, Error> {
+    let size = 96.0;
+    // Rotated 45 degrees about the middle of the 612 x 792 media box
+    let (cos, sin) = (
+        std::f64::consts::FRAC_1_SQRT_2,
+        std::f64::consts::FRAC_1_SQRT_2,
+    );
+    let half = text_width(watermark, size) / 2.0;
+    let (x, y) = (306.0 - half * cos, 396.0 - half * sin);
+
+    let mut ops = vec![
+        "q".to_string(),
+        "0.85 g".to_string(),
+        "BT".to_string(),
+        format!("/F1 {:.1} Tf", size),
+        format!(
+            "{:.4} {:.4} {:.4} {:.4} {:.1} {:.1} Tm",
+            cos, sin, -sin, cos, x, y
+        ),
+        format!("{} Tj", pdf_string(watermark)),
+        "ET".to_string(),
+        "Q".to_string(),
+    ];
+    ops.extend(layout(document)?);
+    Ok(pdf_file(&ops.join("\n")))
+}
@@ -0,0 +1,324 @@
+// Placeholders in descriptions, line items and file names, e.g. "Strøm {prev_month_name} {prev_year}".
+// Date placeholders are relative to the invoice date. An offset moves that date by whole months:
+// "prev_" and "next_" move it one month, and {month_name-3} three months back. Write {{ and }}
+// for literal braces.
+use crate::errors::Error;
+use crate::recipients::{self, Recipient};
+use crate::settings::load_config;
+use crate::terms::{self, RunOptions};
+use chrono::{Datelike, Local, Months, NaiveDate};
+use std::collections::BTreeMap;
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "januar",
+    "februar",
+    "mars",
+    "april",
+    "mai",
+    "juni",
+    "juli",
+    "august",
+    "september",
+    "oktober",
+    "november",
+    "desember",
+];
+
+// Placeholders taken from the invoice date, available everywhere
+pub const DATE_PLACEHOLDERS: [&str; 11] = [
+    "date",
+    "day",
+    "month",
+    "month_name",
+    "year",
+    "quarter",
+    "month_start",
+    "month_end",
+    "month_range",
+    "quarter_start",
+    "quarter_range",
+];
+
+// Placeholders for the recipient of an invoice, available in descriptions and line items
+pub const RECIPIENT_PLACEHOLDERS: [&str; 4] = [
+    "recipient_name",
+    "recipient_email",
+    "recipient_address",
+    "due_date",
+];
+
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder { name: &'a str, offset: i32 },
+}
+
+// Split "prev_month_name" into ("month_name", -1) and "year+2" into ("year", 2)
+fn parse_placeholder(raw: &str) -> Result<(&str, i32), Error> {
+    if let Some(name) = raw.strip_prefix("prev_") {
+        return Ok((name, -1));
+    }
+    if let Some(name) = raw.strip_prefix("next_") {
+        return Ok((name, 1));
+    }
+    match raw.find(['+', '-']) {
+        Some(at) => {
+            let offset = raw[at..]
+                .parse::<i32>()
+                .map_err(|_| Error::invalid(format!("Invalid month offset in {{{}}}", raw)))?;
+            Ok((&raw[..at], offset))
+        }
+        None => Ok((raw, 0)),
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while let Some(at) = rest.find(['{', '}']) {
+        if at > 0 {
+            segments.push(Segment::Text(&rest[..at]));
+        }
+        let after = &rest[at + 1..];
+
+        if rest[at..].starts_with("{{") {
+            segments.push(Segment::Text("{"));
+            rest = &after[1..];
+        } else if rest[at..].starts_with("}}") {
+            segments.push(Segment::Text("}"));
+            rest = &after[1..];
+        } else if rest[at..].starts_with('}') {
+            return Err(Error::invalid(format!(
+                "Unmatched '}}' in \"{}\"",
+                template
+            )));
+        } else {
+            let end = after
+                .find('}')
+                .ok_or_else(|| Error::invalid(format!("Unclosed '{{' in \"{}\"", template)))?;
+            let (name, offset) = parse_placeholder(after[..end].trim())?;
+            segments.push(Segment::Placeholder { name, offset });
+            rest = &after[end + 1..];
+        }
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    Ok(segments)
+}
+
+fn unknown_placeholder(name: &str, template: &str, fields: &[&str]) -> Error {
+    Error::invalid(format!(
+        "Unknown placeholder {{{}}} in \"{}\"",
+        name, template
+    ))
+    .details(format!(
+        "Available: {}",
+        DATE_PLACEHOLDERS
+            .iter()
+            .chain(fields)
+            .map(|p| format!("{{{}}}", p))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+// Check that a template only uses date placeholders and the given fields
+pub fn validate(template: &str, fields: &[&str]) -> Result<(), Error> {
+    for segment in parse(template)? {
+        if let Segment::Placeholder { name, offset } = segment {
+            if DATE_PLACEHOLDERS.contains(&name) {
+                continue;
+            }
+            if !fields.contains(&name) {
+                return Err(unknown_placeholder(name, template, fields));
+            }
+            if offset != 0 {
+                return Err(Error::invalid(format!(
+                    "Only date placeholders take a month offset, not {{{}}}",
+                    name
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+// What placeholders expand to for one invoice
+pub struct Context {
+    pub date: NaiveDate,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Context {
+    pub fn new(date: NaiveDate) -> Self {
+        Context {
+            date,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &str, value: &str) -> Self {
+        self.fields.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    // The recipient's email is the first address line with an @, the address the other lines
+    pub fn for_recipient(date: NaiveDate, recipient: &Recipient, due_date: &str) -> Self {
+        let email = recipient
+            .address
+            .iter()
+            .find(|line| line.contains('@'))
+            .cloned()
+            .unwrap_or_default();
+        let address: Vec<&str> = recipient
+            .address
+            .iter()
+            .filter(|line| **line != email)
+            .map(String::as_str)
+            .collect();
+
+        Context::new(date)
+            .with("recipient_name", &recipient.name)
+            .with("recipient_email", &email)
+            .with("recipient_address", &address.join(", "))
+            .with("due_date", due_date)
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%d.%m.%Y").to_string()
+}
+
+fn month_start(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+fn month_end(date: NaiveDate) -> NaiveDate {
+    month_start(date)
+        .checked_add_months(Months::new(1))
+        .and_then(|d| d.pred_opt())
+        .unwrap_or(date)
+}
+
+fn quarter_start(date: NaiveDate) -> NaiveDate {
+    let month = (date.month0() / 3) * 3 + 1;
+    NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap_or(date)
+}
+
+fn date_value(name: &str, date: NaiveDate) -> String {
+    match name {
+        "date" => date.format("%Y-%m-%d").to_string(),
+        "day" => format!("{:02}", date.day()),
+        "month" => format!("{:02}", date.month()),
+        "month_name" => MONTH_NAMES[date.month0() as usize].to_string(),
+        "year" => date.year().to_string(),
+        "quarter" => (date.month0() / 3 + 1).to_string(),
+        "month_start" => format_date(month_start(date)),
+        "month_end" => format_date(month_end(date)),
+        "month_range" => format!(
+            "{}–{}",
+            format_date(month_start(date)),
+            format_date(month_end(date))
+        ),
+        "quarter_start" => format_date(quarter_start(date)),
+        "quarter_range" => {
+            let start = quarter_start(date);
+            let end = start
+                .checked_add_months(Months::new(3))
+                .and_then(|d| d.pred_opt())
+                .unwrap_or(start);
+            format!("{}–{}", format_date(start), format_date(end))
+        }
+        _ => String::new(),
+    }
+}
+
+fn shift_months(date: NaiveDate, offset: i32) -> Result<NaiveDate, Error> {
+    let months = Months::new(offset.unsigned_abs());
+    let shifted = if offset < 0 {
+        date.checked_sub_months(months)
+    } else {
+        date.checked_add_months(months)
+    };
+    shifted.ok_or_else(|| Error::invalid(format!("Month offset {} is out of range", offset)))
+}
+
+// Expand a template, passing each value through `escape`, e.g. to make it safe in a file name
+pub fn expand_with(
+    template: &str,
+    context: &Context,
+    escape: impl Fn(&str) -> String,
+) -> Result<String, Error> {
+    let mut result = String::new();
+
+    for segment in parse(template)? {
+        match segment {
+            Segment::Text(text) => result.push_str(text),
+            Segment::Placeholder { name, offset } if DATE_PLACEHOLDERS.contains(&name) => {
+                let date = shift_months(context.date, offset)?;
+                result.push_str(&escape(&date_value(name, date)));
+            }
+            Segment::Placeholder { name, offset } => match context.fields.get(name) {
+                Some(value) if offset == 0 => result.push_str(&escape(value)),
+                _ => {
+                    let fields: Vec<&str> = context.fields.keys().map(String::as_str).collect();
+                    return Err(unknown_placeholder(name, template, &fields));
+                }
+            },
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn expand(template: &str, context: &Context) -> Result<String, Error> {
+    expand_with(template, context, str::to_string)
+}
+
+// Expand text for one recipient, to preview a description while editing it. Without a
+// recipient the first configured one is used, and the date defaults to today.
+pub fn expand_text(
+    text: String,
+    recipient: Option<String>,
+    invoice_date: Option<String>,
+) -> Result<String, Error> {
+    let config = load_config()?;
+    let date = match invoice_date.as_deref().map(str::trim) {
+        Some(value) if !value.is_empty() => terms::parse_date(value)?,
+        _ => Local::now().date_naive(),
+    };
+
+    let configured = recipients::parse_recipients(config.get("recipients"));
+    let recipient = match recipient {
+        Some(name) => Some(
+            configured
+                .iter()
+                .find(|r| r.name == name)
+                .ok_or_else(|| Error::not_found(format!("Recipient '{}' not found", name)))?,
+        ),
+        None => configured.first(),
+    };
+
+    let context = match recipient {
+        Some(recipient) => {
+            // The due date follows the payment terms, as it would on a run dated `date`
+            let terms = terms::parse_terms(config.get(terms::TERMS_KEY))?;
+            let dates = terms::resolve_dates(
+                &terms,
+                &RunOptions {
+                    invoice_date: Some(date.format("%Y-%m-%d").to_string()),
+                    ..RunOptions::default()
+                },
+                std::slice::from_ref(&recipient.name),
+                date,
+                date,
+            )?;
+            Context::for_recipient(date, recipient, &dates[0].due_date)
+        }
+        None => Context::new(date),
+    };
+
+    expand(&text, &context)
+}
@@ -0,0 +1,279 @@
+use crate::duplicates::{self, DuplicatePolicy};
+use crate::errors::{Error, ErrorCode};
+use crate::generation;
+use crate::invoices::peek_invoice_numbers;
+use crate::money::Money;
+use crate::recipients::{self, Recipient};
+use crate::renderer::RendererKind;
+use crate::run_plan;
+use crate::settings::{get_app_settings, load_config, InvoiceConfig};
+use crate::terms::{InvoiceDates, RunOptions};
+use crate::vat::VatSummaryLine;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Local;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// One recipient as they would be billed by the next run
+#[derive(Serialize)]
+pub struct PreviewLine {
+    pub recipient: String,
+    pub address: Vec<String>,
+    pub invoice_number: String,
+    pub amount: Money,
+    pub vat_rate: i32,
+    pub vat_amount: Money,
+    pub total: Money,
+    pub vat_summary: Vec<VatSummaryLine>,
+    // Description text as it will appear on the invoice, one entry per invoice line
+    pub descriptions: Vec<String>,
+    #[serde(flatten)]
+    pub dates: InvoiceDates,
+}
+
+#[derive(Serialize)]
+pub struct SplitPreview {
+    pub lines: Vec<PreviewLine>,
+    pub currency: String,
+    pub total: Money,
+    pub warnings: Vec<String>,
+    // Pass back to confirm_generation to generate exactly what was previewed
+    pub config_hash: String,
+}
+
+fn collect_warnings(
+    config: &InvoiceConfig,
+    recipients: &[Recipient],
+    amounts: &[Money],
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for (key, message) in [
+        ("sender", "Sender details are missing"),
+        ("bankdetails", "Bank details are missing"),
+        ("description", "The service description is missing"),
+    ] {
+        if config.get(key).trim().is_empty() {
+            warnings.push(format!("{}; generation will fail", message));
+        }
+    }
+
+    for (i, recipient) in recipients.iter().enumerate() {
+        if recipients[..i].iter().any(|r| r.name == recipient.name) {
+            warnings.push(format!(
+                "'{}' is listed more than once; both get the same split rule",
+                recipient.name
+            ));
+        }
+        if recipient.address.is_empty() {
+            warnings.push(format!("'{}' has no email or address", recipient.name));
+        }
+    }
+
+    for (recipient, amount) in recipients.iter().zip(amounts) {
+        if amount.minor == 0 {
+            warnings.push(format!("'{}' would be billed {}", recipient.name, amount));
+        }
+    }
+
+    warnings
+}
+
+// Recipients the run would bill the same as a recent invoice
+fn duplicate_warnings(plan: &run_plan::RunPlan) -> Result<Vec<String>, Error> {
+    let settings = get_app_settings()?;
+    let suffix = match settings.duplicate_policy {
+        DuplicatePolicy::Off => return Ok(Vec::new()),
+        DuplicatePolicy::Warn => "",
+        DuplicatePolicy::Block => "; generation will be refused unless forced",
+    };
+
+    Ok(
+        duplicates::find_duplicates(plan, settings.duplicate_window_days)?
+            .iter()
+            .map(|d| format!("{}{}", d.describe(), suffix))
+            .collect(),
+    )
+}
+
+// Hash of the configuration, the run options and the day, since undated invoices are dated
+// on the day they are generated, and of the next invoice number, so a preview is stale once
+// a run or a finalized draft has taken the numbers it showed
+pub fn run_fingerprint(config: &InvoiceConfig, options: &RunOptions) -> Result<String, Error> {
+    let options = serde_json::to_string(options)
+        .map_err(|e| Error::invalid("Failed to serialize run options").details(e))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(config.fingerprint().as_bytes());
+    hasher.update([0]);
+    hasher.update(options.as_bytes());
+    hasher.update([0]);
+    hasher.update(Local::now().date_naive().to_string().as_bytes());
+    hasher.update([0]);
+    hasher.update(peek_invoice_numbers(1)?.concat().as_bytes());
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// Show who the next run would bill and how much, without generating or reserving anything
+pub fn preview_split(options: Option<RunOptions>) -> Result<SplitPreview, Error> {
+    preview_run(&load_config()?, &options.unwrap_or_default())
+}
+
+// Preview a run of the given configuration
+pub fn preview_run(config: &InvoiceConfig, options: &RunOptions) -> Result<SplitPreview, Error> {
+    let config_hash = run_fingerprint(config, options)?;
+
+    let recipients = recipients::parse_recipients(config.get("recipients"));
+    let plan = run_plan::build_run_plan(config, options)?;
+    let currency = plan.currency;
+    let invoice_numbers = peek_invoice_numbers(plan.amounts.len())?;
+    let mut warnings = collect_warnings(config, &recipients, &plan.amounts);
+    warnings.extend(duplicate_warnings(&plan)?);
+
+    let mut lines = Vec::new();
+    for (index, (recipient, invoice_number)) in
+        recipients.into_iter().zip(invoice_numbers).enumerate()
+    {
+        let amount = plan.amounts[index];
+        let vat_summary = plan.vat.get(index).cloned().unwrap_or_default();
+        let mut vat_minor = 0;
+        for group in &vat_summary {
+            vat_minor += Money::parse(&group.vat, currency)?.minor;
+        }
+
+        let descriptions = match plan.lines.get(index) {
+            Some(items) if !items.is_empty() => {
+                items.iter().map(|l| l.description.clone()).collect()
+            }
+            _ => vec![plan.descriptions[index].clone()],
+        };
+
+        lines.push(PreviewLine {
+            recipient: recipient.name,
+            address: recipient.address,
+            invoice_number,
+            amount,
+            // The highest rate on the invoice, as stored with it
+            vat_rate: vat_summary.first().map_or(0, |g| g.rate as i32),
+            vat_amount: Money::new(vat_minor, currency),
+            total: Money::new(amount.minor + vat_minor, currency),
+            vat_summary,
+            descriptions,
+            dates: plan.dates[index].clone(),
+        });
+    }
+    let total: i64 = lines.iter().map(|l| l.total.minor).sum();
+
+    Ok(SplitPreview {
+        lines,
+        currency: currency.code.to_string(),
+        total: Money::new(total, currency),
+        warnings,
+        config_hash,
+    })
+}
+
+pub const STALE_PREVIEW: &str =
+    "The configuration or the next invoice number changed since the preview was made. Preview again before generating.";
+
+// The error generation from an outdated preview fails with
+pub fn stale_error() -> Error {
+    Error::new(ErrorCode::StalePreview, STALE_PREVIEW)
+}
+
+// Generate the invoices shown by a preview, refusing if the configuration changed since.
+// The options must be the ones the preview was made with.
+pub fn confirm_generation(
+    config_hash: String,
+    options: Option<RunOptions>,
+) -> Result<String, Error> {
+    let options = options.unwrap_or_default();
+    generation::run_generation(&load_config()?, false, &options, Some(&config_hash))
+        .map(|r| r.output)
+}
+
+// A rendered preview of one recipient's invoice
+#[derive(Serialize, Clone)]
+pub struct InvoicePreview {
+    pub recipient: String,
+    pub config_hash: String,
+    // The OCaml backend and the built-in renderer lay the same invoice out differently
+    pub renderer: RendererKind,
+    pub pdf_base64: String,
+}
+
+// Recently rendered previews, newest last, so editing back to an earlier state is instant.
+// The lock also keeps two previews from rendering into the same file at once.
+const PREVIEW_CACHE_SIZE: usize = 8;
+static PREVIEW_CACHE: Mutex<Vec<InvoicePreview>> = Mutex::new(Vec::new());
+
+fn find_cached(
+    cache: &[InvoicePreview],
+    config_hash: &str,
+    recipient: &str,
+    renderer: RendererKind,
+) -> Option<InvoicePreview> {
+    cache
+        .iter()
+        .find(|p| {
+            p.config_hash == config_hash && p.recipient == recipient && p.renderer == renderer
+        })
+        .cloned()
+}
+
+// A preview rendered earlier from the same configuration by the same renderer
+pub fn cached_preview(
+    config_hash: &str,
+    recipient: &str,
+    renderer: RendererKind,
+) -> Option<InvoicePreview> {
+    let cache = PREVIEW_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    find_cached(&cache, config_hash, recipient, renderer)
+}
+
+// Render one recipient's invoice from configuration values that need not be saved yet.
+// Nothing is stored, no invoice number is used and the output directory is left alone.
+pub fn preview_invoice(
+    values: BTreeMap<String, String>,
+    recipient: String,
+    options: Option<RunOptions>,
+) -> Result<InvoicePreview, Error> {
+    let options = options.unwrap_or_default();
+    let config = load_config()?.with_values(&values)?;
+    let config_hash = run_fingerprint(&config, &options)?;
+
+    let renderer = get_app_settings()?.renderer;
+
+    let mut cache = PREVIEW_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(preview) = find_cached(&cache, &config_hash, &recipient, renderer) {
+        return Ok(preview);
+    }
+
+    let plan = run_plan::build_run_plan(&config, &options)?;
+    let index = plan
+        .recipients
+        .iter()
+        .position(|name| *name == recipient)
+        .ok_or_else(|| Error::not_found(format!("Recipient '{}' not found", recipient)))?;
+    let pdf = generation::render_preview_pdf(&plan.into_recipient(index)?)?;
+
+    let preview = InvoicePreview {
+        recipient,
+        config_hash,
+        renderer,
+        pdf_base64: general_purpose::STANDARD.encode(pdf),
+    };
+    if cache.len() >= PREVIEW_CACHE_SIZE {
+        cache.remove(0);
+    }
+    cache.push(preview.clone());
+
+    Ok(preview)
+}
@@ -0,0 +1,39 @@
+// Rust port of the OCaml Recipients_parser, so the app can reason about recipients before generation
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Recipient {
+    pub name: String,
+    pub address: Vec<String>,
+}
+
+// Recipients are blocks separated by blank lines: the name first, then an optional email and address lines
+pub fn parse_recipients(content: &str) -> Vec<Recipient> {
+    content
+        .split('\n')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .split(|line| line.is_empty())
+        .filter_map(|block| {
+            let (name, rest) = block.split_first()?;
+            Some(Recipient {
+                name: name.to_string(),
+                address: rest.iter().map(|line| line.to_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+// The inverse of parse_recipients
+pub fn format_recipients(recipients: &[Recipient]) -> String {
+    recipients
+        .iter()
+        .map(|r| {
+            std::iter::once(r.name.as_str())
+                .chain(r.address.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
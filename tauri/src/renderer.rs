@@ -0,0 +1,373 @@
+// How a run plan becomes invoices. The OCaml backend runs as a subprocess; the native renderer
+// does the same work in-process, so the app also works where no OCaml build is available.
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::export::{self, ManifestEntry};
+use crate::generation;
+use crate::invoices::{self, NewInvoice, StoredLine};
+use crate::pdf::{self, DocumentLine, InvoiceDocument};
+use crate::recipients::{self, Recipient};
+use crate::run_plan::RunPlan;
+use crate::runs::RunLog;
+use crate::settings::{get_app_data_dir, get_app_settings, AppSettings};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// Which renderer the app uses, chosen in the settings
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RendererKind {
+    #[default]
+    Ocaml,
+    Native,
+}
+
+// What happens to the rest of a run when one recipient's invoice fails
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    // Store every invoice that succeeds
+    #[default]
+    BestEffort,
+    // Store the invoices only if all of them succeed, otherwise none
+    AllOrNothing,
+}
+
+// What a renderer produced for a run
+pub struct RenderedRun {
+    // Progress messages, shown to the user
+    pub output: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+pub trait InvoiceRenderer {
+    fn name(&self) -> &'static str;
+
+    // Produce one PDF per recipient of the plan. Unless `dry_run`, each invoice is numbered and
+    // stored in the database, as the renderer's batch mode allows; dry runs are numbered
+    // PREVIEW-1, PREVIEW-2 and so on. What the renderer printed goes to `log`, also when the
+    // run fails, as do the PDFs of invoices a failed run stored.
+    fn render_run(
+        &self,
+        plan: &RunPlan,
+        dry_run: bool,
+        log: &mut RunLog,
+    ) -> Result<RenderedRun, Error>;
+}
+
+pub struct OcamlRenderer {
+    pub batch_mode: BatchMode,
+}
+
+impl InvoiceRenderer for OcamlRenderer {
+    fn name(&self) -> &'static str {
+        "OCaml backend"
+    }
+
+    fn render_run(
+        &self,
+        plan: &RunPlan,
+        dry_run: bool,
+        log: &mut RunLog,
+    ) -> Result<RenderedRun, Error> {
+        let (output, files) = generation::run_backend(plan, dry_run, self.batch_mode, log)?;
+        Ok(RenderedRun { output, files })
+    }
+}
+
+pub struct NativeRenderer {
+    // Where the PDFs are written before they are exported
+    pub out_dir: PathBuf,
+    pub batch_mode: BatchMode,
+}
+
+pub fn non_empty_lines(value: &str) -> Vec<String> {
+    value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl NativeRenderer {
+    pub fn new(batch_mode: BatchMode) -> Result<Self, Error> {
+        Ok(NativeRenderer {
+            out_dir: get_app_data_dir()?.join("out"),
+            batch_mode,
+        })
+    }
+
+    // The invoice for the recipient at `index`, still without its number
+    fn document(
+        plan: &RunPlan,
+        index: usize,
+        recipient: &Recipient,
+    ) -> Result<InvoiceDocument, Error> {
+        let amount = *plan.amounts.get(index).ok_or_else(|| {
+            Error::invalid(format!("The run plan has no amount for {}", recipient.name))
+        })?;
+        let dates = plan.dates.get(index).ok_or_else(|| {
+            Error::invalid(format!("The run plan has no dates for {}", recipient.name))
+        })?;
+
+        let description = plan
+            .descriptions
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| plan.setting("description").to_string());
+        // Lines from the plan take precedence over the single description and amount
+        let lines = match plan.lines.get(index) {
+            Some(lines) if !lines.is_empty() => lines
+                .iter()
+                .map(|line| DocumentLine {
+                    date: line.date.clone(),
+                    description: line.description.clone(),
+                    amount: line.amount,
+                })
+                .collect(),
+            _ => vec![DocumentLine {
+                date: String::new(),
+                description: description.clone(),
+                amount,
+            }],
+        };
+
+        Ok(InvoiceDocument {
+            invoice_number: String::new(),
+            invoice_date: dates.invoice_date.clone(),
+            due_date: dates.due_date.clone(),
+            sender: non_empty_lines(plan.setting("sender")),
+            customer_name: recipient.name.clone(),
+            customer_address: recipient.address.clone(),
+            service: description,
+            lines,
+            vat_summary: plan.vat.get(index).cloned().unwrap_or_default(),
+            bank_details: non_empty_lines(plan.setting("bankdetails")),
+        })
+    }
+
+    // The file in the out directory a document's PDF is written to
+    pub fn file_for(&self, document: &InvoiceDocument) -> ManifestEntry {
+        ManifestEntry {
+            path: self
+                .out_dir
+                .join(format!("invoice-{}.pdf", document.invoice_number)),
+            invoice_number: document.invoice_number.clone(),
+            customer: document.customer_name.clone(),
+            service: document.service.clone(),
+            invoice_date: document.invoice_date.clone(),
+        }
+    }
+
+    // Render and, unless previewing, number and store one invoice; returns the file written.
+    // The caller commits what is stored.
+    fn render_one(
+        &self,
+        conn: Option<&rusqlite::Connection>,
+        mut document: InvoiceDocument,
+        preview_number: usize,
+    ) -> Result<ManifestEntry, Error> {
+        let pdf = match conn {
+            Some(conn) => store_document(conn, &mut document)?,
+            None => {
+                document.invoice_number = format!("PREVIEW-{}", preview_number);
+                pdf::render_invoice(&document)?
+            }
+        };
+
+        let entry = self.file_for(&document);
+        fs::write(&entry.path, &pdf)
+            .map_err(|e| Error::filesystem("Failed to write PDF").details(e))?;
+        Ok(entry)
+    }
+
+    // Undo an all-or-nothing run after `failed` went wrong: the PDFs written so far are removed
+    // and the transaction is dropped without being committed. Returns the run's error.
+    fn roll_back(
+        log: &mut RunLog,
+        mut output: String,
+        files: &[ManifestEntry],
+        failed: &str,
+        error: &Error,
+    ) -> Error {
+        for entry in files {
+            export::remove_run_file(&entry.path);
+        }
+        output
+            .push_str("All-or-nothing mode: the run was rolled back and no invoices were stored\n");
+        log.stdout = output;
+        // The rolled back run failed the way the invoice did
+        Error::new(
+            error.code,
+            format!(
+                "Generation failed for {}: {}. No invoices were stored, since the run is all or nothing.",
+                failed, error
+            ),
+        )
+    }
+
+    // Render and store one invoice in a transaction of its own, so the number is only used
+    // up if the invoice is stored
+    fn store_one(
+        &self,
+        conn: &rusqlite::Connection,
+        document: InvoiceDocument,
+    ) -> Result<ManifestEntry, Error> {
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+        let entry = self.render_one(Some(&*tx), document, 0)?;
+        match tx.commit() {
+            Ok(()) => Ok(entry),
+            Err(e) => {
+                export::remove_run_file(&entry.path);
+                Err(Error::database("Failed to store invoice").details(e))
+            }
+        }
+    }
+}
+
+impl InvoiceRenderer for NativeRenderer {
+    fn name(&self) -> &'static str {
+        "native renderer"
+    }
+
+    fn render_run(
+        &self,
+        plan: &RunPlan,
+        dry_run: bool,
+        log: &mut RunLog,
+    ) -> Result<RenderedRun, Error> {
+        for key in ["sender", "bankdetails", "description", "amount"] {
+            if plan.setting(key).trim().is_empty() {
+                return Err(Error::invalid(format!(
+                    "Required setting '{}' is missing. Please configure it in the application.",
+                    key
+                )));
+            }
+        }
+        let recipients = recipients::parse_recipients(plan.setting("recipients"));
+        if recipients.is_empty() {
+            return Err(Error::invalid(
+                "No recipients found. Please configure recipient information in the application.",
+            ));
+        }
+
+        fs::create_dir_all(&self.out_dir)
+            .map_err(|e| Error::filesystem("Failed to create output directory").details(e))?;
+        let conn = if dry_run {
+            None
+        } else {
+            let conn = connect_database()?;
+            invoices::create_backend_tables(&conn)?;
+            Some(conn)
+        };
+        // All or nothing stores every invoice in one transaction, committed after the last
+        let batch = match &conn {
+            Some(conn) if self.batch_mode == BatchMode::AllOrNothing => Some(
+                conn.unchecked_transaction()
+                    .map_err(|e| Error::database("Failed to start transaction").details(e))?,
+            ),
+            _ => None,
+        };
+
+        // Like the OCaml backend, one failed invoice does not stop the others in best effort
+        let mut output = format!("Processing {} recipients...\n", recipients.len());
+        let mut files = Vec::new();
+        for (index, recipient) in recipients.iter().enumerate() {
+            let result = Self::document(plan, index, recipient).and_then(|document| {
+                match (batch.as_deref(), &conn) {
+                    (Some(tx), _) => self.render_one(Some(tx), document, index + 1),
+                    (None, Some(conn)) => self.store_one(conn, document),
+                    (None, None) => self.render_one(None, document, index + 1),
+                }
+            });
+            match result {
+                Ok(entry) => {
+                    output.push_str(&format!(
+                        "✓ Generated invoice {} for {}\n",
+                        entry.invoice_number, recipient.name
+                    ));
+                    files.push(entry);
+                }
+                Err(e) => {
+                    output.push_str(&format!(
+                        "✗ Failed to generate invoice for {}: {}\n",
+                        recipient.name, e
+                    ));
+                    if batch.is_some() {
+                        return Err(Self::roll_back(log, output, &files, &recipient.name, &e));
+                    }
+                }
+            }
+        }
+        if let Some(tx) = batch {
+            if let Err(e) = tx.commit() {
+                let e = Error::database("Failed to store invoices").details(e);
+                return Err(Self::roll_back(log, output, &files, "the run", &e));
+            }
+        }
+        output.push_str(&format!(
+            "Batch processing complete: {} invoices generated successfully\n",
+            files.len()
+        ));
+
+        log.stdout = output.clone();
+        log.detail = "completed in-process".to_string();
+        Ok(RenderedRun { output, files })
+    }
+}
+
+// Give a document the next invoice number, render it and store it as an invoice; returns the
+// PDF. Nothing is kept unless the caller commits.
+pub fn store_document(
+    conn: &rusqlite::Connection,
+    document: &mut InvoiceDocument,
+) -> Result<Vec<u8>, Error> {
+    document.invoice_number = invoices::next_invoice_number(conn)?;
+    let pdf = pdf::render_invoice(document)?;
+
+    invoices::insert_invoice(
+        conn,
+        &NewInvoice {
+            invoice_number: &document.invoice_number,
+            invoice_date: &document.invoice_date,
+            due_date: &document.due_date,
+            service: &document.service,
+            vat_enabled: !document.vat_summary.is_empty(),
+            vat_rate: document
+                .vat_summary
+                .iter()
+                .map(|l| l.rate)
+                .max()
+                .unwrap_or(0),
+            lines: document
+                .lines
+                .iter()
+                .map(|line| StoredLine {
+                    date: line.date.clone(),
+                    description: line.description.clone(),
+                    price: line.amount.plain(),
+                })
+                .collect(),
+            pdf: &pdf,
+        },
+    )?;
+
+    Ok(pdf)
+}
+
+pub fn renderer_for(settings: &AppSettings) -> Result<Box<dyn InvoiceRenderer>, Error> {
+    let batch_mode = settings.batch_mode;
+    Ok(match settings.renderer {
+        RendererKind::Ocaml => Box::new(OcamlRenderer { batch_mode }),
+        RendererKind::Native => Box::new(NativeRenderer::new(batch_mode)?),
+    })
+}
+
+// The renderer chosen in the settings
+pub fn configured_renderer() -> Result<Box<dyn InvoiceRenderer>, Error> {
+    renderer_for(&get_app_settings()?)
+}
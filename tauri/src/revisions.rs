@@ -0,0 +1,637 @@
+// Stored invoices rendered again from their structured data, e.g. after a layout fix, or
+// corrected. Every re-render and correction is kept as a numbered revision with its data, PDF,
+// reason and time, and any two revisions can be compared field by field. The PDF stored with
+// the invoice is never replaced.
+use crate::batches;
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::invoices::{self, InvoiceParties, StoredLine};
+use crate::money::{Currency, Money};
+use crate::pdf::{self, DocumentLine, InvoiceDocument};
+use crate::recipients;
+use crate::renderer::{self, RendererKind};
+use crate::settings::get_app_settings;
+use crate::terms;
+use crate::vat::{self, VatCode, VatSummaryLine, VatTreatment};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Local, NaiveDate};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+// Reason recorded for the copy of the original PDF made before the first re-render
+pub const ORIGINAL_REASON: &str = "Original";
+
+// Revisions and finalized drafts are always rendered natively, as the OCaml backend renders
+// whole runs from the configuration rather than one invoice. Said with each one made while the
+// OCaml backend is the configured renderer.
+pub const NATIVE_RENDER_WARNING: &str = "Rendered by the built-in renderer, as the OCaml backend \
+     only renders whole runs; the layout may differ from the invoices it renders";
+
+// Everything an invoice is rendered from, as stored in the database
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct InvoiceData {
+    pub invoice_number: String,
+    pub invoice_date: String,
+    pub due_date: String,
+    pub parties: InvoiceParties,
+    pub lines: Vec<StoredLine>,
+    pub vat_enabled: bool,
+    pub vat_rate: u32,
+    #[serde(default)]
+    pub vat_summary: Vec<VatSummaryLine>,
+}
+
+impl InvoiceData {
+    pub fn document(&self) -> Result<InvoiceDocument, Error> {
+        let currency = Currency::from_code(&self.parties.currency)?;
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| {
+                Ok(DocumentLine {
+                    date: line.date.clone(),
+                    description: line.description.clone(),
+                    amount: Money::parse(&line.price, currency)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Invoices stored by the backend before VAT summaries were kept have one rate
+        let vat_summary = if !self.vat_enabled || !self.vat_summary.is_empty() {
+            self.vat_summary.clone()
+        } else {
+            let code = VatCode {
+                rate: self.vat_rate,
+                treatment: VatTreatment::Standard,
+            };
+            let amounts: Vec<_> = lines
+                .iter()
+                .map(|line| (code, line.amount.minor, code.vat_on(line.amount.minor)))
+                .collect();
+            vat::summarize(&amounts, currency)
+        };
+
+        Ok(InvoiceDocument {
+            invoice_number: self.invoice_number.clone(),
+            invoice_date: self.invoice_date.clone(),
+            due_date: self.due_date.clone(),
+            sender: self.parties.sender.clone(),
+            customer_name: self.parties.customer_name.clone(),
+            customer_address: self.parties.customer_address.clone(),
+            service: self
+                .lines
+                .first()
+                .map(|line| line.description.clone())
+                .unwrap_or_default(),
+            lines,
+            vat_summary,
+            bank_details: self.parties.bank_details.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct InvoiceRevision {
+    pub invoice_number: String,
+    // 1 for the original PDF, counting up with each re-render or correction
+    pub revision: u32,
+    pub reason: String,
+    pub data: InvoiceData,
+    pub created_at: String,
+    pub pdf_base64: String,
+    // About how the revision was just made; not stored, so empty when revisions are listed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// The parties of an invoice stored before they were kept, taken from the batch that stored it.
+// Only batches that stored an invoice for every recipient tell which invoice was whose.
+fn parties_from_batch(conn: &Connection, invoice_number: &str) -> Result<InvoiceParties, Error> {
+    let missing = || {
+        Error::not_found(format!(
+            "No customer details are stored for invoice {}, so it cannot be rendered again",
+            invoice_number
+        ))
+    };
+    let batch_id: i64 = match conn.query_row(
+        "SELECT id FROM batches WHERE ',' || invoice_numbers || ',' LIKE '%,' || ?1 || ',%'
+         ORDER BY id DESC LIMIT 1",
+        [invoice_number],
+        |row| row.get(0),
+    ) {
+        Ok(id) => id,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(missing()),
+        Err(e) => return Err(Error::database("Failed to find the batch of the invoice").details(e)),
+    };
+
+    let batch = batches::find_batch(batch_id)?;
+    let recipients = recipients::parse_recipients(batch.config.get("recipients"));
+    if batch.invoice_numbers.len() != recipients.len() {
+        return Err(missing());
+    }
+    let index = batch
+        .invoice_numbers
+        .iter()
+        .position(|number| number == invoice_number)
+        .ok_or_else(missing)?;
+
+    Ok(InvoiceParties {
+        currency: batch.config.currency()?.code.to_string(),
+        sender: renderer::non_empty_lines(batch.config.get("sender")),
+        customer_name: recipients[index].name.clone(),
+        customer_address: recipients[index].address.clone(),
+        bank_details: renderer::non_empty_lines(batch.config.get("bankdetails")),
+    })
+}
+
+// Rebuild the data of a stored invoice from its invoice row, line items, VAT summary and parties
+pub fn load_invoice_data(conn: &Connection, id: i32) -> Result<InvoiceData, Error> {
+    let data = conn.query_row(
+        "SELECT invoice_number, invoice_date, due_date, vat_enabled, vat_rate
+         FROM invoices WHERE id = ?",
+        [id],
+        |row| {
+            Ok(InvoiceData {
+                invoice_number: row.get(0)?,
+                invoice_date: row.get(1)?,
+                due_date: row.get(2)?,
+                vat_enabled: row.get(3)?,
+                vat_rate: row.get(4)?,
+                ..InvoiceData::default()
+            })
+        },
+    );
+    let data = match data {
+        Ok(data) => data,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(Error::not_found(format!("Invoice {} not found", id)))
+        }
+        Err(e) => return Err(Error::database("Failed to get invoice").details(e)),
+    };
+
+    let parties = match invoices::load_invoice_parties(conn, &data.invoice_number)? {
+        Some(parties) => parties,
+        None => parties_from_batch(conn, &data.invoice_number)?,
+    };
+
+    Ok(InvoiceData {
+        lines: invoices::load_line_items(conn, id as i64)?,
+        vat_summary: invoices::load_vat_summary(conn, &data.invoice_number)?,
+        parties,
+        ..data
+    })
+}
+
+fn insert_revision(
+    conn: &Connection,
+    data: &InvoiceData,
+    reason: &str,
+    pdf: &[u8],
+) -> Result<u32, Error> {
+    let revision: u32 = conn
+        .query_row(
+            "SELECT COALESCE(MAX(revision), 0) + 1 FROM invoice_revisions WHERE invoice_number = ?",
+            [&data.invoice_number],
+            |row| row.get(0),
+        )
+        .map_err(|e| Error::database("Failed to number revision").details(e))?;
+    let encoded = serde_json::to_string(data)
+        .map_err(|e| Error::invalid("Failed to serialize revision").details(e))?;
+
+    conn.execute(
+        "INSERT INTO invoice_revisions (invoice_number, revision, reason, data, pdf_content)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![data.invoice_number, revision, reason, encoded, pdf],
+    )
+    .map_err(|e| Error::database("Failed to store revision").details(e))?;
+
+    Ok(revision)
+}
+
+// The revisions of one invoice, oldest first
+fn query_revisions(
+    conn: &Connection,
+    invoice_number: &str,
+    revision: Option<u32>,
+) -> Result<Vec<InvoiceRevision>, Error> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT revision, reason, data, created_at, pdf_content FROM invoice_revisions
+             WHERE invoice_number = ?1 AND (?2 IS NULL OR revision = ?2) ORDER BY revision",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map(rusqlite::params![invoice_number, revision], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })
+        .map_err(|e| Error::database("Failed to get revisions").details(e))?;
+
+    rows.map(|row| {
+        let (revision, reason, data, created_at, pdf) =
+            row.map_err(|e| Error::database("Failed to read revision").details(e))?;
+        Ok(InvoiceRevision {
+            invoice_number: invoice_number.to_string(),
+            revision,
+            reason,
+            data: serde_json::from_str(&data).map_err(|e| {
+                Error::database(format!("Invalid revision {}", revision)).details(e)
+            })?,
+            created_at,
+            pdf_base64: general_purpose::STANDARD.encode(pdf),
+            warnings: Vec::new(),
+        })
+    })
+    .collect()
+}
+
+fn invoice_number(conn: &Connection, id: i32) -> Result<String, Error> {
+    match conn.query_row(
+        "SELECT invoice_number FROM invoices WHERE id = ?",
+        [id],
+        |row| row.get(0),
+    ) {
+        Ok(number) => Ok(number),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            Err(Error::not_found(format!("Invoice {} not found", id)))
+        }
+        Err(e) => Err(Error::database("Failed to get invoice").details(e)),
+    }
+}
+
+// The revisions of an invoice, oldest first; empty until it is first rendered again
+pub fn list_invoice_revisions(id: i32) -> Result<Vec<InvoiceRevision>, Error> {
+    let conn = connect_database()?;
+    query_revisions(&conn, &invoice_number(&conn, id)?, None)
+}
+
+// The data of the latest revision of an invoice, if it has any
+fn latest_revision_data(
+    conn: &Connection,
+    invoice_number: &str,
+) -> Result<Option<InvoiceData>, Error> {
+    let data: Option<(u32, String)> = conn
+        .query_row(
+            "SELECT revision, data FROM invoice_revisions WHERE invoice_number = ?
+             ORDER BY revision DESC LIMIT 1",
+            [invoice_number],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| Error::database("Failed to get revisions").details(e))?;
+    data.map(|(revision, data)| {
+        serde_json::from_str(&data)
+            .map_err(|e| Error::database(format!("Invalid revision {}", revision)).details(e))
+    })
+    .transpose()
+}
+
+// The data of the latest revision, or the stored data if the invoice has no revisions yet
+pub fn current_invoice_data(conn: &Connection, id: i32) -> Result<InvoiceData, Error> {
+    let stored = load_invoice_data(conn, id)?;
+    Ok(latest_revision_data(conn, &stored.invoice_number)?.unwrap_or(stored))
+}
+
+// Render `data` with the native renderer and keep it as the next revision of the invoice.
+// Before the first revision the original PDF is kept as revision 1, with the data stored for the invoice.
+fn add_revision(
+    conn: &mut Connection,
+    id: i32,
+    data: &InvoiceData,
+    reason: &str,
+) -> Result<InvoiceRevision, Error> {
+    let pdf = pdf::render_invoice(&data.document()?)?;
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    if latest_revision_data(&tx, &data.invoice_number)?.is_none() {
+        let original: Vec<u8> = tx
+            .query_row(
+                "SELECT pdf_content FROM invoices WHERE id = ?",
+                [id],
+                |row| row.get(0),
+            )
+            .map_err(|e| Error::database("Failed to get invoice").details(e))?;
+        insert_revision(
+            &tx,
+            &load_invoice_data(&tx, id)?,
+            ORIGINAL_REASON,
+            &original,
+        )?;
+    }
+    let revision = insert_revision(&tx, data, reason.trim(), &pdf)?;
+    tx.commit()
+        .map_err(|e| Error::database("Failed to store revision").details(e))?;
+
+    let mut revision = find_revision(conn, &data.invoice_number, revision)?;
+    if get_app_settings()?.renderer != RendererKind::Native {
+        revision.warnings.push(NATIVE_RENDER_WARNING.to_string());
+    }
+    Ok(revision)
+}
+
+fn find_revision(
+    conn: &Connection,
+    invoice_number: &str,
+    revision: u32,
+) -> Result<InvoiceRevision, Error> {
+    query_revisions(conn, invoice_number, Some(revision))?
+        .pop()
+        .ok_or_else(|| {
+            Error::not_found(format!(
+                "Revision {} of invoice {} not found",
+                revision, invoice_number
+            ))
+        })
+}
+
+// Render an invoice again with the current layout, from its latest data, as a new revision
+pub fn regenerate_invoice(id: i32, reason: String) -> Result<InvoiceRevision, Error> {
+    let mut conn = connect_database()?;
+    let data = current_invoice_data(&conn, id)?;
+    add_revision(&mut conn, id, &data, &reason)
+}
+
+// Correct an invoice's data, e.g. a wrong address or amount, and render it as a new revision.
+// The invoice keeps its number; the stored invoice and earlier revisions are not changed.
+pub fn correct_invoice(
+    id: i32,
+    data: InvoiceData,
+    reason: String,
+) -> Result<InvoiceRevision, Error> {
+    if reason.trim().is_empty() {
+        return Err(Error::invalid("A correction needs a reason"));
+    }
+
+    let mut conn = connect_database()?;
+    check_correction(&data, &current_invoice_data(&conn, id)?)?;
+    let data = InvoiceData {
+        invoice_number: invoice_number(&conn, id)?,
+        vat_summary: corrected_vat_summary(&data)?,
+        ..data
+    };
+    add_revision(&mut conn, id, &data, &reason)
+}
+
+// Stored dates may carry a time, e.g. "2025-01-31T12:00:00"
+fn stored_date(value: &str) -> Result<NaiveDate, Error> {
+    terms::parse_date(value.split('T').next().unwrap_or(value))
+}
+
+// A correction is checked as a run is: it needs a customer, and its due date may not be before
+// its invoice date. A changed invoice date must lie in the open period and not in the future;
+// the date it was issued with is kept even once that period has closed.
+fn check_correction(data: &InvoiceData, current: &InvoiceData) -> Result<(), Error> {
+    if data.parties.customer_name.trim().is_empty() {
+        return Err(Error::invalid("A correction needs a customer"));
+    }
+
+    let invoice_date = stored_date(&data.invoice_date)?;
+    if invoice_date != stored_date(&current.invoice_date)? {
+        terms::check_invoice_date(
+            invoice_date,
+            Local::now().date_naive(),
+            terms::current_open_period_start()?,
+        )?;
+    }
+    let due_date = stored_date(&data.due_date)?;
+    if due_date < invoice_date {
+        return Err(Error::invalid(format!(
+            "Due date {} is before the invoice date {}",
+            due_date, invoice_date
+        )));
+    }
+    Ok(())
+}
+
+// The VAT summary for corrected lines. Stored lines do not say which VAT rate each had, so an
+// invoice with one rate has its summary worked out again from the lines, and one with several
+// rates keeps the summary given only if it still adds up to the lines.
+fn corrected_vat_summary(data: &InvoiceData) -> Result<Vec<VatSummaryLine>, Error> {
+    if !data.vat_enabled {
+        return Ok(Vec::new());
+    }
+    let currency = Currency::from_code(&data.parties.currency)?;
+    let amounts = data
+        .lines
+        .iter()
+        .map(|line| Ok(Money::parse(&line.price, currency)?.minor))
+        .collect::<Result<Vec<i64>, Error>>()?;
+
+    let mut codes: Vec<VatCode> = data
+        .vat_summary
+        .iter()
+        .map(|line| VatCode {
+            rate: line.rate,
+            treatment: line.treatment,
+        })
+        .collect();
+    codes.sort();
+    codes.dedup();
+    if codes.is_empty() {
+        codes.push(VatCode {
+            rate: data.vat_rate,
+            treatment: VatTreatment::Standard,
+        });
+    }
+    if let [code] = codes[..] {
+        let by_code: Vec<_> = amounts
+            .iter()
+            .map(|amount| (code, *amount, code.vat_on(*amount)))
+            .collect();
+        return Ok(vat::summarize(&by_code, currency));
+    }
+
+    let mut net_total = 0;
+    for line in &data.vat_summary {
+        let code = VatCode {
+            rate: line.rate,
+            treatment: line.treatment,
+        };
+        let net = Money::parse(&line.net, currency)?.minor;
+        let vat = Money::parse(&line.vat, currency)?.minor;
+        // VAT is rounded line by line, so it may be off the VAT on the net by half a minor
+        // unit for each line
+        if (vat - code.vat_on(net)).abs() * 2 > amounts.len() as i64 + 1 {
+            return Err(Error::invalid(format!(
+                "The VAT at {}% ({}) does not match its net amount ({})",
+                line.rate,
+                Money::new(vat, currency).display(),
+                Money::new(net, currency).display()
+            )));
+        }
+        net_total += net;
+    }
+    let lines_total: i64 = amounts.iter().sum();
+    if net_total != lines_total {
+        return Err(Error::invalid(format!(
+            "The VAT summary adds up to {}, but the invoice lines to {}",
+            Money::new(net_total, currency).display(),
+            Money::new(lines_total, currency).display()
+        )));
+    }
+    Ok(data.vat_summary.clone())
+}
+
+// One field that differs between two revisions
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    // e.g. "due_date", "customer_address" or "line 2 price"
+    pub field: String,
+    // Empty for fields that were added, or removed
+    pub before: String,
+    pub after: String,
+}
+
+fn compare(changes: &mut Vec<FieldChange>, field: &str, before: String, after: String) {
+    if before != after {
+        changes.push(FieldChange {
+            field: field.to_string(),
+            before,
+            after,
+        });
+    }
+}
+
+fn describe_line(line: &StoredLine) -> String {
+    if line.date.is_empty() {
+        format!("{}: {}", line.description, line.price)
+    } else {
+        format!("{} {}: {}", line.date, line.description, line.price)
+    }
+}
+
+fn describe_vat(summary: &[VatSummaryLine]) -> String {
+    summary
+        .iter()
+        .map(|line| {
+            format!(
+                "{}% {}: {} + {}",
+                line.rate,
+                line.treatment.code(),
+                line.net,
+                line.vat
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// The amount due, if the data can be rendered
+fn describe_total(data: &InvoiceData) -> String {
+    let (Ok(document), Ok(currency)) =
+        (data.document(), Currency::from_code(&data.parties.currency))
+    else {
+        return String::new();
+    };
+    let net: i64 = document.lines.iter().map(|line| line.amount.minor).sum();
+    let vat: i64 = document
+        .vat_summary
+        .iter()
+        .filter_map(|line| Money::parse(&line.vat, currency).ok())
+        .map(|vat| vat.minor)
+        .sum();
+    Money::new(net + vat, currency).display()
+}
+
+// What changed from one version of an invoice's data to another, field by field. Lines are
+// compared by position; multi-line values such as addresses are compared as a whole.
+pub fn diff_invoice_data(before: &InvoiceData, after: &InvoiceData) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    for (field, b, a) in [
+        ("invoice_date", &before.invoice_date, &after.invoice_date),
+        ("due_date", &before.due_date, &after.due_date),
+        (
+            "currency",
+            &before.parties.currency,
+            &after.parties.currency,
+        ),
+        (
+            "customer_name",
+            &before.parties.customer_name,
+            &after.parties.customer_name,
+        ),
+    ] {
+        compare(&mut changes, field, b.clone(), a.clone());
+    }
+    for (field, b, a) in [
+        (
+            "customer_address",
+            &before.parties.customer_address,
+            &after.parties.customer_address,
+        ),
+        ("sender", &before.parties.sender, &after.parties.sender),
+        (
+            "bank_details",
+            &before.parties.bank_details,
+            &after.parties.bank_details,
+        ),
+    ] {
+        compare(&mut changes, field, b.join("\n"), a.join("\n"));
+    }
+
+    for index in 0..before.lines.len().max(after.lines.len()) {
+        let number = index + 1;
+        match (before.lines.get(index), after.lines.get(index)) {
+            (Some(b), Some(a)) => {
+                for (name, b, a) in [
+                    ("date", &b.date, &a.date),
+                    ("description", &b.description, &a.description),
+                    ("price", &b.price, &a.price),
+                ] {
+                    let field = format!("line {} {}", number, name);
+                    compare(&mut changes, &field, b.clone(), a.clone());
+                }
+            }
+            (b, a) => compare(
+                &mut changes,
+                &format!("line {}", number),
+                b.map(describe_line).unwrap_or_default(),
+                a.map(describe_line).unwrap_or_default(),
+            ),
+        }
+    }
+
+    compare(
+        &mut changes,
+        "vat_enabled",
+        before.vat_enabled.to_string(),
+        after.vat_enabled.to_string(),
+    );
+    compare(
+        &mut changes,
+        "vat_rate",
+        before.vat_rate.to_string(),
+        after.vat_rate.to_string(),
+    );
+    compare(
+        &mut changes,
+        "vat_summary",
+        describe_vat(&before.vat_summary),
+        describe_vat(&after.vat_summary),
+    );
+    compare(
+        &mut changes,
+        "total",
+        describe_total(before),
+        describe_total(after),
+    );
+
+    changes
+}
+
+// What changed between two revisions of an invoice, in either order
+pub fn diff_invoice_revisions(id: i32, from: u32, to: u32) -> Result<Vec<FieldChange>, Error> {
+    let conn = connect_database()?;
+    let number = invoice_number(&conn, id)?;
+    let before = find_revision(&conn, &number, from)?;
+    let after = find_revision(&conn, &number, to)?;
+    Ok(diff_invoice_data(&before.data, &after.data))
+}
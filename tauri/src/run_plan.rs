@@ -0,0 +1,282 @@
+// Per-run data handed to the OCaml backend with -plan, read by ocaml-backend/src/run_plan.ml
+use crate::errors::Error;
+use crate::line_items;
+use crate::money::{Currency, Money};
+use crate::placeholders::{self, Context};
+use crate::recipients;
+use crate::settings::InvoiceConfig;
+use crate::split;
+use crate::terms::{self, InvoiceDates, RunOptions};
+use crate::vat::{self, VatCode, VatSummaryLine};
+use std::fs;
+use std::path::Path;
+
+// One invoice line for one recipient, already reduced to their share
+pub struct PlanLine {
+    pub date: String,
+    pub description: String,
+    pub amount: Money,
+    pub vat_code: VatCode,
+}
+
+// Settings the backend reads itself; the plan carries them so a run can use other values
+// than the stored ones
+pub const BACKEND_KEYS: [&str; 5] = [
+    "sender",
+    "bankdetails",
+    "description",
+    "amount",
+    "recipients",
+];
+
+pub struct RunPlan {
+    // Values for BACKEND_KEYS
+    pub settings: Vec<(String, String)>,
+    pub currency: Currency,
+    // Recipient names, in recipient order
+    pub recipients: Vec<String>,
+    // Net amount for each recipient, in recipient order
+    pub amounts: Vec<Money>,
+    // The description for each recipient, with placeholders expanded
+    pub descriptions: Vec<String>,
+    // Invoice lines for each recipient; empty when the single description and amount are used
+    pub lines: Vec<Vec<PlanLine>>,
+    // VAT summary for each recipient's invoice; empty when the sender is not VAT registered
+    pub vat: Vec<Vec<VatSummaryLine>>,
+    // Invoice and due date for each recipient
+    pub dates: Vec<InvoiceDates>,
+}
+
+// Fields are tab separated, so tabs, newlines and backslashes inside them are escaped
+fn escape_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\r', "")
+        .replace('\n', "\\n")
+}
+
+fn keep_nth<T>(values: Vec<T>, index: usize) -> Vec<T> {
+    values.into_iter().skip(index).take(1).collect()
+}
+
+impl RunPlan {
+    // This run's value for one of BACKEND_KEYS
+    pub fn setting(&self, key: &str) -> &str {
+        self.settings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or("", |(_, value)| value.as_str())
+    }
+
+    // One tab-separated record per line, the first field naming the record
+    pub fn to_file_content(&self) -> String {
+        let currency = self.currency;
+        let mut content = format!(
+            "currency\t{}\t{}\t{}\n",
+            currency.code, currency.name, currency.symbol
+        );
+
+        for (key, value) in &self.settings {
+            content.push_str(&format!("setting\t{}\t{}\n", key, escape_field(value)));
+        }
+
+        for (index, amount) in self.amounts.iter().enumerate() {
+            content.push_str(&format!("amount\t{}\t{}\n", index, amount.plain()));
+        }
+
+        for (index, description) in self.descriptions.iter().enumerate() {
+            content.push_str(&format!(
+                "description\t{}\t{}\n",
+                index,
+                escape_field(description)
+            ));
+        }
+
+        for (index, lines) in self.lines.iter().enumerate() {
+            for line in lines {
+                content.push_str(&format!(
+                    "line\t{}\t{}\t{}\t{}\n",
+                    index,
+                    escape_field(&line.date),
+                    escape_field(&line.description),
+                    line.amount.plain()
+                ));
+            }
+        }
+
+        for (index, dates) in self.dates.iter().enumerate() {
+            content.push_str(&format!(
+                "dates\t{}\t{}\t{}\t{}\n",
+                index, dates.invoice_date, dates.due_date, dates.payment_days
+            ));
+        }
+
+        for (index, summary) in self.vat.iter().enumerate() {
+            for group in summary {
+                content.push_str(&format!(
+                    "vat\t{}\t{}\t{}\t{}\t{}\n",
+                    index,
+                    group.rate,
+                    group.treatment.code(),
+                    group.net,
+                    group.vat
+                ));
+            }
+        }
+
+        content
+    }
+
+    // The plan narrowed to one recipient, who keeps the share they have in the whole run
+    pub fn into_recipient(self, index: usize) -> Result<RunPlan, Error> {
+        let name = self
+            .recipients
+            .get(index)
+            .cloned()
+            .ok_or_else(|| Error::invalid(format!("No recipient at position {}", index + 1)))?;
+
+        let settings = self
+            .settings
+            .into_iter()
+            .map(|(key, value)| {
+                if key != "recipients" {
+                    return (key, value);
+                }
+                let recipient = recipients::parse_recipients(&value)
+                    .into_iter()
+                    .nth(index)
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                (key, recipients::format_recipients(&recipient))
+            })
+            .collect();
+
+        Ok(RunPlan {
+            settings,
+            currency: self.currency,
+            recipients: vec![name],
+            amounts: keep_nth(self.amounts, index),
+            descriptions: keep_nth(self.descriptions, index),
+            lines: keep_nth(self.lines, index),
+            vat: keep_nth(self.vat, index),
+            dates: keep_nth(self.dates, index),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_file_content())
+            .map_err(|e| Error::filesystem("Failed to write run plan").details(e))
+    }
+}
+
+// Spread one recipient's share across the invoice lines in proportion to the line amounts
+fn recipient_lines(
+    totals: &line_items::LineTotals,
+    share: Money,
+    context: &Context,
+) -> Result<Vec<PlanLine>, Error> {
+    let weights: Vec<i64> = totals.lines.iter().map(|l| l.net.minor).collect();
+
+    totals
+        .lines
+        .iter()
+        .zip(split::distribute(share.minor, &weights))
+        .map(|(line, amount)| {
+            let mut line = line.clone();
+            line.item.description = placeholders::expand(&line.item.description, context)?;
+            Ok(PlanLine {
+                date: line.item.date.trim().to_string(),
+                description: line_items::describe(&line),
+                amount: Money::new(amount, share.currency),
+                vat_code: line.vat_code,
+            })
+        })
+        .collect()
+}
+
+// VAT for each rate on one recipient's invoice, computed from their own amounts
+fn recipient_vat(amounts: &[(VatCode, Money)], currency: Currency) -> Vec<VatSummaryLine> {
+    let by_code: Vec<(VatCode, i64, i64)> = amounts
+        .iter()
+        .map(|(code, amount)| (*code, amount.minor, code.vat_on(amount.minor)))
+        .collect();
+    vat::summarize(&by_code, currency)
+}
+
+// Build the plan for a configuration and this run's options
+pub fn build_run_plan(config: &InvoiceConfig, options: &RunOptions) -> Result<RunPlan, Error> {
+    let currency = config.currency()?;
+    let vat_settings = config.vat()?;
+    let allocations = split::split_for(config)?;
+    let recipients: Vec<String> = allocations.iter().map(|a| a.recipient.clone()).collect();
+    let terms = terms::parse_terms(config.get(terms::TERMS_KEY))?;
+    let dates = terms::run_dates(&terms, options, &recipients)?;
+    let amounts: Vec<Money> = allocations.iter().map(|a| a.amount).collect();
+
+    // Placeholders are expanded for each recipient on the day their invoice bills for
+    let contexts = recipients::parse_recipients(config.get("recipients"))
+        .iter()
+        .zip(&dates)
+        .map(|(recipient, dates)| {
+            Ok(Context::for_recipient(
+                terms::parse_date(&dates.period_date)?,
+                recipient,
+                &dates.due_date,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let descriptions = contexts
+        .iter()
+        .map(|context| placeholders::expand(config.get("description"), context))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let items = line_items::decode_line_items(config.get(line_items::LINE_ITEMS_KEY))?;
+    let lines: Vec<Vec<PlanLine>> = if items.is_empty() {
+        Vec::new()
+    } else {
+        let totals = line_items::calculate_totals(&items, currency, &vat_settings)?;
+        amounts
+            .iter()
+            .zip(&contexts)
+            .map(|(share, context)| recipient_lines(&totals, *share, context))
+            .collect::<Result<_, Error>>()?
+    };
+
+    let vat = if !vat_settings.enabled {
+        Vec::new()
+    } else if lines.is_empty() {
+        let code = vat_settings.code_for(None, None)?;
+        amounts
+            .iter()
+            .map(|amount| recipient_vat(&[(code, *amount)], currency))
+            .collect()
+    } else {
+        lines
+            .iter()
+            .map(|recipient_lines| {
+                let amounts: Vec<(VatCode, Money)> = recipient_lines
+                    .iter()
+                    .map(|line| (line.vat_code, line.amount))
+                    .collect();
+                recipient_vat(&amounts, currency)
+            })
+            .collect()
+    };
+
+    let settings = BACKEND_KEYS
+        .iter()
+        .map(|key| (key.to_string(), config.get(key).to_string()))
+        .collect();
+
+    Ok(RunPlan {
+        settings,
+        currency,
+        recipients,
+        amounts,
+        descriptions,
+        lines,
+        vat,
+        dates,
+    })
+}
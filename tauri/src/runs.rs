@@ -0,0 +1,292 @@
+// Guarding and recording generation runs: one run at a time per database, a time limit on
+// the backend process, and a log of what every run printed
+use crate::database::{connect_database, get_database_path};
+use crate::errors::{Error, ErrorCode};
+use crate::export::ManifestEntry;
+use chrono::Utc;
+use fs2::FileExt;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const RUN_IN_PROGRESS: &str =
+    "Another invoice generation is already running. Wait for it to finish and try again.";
+
+// Older entries are removed as new runs are logged
+pub const RUN_LOG_KEEP: i64 = 200;
+
+// Held for the whole of a run. The OS releases the lock when the file is closed, so a run
+// that crashed cannot leave the database locked.
+pub struct RunLock {
+    _file: File,
+}
+
+// The lock file sits next to the database, so runs against other databases do not wait
+pub fn lock_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(".lock");
+    db_path.with_file_name(name)
+}
+
+impl RunLock {
+    // Fails at once rather than waiting when another run holds the lock
+    pub fn acquire(db_path: &Path) -> Result<RunLock, Error> {
+        let path = lock_path(db_path);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| {
+                Error::filesystem(format!("Failed to open run lock {}", path.display())).details(e)
+            })?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(RunLock { _file: file }),
+            Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                Err(Error::new(ErrorCode::RunInProgress, RUN_IN_PROGRESS))
+            }
+            Err(e) => {
+                Err(Error::filesystem(format!("Failed to lock {}", path.display())).details(e))
+            }
+        }
+    }
+}
+
+pub fn lock_database() -> Result<RunLock, Error> {
+    RunLock::acquire(&get_database_path()?)
+}
+
+// What a process printed, and how it ended
+pub struct ProcessOutput {
+    // None when the process was stopped for running too long
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// Output read so far from one pipe, and the thread reading it
+struct PipeReader {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl PipeReader {
+    fn start<R: Read + Send + 'static>(pipe: Option<R>) -> Self {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&bytes);
+        let thread = thread::spawn(move || {
+            let Some(mut pipe) = pipe else { return };
+            let mut buffer = [0; 8192];
+            while let Ok(n @ 1..) = pipe.read(&mut buffer) {
+                sink.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .extend_from_slice(&buffer[..n]);
+            }
+        });
+        PipeReader { bytes, thread }
+    }
+
+    // Everything read; waits for the pipe to close only if `wait` is set, since a killed
+    // process may have left children holding it open
+    fn text(self, wait: bool) -> String {
+        if wait {
+            let _ = self.thread.join();
+        }
+        let bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+        String::from_utf8_lossy(&bytes).to_string()
+    }
+}
+
+// Run a command to completion, killing it once `timeout` has passed
+pub fn output_with_timeout(
+    cmd: &mut Command,
+    timeout: Option<Duration>,
+) -> Result<ProcessOutput, Error> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::backend("Failed to execute invoice generation").details(e))?;
+
+    // Both pipes are drained while waiting, so a chatty process cannot block on a full pipe
+    let stdout = PipeReader::start(child.stdout.take());
+    let stderr = PipeReader::start(child.stderr.take());
+
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => {
+                let _ = child.kill();
+                return Err(Error::backend("Failed to wait for invoice generation").details(e));
+            }
+        }
+    };
+
+    let finished = status.is_some();
+    Ok(ProcessOutput {
+        status,
+        stdout: stdout.text(finished),
+        stderr: stderr.text(finished),
+    })
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        2 => " (SIGINT)",
+        6 => " (SIGABRT)",
+        9 => " (SIGKILL, possibly out of memory)",
+        11 => " (SIGSEGV)",
+        15 => " (SIGTERM)",
+        _ => "",
+    }
+}
+
+// "exited with code 2", "was killed by signal 11 (SIGSEGV)"
+pub fn describe_exit(status: &ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exited with code {}", code);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("was killed by signal {}{}", signal, signal_name(signal));
+        }
+    }
+
+    "ended without an exit code".to_string()
+}
+
+// The error of a backend that failed or was stopped after `timeout_secs` seconds
+pub fn process_error(output: &ProcessOutput, timeout_secs: u64) -> Error {
+    match &output.status {
+        Some(status) => {
+            let error = Error::backend(format!("The backend {}", describe_exit(status)));
+            match output.stderr.trim() {
+                "" => error,
+                stderr => error.details(stderr),
+            }
+        }
+        None => Error::new(
+            ErrorCode::BackendTimeout,
+            format!(
+                "The backend did not finish within {} seconds and was stopped. Invoices it stored before then are kept; raise the time limit in Settings if large runs need longer.",
+                timeout_secs
+            ),
+        ),
+    }
+}
+
+// What one generation run did, kept whether it succeeded or not
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RunLog {
+    // Assigned when the log is stored
+    pub id: i64,
+    pub started_at: String,
+    pub finished_at: String,
+    pub renderer: String,
+    pub dry_run: bool,
+    pub succeeded: bool,
+    // How the run ended: the backend's exit, or the error that stopped it
+    pub detail: String,
+    pub stdout: String,
+    pub stderr: String,
+    // PDFs of the invoices a failed run stored before it stopped, still to be exported
+    #[serde(skip)]
+    pub stored: Vec<ManifestEntry>,
+}
+
+// Timestamps in the form SQLite's CURRENT_TIMESTAMP uses
+pub fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub fn store_run_log(log: &RunLog) -> Result<i64, Error> {
+    let conn = connect_database()?;
+
+    conn.execute(
+        "INSERT INTO run_logs (started_at, finished_at, renderer, dry_run, succeeded, detail,
+         stdout, stderr) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![
+            log.started_at,
+            log.finished_at,
+            log.renderer,
+            log.dry_run,
+            log.succeeded,
+            log.detail,
+            log.stdout,
+            log.stderr
+        ],
+    )
+    .map_err(|e| Error::database("Failed to store run log").details(e))?;
+    let id = conn.last_insert_rowid();
+
+    // Logs of batches are kept as long as the batch
+    conn.execute(
+        "DELETE FROM run_logs WHERE id <= ? AND id NOT IN (SELECT run_log_id FROM batches)",
+        [id - RUN_LOG_KEEP],
+    )
+    .map_err(|e| Error::database("Failed to prune run log").details(e))?;
+
+    Ok(id)
+}
+
+// The columns read by `run_log_from_row`, in order
+pub const RUN_LOG_COLUMNS: &str =
+    "run_logs.id, run_logs.started_at, run_logs.finished_at, run_logs.renderer, run_logs.dry_run,
+     run_logs.succeeded, run_logs.detail, run_logs.stdout, run_logs.stderr";
+
+// A run log from RUN_LOG_COLUMNS, starting at column `first`
+pub fn run_log_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<RunLog> {
+    Ok(RunLog {
+        id: row.get(first)?,
+        started_at: row.get(first + 1)?,
+        finished_at: row.get(first + 2)?,
+        renderer: row.get(first + 3)?,
+        dry_run: row.get(first + 4)?,
+        succeeded: row.get(first + 5)?,
+        detail: row.get(first + 6)?,
+        stdout: row.get(first + 7)?,
+        stderr: row.get(first + 8)?,
+        stored: Vec::new(),
+    })
+}
+
+// The most recent runs, newest first
+pub fn list_run_logs(limit: Option<u32>) -> Result<Vec<RunLog>, Error> {
+    let conn = connect_database()?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM run_logs ORDER BY id DESC LIMIT ?",
+            RUN_LOG_COLUMNS
+        ))
+        .map_err(|e| Error::database("Failed to prepare statement").details(e))?;
+
+    let logs = stmt
+        .query_map([limit.unwrap_or(20)], |row| run_log_from_row(row, 0))
+        .map_err(|e| Error::database("Failed to query run log").details(e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::database("Failed to read run log").details(e))?;
+
+    Ok(logs)
+}
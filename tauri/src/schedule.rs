@@ -0,0 +1,501 @@
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::generation;
+use crate::invoices;
+use crate::preview::{self, SplitPreview};
+use crate::recipients;
+use crate::runs::{self, RunLock};
+use crate::settings::{load_config, InvoiceConfig};
+use crate::templates;
+use crate::terms::{self, parse_date, RunOptions};
+use chrono::{Datelike, Local, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cadence {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Cadence {
+    fn months(&self) -> u32 {
+        match self {
+            Cadence::Monthly => 1,
+            Cadence::Quarterly => 3,
+            Cadence::Yearly => 12,
+        }
+    }
+}
+
+// A recurring run. Runs fall on `day_of_month` every period, counted from the month of
+// the start date, and the invoices are dated on the day of the run, or today for a run that
+// is no longer in the open period.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Schedule {
+    // Assigned when the schedule is first saved
+    #[serde(default)]
+    pub id: i64,
+    pub name: String,
+    // Template whose values are used instead of the stored ones; empty for none
+    #[serde(default)]
+    pub template: String,
+    // Configuration values used instead of the stored ones and the template's, by setting key
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+    // Names of the recipients to bill; empty for everyone in the configuration
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    pub cadence: Cadence,
+    // Later than the last day of a month means the last day
+    pub day_of_month: u32,
+    pub start_date: String,
+    // Last day a run may fall on; empty for no end
+    #[serde(default)]
+    pub end_date: String,
+}
+
+// A run that is due and has not been generated or skipped
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DueRun {
+    pub schedule_id: i64,
+    pub name: String,
+    pub run_date: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    // Claimed by a generation that has not finished
+    Running,
+    Generated,
+    Skipped,
+    // Generation failed after some invoices were stored; not offered again
+    Failed,
+}
+
+impl RunStatus {
+    fn code(&self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Generated => "generated",
+            RunStatus::Skipped => "skipped",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    fn from_code(code: &str) -> Result<RunStatus, Error> {
+        [
+            RunStatus::Running,
+            RunStatus::Generated,
+            RunStatus::Skipped,
+            RunStatus::Failed,
+        ]
+        .into_iter()
+        .find(|s| s.code() == code)
+        .ok_or_else(|| Error::database(format!("Unknown run status '{}'", code)))
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ScheduleRun {
+    pub schedule_id: i64,
+    pub run_date: String,
+    pub status: RunStatus,
+    pub invoice_numbers: Vec<String>,
+    pub created_at: String,
+}
+
+// The given day in the month starting at `first`, or the month's last day if it is shorter
+fn day_in_month(first: NaiveDate, day: u32) -> NaiveDate {
+    let last = first
+        .checked_add_months(Months::new(1))
+        .and_then(|d| d.pred_opt())
+        .unwrap_or(first);
+    first.with_day(day.min(last.day())).unwrap_or(last)
+}
+
+// Run dates of a schedule up to and including `until`
+pub fn occurrences(schedule: &Schedule, until: NaiveDate) -> Result<Vec<NaiveDate>, Error> {
+    let start = parse_date(&schedule.start_date)?;
+    let end = match schedule.end_date.trim() {
+        "" => until,
+        value => parse_date(value)?.min(until),
+    };
+
+    let first_month = start.with_day(1).unwrap_or(start);
+    let mut dates = Vec::new();
+    for period in 0.. {
+        let Some(month) =
+            first_month.checked_add_months(Months::new(period * schedule.cadence.months()))
+        else {
+            break;
+        };
+        if month > end {
+            break;
+        }
+        let date = day_in_month(month, schedule.day_of_month);
+        if date >= start && date <= end {
+            dates.push(date);
+        }
+    }
+
+    Ok(dates)
+}
+
+// The stored configuration with the values of the schedule's template, if it has one
+fn template_base(schedule: &Schedule) -> Result<InvoiceConfig, Error> {
+    let config = load_config()?;
+    if schedule.template.trim().is_empty() {
+        return Ok(config);
+    }
+    templates::template_config(&templates::find_template(&schedule.template)?, &config)
+}
+
+// The configuration a schedule bills: the given one with the schedule's values and only
+// its recipients
+pub fn schedule_config(schedule: &Schedule, base: &InvoiceConfig) -> Result<InvoiceConfig, Error> {
+    let config = base.with_values(&schedule.config)?;
+    if schedule.recipients.is_empty() {
+        return Ok(config);
+    }
+
+    let configured = recipients::parse_recipients(config.get("recipients"));
+    for name in &schedule.recipients {
+        if !configured.iter().any(|r| r.name == *name) {
+            return Err(Error::invalid(format!(
+                "Recipient '{}' of schedule '{}' is not configured",
+                name, schedule.name
+            )));
+        }
+    }
+    let chosen: Vec<_> = configured
+        .into_iter()
+        .filter(|r| schedule.recipients.contains(&r.name))
+        .collect();
+
+    config.with_values(&BTreeMap::from([(
+        "recipients".to_string(),
+        recipients::format_recipients(&chosen),
+    )]))
+}
+
+pub fn validate_schedule(schedule: &Schedule, base: &InvoiceConfig) -> Result<(), Error> {
+    if schedule.name.trim().is_empty() {
+        return Err(Error::invalid("The schedule needs a name"));
+    }
+    if !(1..=31).contains(&schedule.day_of_month) {
+        return Err(Error::invalid(format!(
+            "Day of month {} is not between 1 and 31",
+            schedule.day_of_month
+        )));
+    }
+    let start = parse_date(&schedule.start_date)?;
+    if !schedule.end_date.trim().is_empty() && parse_date(&schedule.end_date)? < start {
+        return Err(Error::invalid("The end date is before the start date"));
+    }
+    schedule_config(schedule, base)?;
+    Ok(())
+}
+
+fn decode_schedule(id: i64, definition: &str) -> Result<Schedule, Error> {
+    let mut schedule: Schedule = serde_json::from_str(definition)
+        .map_err(|e| Error::database(format!("Invalid schedule {}", id)).details(e))?;
+    schedule.id = id;
+    Ok(schedule)
+}
+
+pub fn list_schedules() -> Result<Vec<Schedule>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare("SELECT id, definition FROM schedules ORDER BY id")
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| Error::database("Failed to get schedules").details(e))?;
+
+    rows.map(|row| {
+        let (id, definition) =
+            row.map_err(|e| Error::database("Failed to read schedule").details(e))?;
+        decode_schedule(id, &definition)
+    })
+    .collect()
+}
+
+pub fn find_schedule(id: i64) -> Result<Schedule, Error> {
+    let conn = connect_database()?;
+    match conn.query_row(
+        "SELECT definition FROM schedules WHERE id = ?",
+        [id],
+        |row| row.get::<_, String>(0),
+    ) {
+        Ok(definition) => decode_schedule(id, &definition),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            Err(Error::not_found(format!("Schedule {} not found", id)))
+        }
+        Err(e) => Err(Error::database("Failed to get schedule").details(e)),
+    }
+}
+
+// Create a schedule, or replace the one with the same id
+pub fn save_schedule(schedule: Schedule) -> Result<Schedule, Error> {
+    validate_schedule(&schedule, &template_base(&schedule)?)?;
+    let definition = serde_json::to_string(&schedule)
+        .map_err(|e| Error::invalid("Failed to serialize schedule").details(e))?;
+
+    let conn = connect_database()?;
+    let id = if schedule.id == 0 {
+        conn.execute(
+            "INSERT INTO schedules (definition) VALUES (?)",
+            [&definition],
+        )
+        .map_err(|e| Error::database("Failed to save schedule").details(e))?;
+        conn.last_insert_rowid()
+    } else {
+        let updated = conn
+            .execute(
+                "UPDATE schedules SET definition = ?1 WHERE id = ?2",
+                rusqlite::params![definition, schedule.id],
+            )
+            .map_err(|e| Error::database("Failed to save schedule").details(e))?;
+        if updated == 0 {
+            return Err(Error::not_found(format!(
+                "Schedule {} not found",
+                schedule.id
+            )));
+        }
+        schedule.id
+    };
+
+    Ok(Schedule { id, ..schedule })
+}
+
+// Runs already made are kept, so they are not repeated if the schedule is restored
+pub fn delete_schedule(id: i64) -> Result<(), Error> {
+    let conn = connect_database()?;
+    conn.execute("DELETE FROM schedules WHERE id = ?", [id])
+        .map_err(|e| Error::database("Failed to delete schedule").details(e))?;
+    Ok(())
+}
+
+pub fn list_schedule_runs(schedule_id: i64) -> Result<Vec<ScheduleRun>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT run_date, status, invoice_numbers, created_at FROM schedule_runs
+             WHERE schedule_id = ? ORDER BY run_date",
+        )
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([schedule_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| Error::database("Failed to get schedule runs").details(e))?;
+
+    rows.map(|row| {
+        let (run_date, status, invoice_numbers, created_at) =
+            row.map_err(|e| Error::database("Failed to read schedule run").details(e))?;
+        Ok(ScheduleRun {
+            schedule_id,
+            run_date,
+            status: RunStatus::from_code(&status)?,
+            invoice_numbers: invoice_numbers
+                .split(',')
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+                .collect(),
+            created_at,
+        })
+    })
+    .collect()
+}
+
+// Runs of every schedule that are due by today and not yet recorded, oldest first. A run left
+// marked running by a generation that stopped is due again once no run is in progress.
+pub fn due_scheduled_runs() -> Result<Vec<DueRun>, Error> {
+    let today = Local::now().date_naive();
+    let idle = runs::lock_database().is_ok();
+    let mut due = Vec::new();
+
+    for schedule in list_schedules()? {
+        let recorded: Vec<String> = list_schedule_runs(schedule.id)?
+            .into_iter()
+            .filter(|run| !(idle && run.status == RunStatus::Running))
+            .map(|run| run.run_date)
+            .collect();
+
+        for date in occurrences(&schedule, today)? {
+            let run_date = date.format("%Y-%m-%d").to_string();
+            if !recorded.contains(&run_date) {
+                due.push(DueRun {
+                    schedule_id: schedule.id,
+                    name: schedule.name.clone(),
+                    run_date,
+                });
+            }
+        }
+    }
+    due.sort_by(|a, b| a.run_date.cmp(&b.run_date));
+
+    Ok(due)
+}
+
+// Refuse dates that are not a run of the schedule, or not due yet
+fn check_due(schedule: &Schedule, run_date: &str) -> Result<NaiveDate, Error> {
+    let date = parse_date(run_date)?;
+    if !occurrences(schedule, Local::now().date_naive())?.contains(&date) {
+        return Err(Error::invalid(format!(
+            "{} is not a due run of schedule '{}'",
+            run_date, schedule.name
+        )));
+    }
+    Ok(date)
+}
+
+// The configuration and options for one run
+fn prepare_run(schedule_id: i64, run_date: &str) -> Result<(InvoiceConfig, RunOptions), Error> {
+    let schedule = find_schedule(schedule_id)?;
+    let date = check_due(&schedule, run_date)?;
+
+    let config = schedule_config(&schedule, &template_base(&schedule)?)?;
+    // A run that came due before the open period, because the app was not opened for a while
+    // or invoices were stored since, is dated today. It still bills for its run date.
+    let invoice_date = if date < terms::current_open_period_start()? {
+        Local::now().date_naive()
+    } else {
+        date
+    };
+    let options = RunOptions {
+        invoice_date: Some(invoice_date.format("%Y-%m-%d").to_string()),
+        period_date: Some(run_date.to_string()),
+        ..RunOptions::default()
+    };
+    Ok((config, options))
+}
+
+pub fn preview_scheduled_run(schedule_id: i64, run_date: String) -> Result<SplitPreview, Error> {
+    let (config, options) = prepare_run(schedule_id, &run_date)?;
+    preview::preview_run(&config, &options)
+}
+
+// Record a run before it starts, with the run lock held. A run still marked running then
+// belongs to a generation that stopped without finishing, e.g. because the app crashed, and
+// is claimed again; any other recorded run cannot be.
+fn claim_run(
+    _lock: &RunLock,
+    schedule_id: i64,
+    run_date: &str,
+    status: RunStatus,
+) -> Result<(), Error> {
+    let conn = connect_database()?;
+    match conn.query_row(
+        "SELECT status FROM schedule_runs WHERE schedule_id = ?1 AND run_date = ?2",
+        rusqlite::params![schedule_id, run_date],
+        |row| row.get::<_, String>(0),
+    ) {
+        Ok(code) if RunStatus::from_code(&code)? == RunStatus::Running => {
+            release_run(schedule_id, run_date)?;
+        }
+        Ok(status) => {
+            return Err(Error::invalid(format!(
+                "The run of {} is already {}",
+                run_date, status
+            )))
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => {}
+        Err(e) => return Err(Error::database("Failed to get schedule run").details(e)),
+    }
+
+    conn.execute(
+        "INSERT INTO schedule_runs (schedule_id, run_date, status) VALUES (?1, ?2, ?3)",
+        rusqlite::params![schedule_id, run_date, status.code()],
+    )
+    .map_err(|e| Error::database("Failed to record schedule run").details(e))?;
+    Ok(())
+}
+
+fn finish_run(
+    schedule_id: i64,
+    run_date: &str,
+    status: RunStatus,
+    invoice_numbers: &[String],
+) -> Result<(), Error> {
+    let conn = connect_database()?;
+    conn.execute(
+        "UPDATE schedule_runs SET status = ?1, invoice_numbers = ?2
+         WHERE schedule_id = ?3 AND run_date = ?4",
+        rusqlite::params![
+            status.code(),
+            invoice_numbers.join(","),
+            schedule_id,
+            run_date
+        ],
+    )
+    .map_err(|e| Error::database("Failed to record schedule run").details(e))?;
+    Ok(())
+}
+
+fn release_run(schedule_id: i64, run_date: &str) -> Result<(), Error> {
+    let conn = connect_database()?;
+    conn.execute(
+        "DELETE FROM schedule_runs WHERE schedule_id = ?1 AND run_date = ?2",
+        rusqlite::params![schedule_id, run_date],
+    )
+    .map_err(|e| Error::database("Failed to release schedule run").details(e))?;
+    Ok(())
+}
+
+// Generate a due run as previewed. A failed run is offered again only if it stored no invoices.
+pub fn run_scheduled(
+    schedule_id: i64,
+    run_date: String,
+    config_hash: String,
+) -> Result<String, Error> {
+    let (config, options) = prepare_run(schedule_id, &run_date)?;
+    let lock = runs::lock_database()?;
+    claim_run(&lock, schedule_id, &run_date, RunStatus::Running)?;
+    let latest_id = invoices::latest_invoice_id()?;
+
+    match generation::generate_locked(&lock, &config, false, &options, Some(&config_hash)) {
+        Ok(result) => {
+            finish_run(
+                schedule_id,
+                &run_date,
+                RunStatus::Generated,
+                &result.invoice_numbers,
+            )?;
+            Ok(result.output)
+        }
+        Err(e) => {
+            let stored = invoices::invoice_numbers_after(latest_id)?;
+            if stored.is_empty() {
+                release_run(schedule_id, &run_date)?;
+            } else {
+                finish_run(schedule_id, &run_date, RunStatus::Failed, &stored)?;
+            }
+            Err(e)
+        }
+    }
+}
+
+// Mark a due run as done without generating it
+pub fn skip_scheduled_run(schedule_id: i64, run_date: String) -> Result<(), Error> {
+    check_due(&find_schedule(schedule_id)?, &run_date)?;
+    claim_run(
+        &runs::lock_database()?,
+        schedule_id,
+        &run_date,
+        RunStatus::Skipped,
+    )
+}
@@ -0,0 +1,420 @@
+use crate::api;
+use crate::database::connect_database;
+use crate::duplicates::DuplicatePolicy;
+use crate::errors::Error;
+use crate::export::{self, CollisionPolicy};
+use crate::line_items;
+use crate::money::{Currency, Money};
+use crate::placeholders;
+use crate::renderer::{BatchMode, RendererKind};
+use crate::split;
+use crate::terms;
+use crate::vat;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// Setting keys that make up the invoice configuration
+pub const CONFIG_KEYS: [&str; 10] = [
+    "sender",
+    "bankdetails",
+    "description",
+    "amount",
+    CURRENCY_KEY,
+    "recipients",
+    split::SPLIT_KEY,
+    line_items::LINE_ITEMS_KEY,
+    vat::VAT_KEY,
+    terms::TERMS_KEY,
+];
+
+// ISO code of the invoice currency; empty means NOK
+pub const CURRENCY_KEY: &str = "currency";
+
+#[derive(Serialize, Deserialize)]
+pub struct InvoiceFiles {
+    pub sender: String,
+    pub bankdetails: String,
+    pub description: String,
+    pub amount: String,
+    pub recipients: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppSettings {
+    pub output_directory: String,
+    // Where dry-run PDFs go; empty means a "previews" folder inside the output directory
+    #[serde(default)]
+    pub preview_directory: String,
+    #[serde(default)]
+    pub collision_policy: CollisionPolicy,
+    // Exported file name without extension, e.g. "{year}-{month} {customer} {number}"
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    // Folder below the output directory, e.g. "{customer}/{year}"; empty keeps a flat layout
+    #[serde(default)]
+    pub subfolder_template: String,
+    // Local HTTP API for integrations; only listens on localhost and is off by default
+    #[serde(default)]
+    pub api_enabled: bool,
+    #[serde(default = "default_api_port")]
+    pub api_port: u16,
+    #[serde(default)]
+    pub api_token: String,
+    // Whether PDFs come from the OCaml backend or the in-process renderer
+    #[serde(default)]
+    pub renderer: RendererKind,
+    // A backend still running after this many seconds is stopped; 0 for no limit
+    #[serde(default = "default_backend_timeout_secs")]
+    pub backend_timeout_secs: u64,
+    // Whether a run stores the invoices that succeed, or none unless all of them do
+    #[serde(default)]
+    pub batch_mode: BatchMode,
+    // What happens when a run would bill a recipient the same as a recent invoice
+    #[serde(default)]
+    pub duplicate_policy: DuplicatePolicy,
+    // How many days back an invoice counts as recent; 0 for any age
+    #[serde(default = "default_duplicate_window_days")]
+    pub duplicate_window_days: u32,
+}
+
+fn default_filename_template() -> String {
+    export::DEFAULT_FILENAME_TEMPLATE.to_string()
+}
+
+fn default_api_port() -> u16 {
+    api::DEFAULT_PORT
+}
+
+fn default_backend_timeout_secs() -> u64 {
+    300
+}
+
+fn default_duplicate_window_days() -> u32 {
+    31
+}
+
+// Overrides the app data directory (settings.json, backups), e.g. for scripts and tests
+pub const DATA_DIR_ENV: &str = "INVOICE_SPLITTER_DATA_DIR";
+
+// Directory management functions
+pub fn get_app_data_dir() -> Result<PathBuf, Error> {
+    let app_data = match std::env::var_os(DATA_DIR_ENV).filter(|p| !p.is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => dirs::data_dir()
+            .ok_or_else(|| Error::filesystem("Could not determine data directory"))?
+            .join("InvoiceSplitter"),
+    };
+
+    // Ensure the directory exists
+    fs::create_dir_all(&app_data)
+        .map_err(|e| Error::filesystem("Failed to create app data directory").details(e))?;
+
+    Ok(app_data)
+}
+
+fn get_default_output_dir() -> Result<PathBuf, Error> {
+    let documents = dirs::document_dir()
+        .ok_or_else(|| Error::filesystem("Could not determine documents directory"))?
+        .join("InvoiceSplitter");
+
+    // Ensure the directory exists
+    fs::create_dir_all(&documents)
+        .map_err(|e| Error::filesystem("Failed to create documents directory").details(e))?;
+
+    Ok(documents)
+}
+
+pub fn get_settings_path() -> Result<PathBuf, Error> {
+    let app_data = get_app_data_dir()?;
+    Ok(app_data.join("settings.json"))
+}
+
+// Settings management
+pub fn get_app_settings() -> Result<AppSettings, Error> {
+    let settings_path = get_settings_path()?;
+
+    if settings_path.exists() {
+        let content = fs::read_to_string(&settings_path)
+            .map_err(|e| Error::filesystem("Failed to read settings").details(e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| Error::filesystem("Failed to parse settings").details(e))
+    } else {
+        // Create default settings
+        let default_output = get_default_output_dir()?;
+
+        let settings = AppSettings {
+            output_directory: default_output.to_string_lossy().to_string(),
+            preview_directory: String::new(),
+            collision_policy: CollisionPolicy::default(),
+            filename_template: default_filename_template(),
+            subfolder_template: String::new(),
+            api_enabled: false,
+            api_port: default_api_port(),
+            api_token: String::new(),
+            renderer: RendererKind::default(),
+            backend_timeout_secs: default_backend_timeout_secs(),
+            batch_mode: BatchMode::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            duplicate_window_days: default_duplicate_window_days(),
+        };
+
+        // Save default settings
+        save_app_settings(settings.clone())?;
+        Ok(settings)
+    }
+}
+
+pub fn save_app_settings(settings: AppSettings) -> Result<(), Error> {
+    let settings_path = get_settings_path()?;
+
+    export::validate_template(&settings.filename_template)?;
+    export::validate_template(&settings.subfolder_template)?;
+
+    // Enabling the API without a token generates one, so it is never left open
+    let mut settings = settings;
+    if settings.api_enabled && settings.api_token.trim().is_empty() {
+        settings.api_token = api::generate_token()?;
+    }
+
+    // Ensure output directory exists
+    fs::create_dir_all(&settings.output_directory)
+        .map_err(|e| Error::filesystem("Failed to create output directory").details(e))?;
+
+    let content = serde_json::to_string_pretty(&settings)
+        .map_err(|e| Error::invalid("Failed to serialize settings").details(e))?;
+
+    fs::write(settings_path, content)
+        .map_err(|e| Error::filesystem("Failed to save settings").details(e))
+}
+
+// Settings management functions
+pub fn get_setting(key: &str) -> Result<Option<String>, Error> {
+    let conn = connect_database()?;
+
+    let mut stmt = conn
+        .prepare("SELECT value FROM settings WHERE key = ?")
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    match stmt.query_row([key], |row| row.get::<_, String>(0)) {
+        Ok(value) => Ok(Some(value)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::database("Failed to get setting").details(e)),
+    }
+}
+
+pub fn set_setting(key: &str, value: &str) -> Result<(), Error> {
+    let conn = connect_database()?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+        [key, value],
+    )
+    .map_err(|e| Error::database("Failed to set setting").details(e))?;
+
+    Ok(())
+}
+
+// The invoice configuration as a whole: the stored values, or another set of values used
+// for one run without touching the stored ones
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct InvoiceConfig {
+    values: BTreeMap<String, String>,
+}
+
+impl InvoiceConfig {
+    pub fn get(&self, key: &str) -> &str {
+        self.values.get(key).map_or("", String::as_str)
+    }
+
+    pub fn currency(&self) -> Result<Currency, Error> {
+        Currency::from_code(self.get(CURRENCY_KEY))
+    }
+
+    pub fn vat(&self) -> Result<vat::VatSettings, Error> {
+        vat::parse_vat_settings(self.get(vat::VAT_KEY))
+    }
+
+    // A copy with some values replaced. Each value is checked against the resulting
+    // configuration, so e.g. an amount is parsed in the replacement currency.
+    pub fn with_values(&self, values: &BTreeMap<String, String>) -> Result<InvoiceConfig, Error> {
+        let mut config = self.clone();
+        for (key, value) in values {
+            if !CONFIG_KEYS.contains(&key.as_str()) {
+                return Err(Error::invalid(format!(
+                    "Unknown configuration key '{}'",
+                    key
+                )));
+            }
+            config.values.insert(key.clone(), value.clone());
+        }
+
+        for key in CONFIG_KEYS {
+            if values.contains_key(key) {
+                let value = validate_config_value(&config, key, config.get(key).to_string())?;
+                config.values.insert(key.to_string(), value);
+            }
+        }
+        Ok(config)
+    }
+
+    // Hash of the values, used to tell whether the configuration changed between two points in time
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for key in CONFIG_KEYS {
+            hasher.update(key.as_bytes());
+            hasher.update([0]);
+            hasher.update(self.get(key).as_bytes());
+            hasher.update([0]);
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// The stored invoice configuration
+pub fn load_config() -> Result<InvoiceConfig, Error> {
+    let mut values = BTreeMap::new();
+    for key in CONFIG_KEYS {
+        values.insert(key.to_string(), get_setting(key)?.unwrap_or_default());
+    }
+    Ok(InvoiceConfig { values })
+}
+
+// Check several configuration values together and store them in one transaction
+pub fn store_config_values(values: &BTreeMap<String, String>) -> Result<(), Error> {
+    let config = load_config()?.with_values(values)?;
+
+    let mut conn = connect_database()?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| Error::database("Failed to start transaction").details(e))?;
+    for key in values.keys() {
+        tx.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            [key.as_str(), config.get(key)],
+        )
+        .map_err(|e| Error::database("Failed to set setting").details(e))?;
+    }
+    tx.commit()
+        .map_err(|e| Error::database("Failed to store configuration").details(e))
+}
+
+// Hash of the stored invoice configuration
+pub fn config_fingerprint() -> Result<String, Error> {
+    Ok(load_config()?.fingerprint())
+}
+
+pub fn configured_currency() -> Result<Currency, Error> {
+    Currency::from_code(&get_setting(CURRENCY_KEY)?.unwrap_or_default())
+}
+
+// Check a configuration value before it is stored, returning it in its canonical form.
+// Amounts are stored as plain decimals ("5000.00") because the backend reads them as such.
+fn validate_config_value(
+    config: &InvoiceConfig,
+    key: &str,
+    value: String,
+) -> Result<String, Error> {
+    match key {
+        "amount" if !value.trim().is_empty() => {
+            Ok(Money::parse(&value, config.currency()?)?.plain())
+        }
+        "description" => {
+            placeholders::validate(&value, &placeholders::RECIPIENT_PLACEHOLDERS)?;
+            Ok(value)
+        }
+        CURRENCY_KEY if !value.trim().is_empty() => {
+            Ok(Currency::from_code(&value)?.code.to_string())
+        }
+        split::SPLIT_KEY => {
+            split::parse_split_config(&value, config.currency()?)?;
+            Ok(value)
+        }
+        line_items::LINE_ITEMS_KEY => {
+            line_items::parse_line_items(&value, config.currency()?, &config.vat()?)?;
+            Ok(value)
+        }
+        vat::VAT_KEY => {
+            vat::parse_vat_settings(&value)?;
+            Ok(value)
+        }
+        terms::TERMS_KEY => {
+            terms::parse_terms(&value)?;
+            Ok(value)
+        }
+        _ => Ok(value),
+    }
+}
+
+pub fn get_config_setting(key: String) -> Result<String, Error> {
+    get_setting(&key).map(|opt| opt.unwrap_or_default())
+}
+
+pub fn set_config_setting(key: String, value: String) -> Result<(), Error> {
+    let value = validate_config_value(&load_config()?, &key, value)?;
+    set_setting(&key, &value)
+}
+
+pub fn is_first_run() -> Result<bool, Error> {
+    match get_setting("_app_initialized") {
+        Ok(Some(_)) => Ok(false), // App has been initialized
+        Ok(None) => Ok(true),     // First run
+        Err(e) => Err(e),
+    }
+}
+
+// Legacy file operations (now using database)
+pub fn read_file(file_path: String) -> Result<String, Error> {
+    // Map file names to setting keys
+    let setting_key = match file_path.as_str() {
+        "sender.txt" => "sender",
+        "bankdetails.txt" => "bankdetails",
+        "description.txt" => "description",
+        "amount.txt" => "amount",
+        "recipients.txt" => "recipients",
+        _ => return Ok(String::new()),
+    };
+
+    get_config_setting(setting_key.to_string())
+}
+
+pub fn write_file(file_path: String, content: String) -> Result<(), Error> {
+    // Map file names to setting keys
+    let setting_key = match file_path.as_str() {
+        "sender.txt" => "sender",
+        "bankdetails.txt" => "bankdetails",
+        "description.txt" => "description",
+        "amount.txt" => "amount",
+        "recipients.txt" => "recipients",
+        _ => return Err(Error::invalid("Unknown config file")),
+    };
+
+    set_config_setting(setting_key.to_string(), content)
+}
+
+// Read all config files
+pub fn read_all_files() -> Result<InvoiceFiles, Error> {
+    Ok(InvoiceFiles {
+        sender: get_config_setting("sender".to_string())?,
+        bankdetails: get_config_setting("bankdetails".to_string())?,
+        description: get_config_setting("description".to_string())?,
+        amount: get_config_setting("amount".to_string())?,
+        recipients: get_config_setting("recipients".to_string())?,
+    })
+}
+
+// Save invoice details (description and amount) together
+pub fn save_invoice_details(description: String, amount: String) -> Result<(), Error> {
+    store_config_values(&BTreeMap::from([
+        ("description".to_string(), description),
+        ("amount".to_string(), amount),
+    ]))
+}
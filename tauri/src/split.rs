@@ -0,0 +1,334 @@
+use crate::errors::Error;
+use crate::line_items;
+use crate::money::{self, Currency, Money};
+use crate::recipients::{self, Recipient};
+use crate::settings::{configured_currency, get_setting, load_config, set_setting, InvoiceConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Setting key holding the split configuration as JSON
+pub const SPLIT_KEY: &str = "split";
+
+// How one recipient's share is determined. Fixed amounts are taken off the total first,
+// percentages apply to what is left after that, and weighted recipients share the rest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Share {
+    // Same as a weight of 1
+    Equal,
+    Weight(u32),
+    // Percentage with up to two decimals, e.g. "33.5"
+    Percent(String),
+    // Amount such as "1500.00"
+    Fixed(String),
+}
+
+// Shares keyed by recipient name; recipients not listed get an equal share
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SplitConfig {
+    #[serde(default)]
+    pub shares: BTreeMap<String, Share>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Allocation {
+    pub recipient: String,
+    pub amount: Money,
+}
+
+const PERCENT_DIGITS: u32 = 2;
+const HUNDRED_PERCENT: i64 = 100 * 100;
+
+enum Rule {
+    Fixed(i64),
+    // Hundredths of a percent
+    Percent(i64),
+    Weight(i64),
+}
+
+fn rule_for(share: &Share, currency: Currency) -> Result<Rule, Error> {
+    match share {
+        Share::Equal => Ok(Rule::Weight(1)),
+        Share::Weight(0) => Err(Error::invalid("Weights must be at least 1")),
+        Share::Weight(weight) => Ok(Rule::Weight(i64::from(*weight))),
+        Share::Percent(value) => {
+            let percent = money::parse_decimal(value, PERCENT_DIGITS)
+                .map_err(|e| e.context("Invalid percentage"))?;
+            if percent <= 0 || percent > HUNDRED_PERCENT {
+                return Err(Error::invalid(format!(
+                    "Percentage {} must be above 0 and at most 100",
+                    value
+                )));
+            }
+            Ok(Rule::Percent(percent))
+        }
+        Share::Fixed(value) => {
+            let amount = Money::parse(value, currency)?;
+            if amount.minor < 0 {
+                return Err(Error::invalid(format!(
+                    "Fixed amount {} cannot be negative",
+                    value
+                )));
+            }
+            Ok(Rule::Fixed(amount.minor))
+        }
+    }
+}
+
+// Split a total by the given shares. The result always adds up to the total; leftover
+// minor units go to the largest fractional parts, ties to the earlier share.
+pub fn allocate(total: Money, shares: &[Share]) -> Result<Vec<Money>, Error> {
+    if shares.is_empty() {
+        return Err(Error::invalid("There are no recipients to split between"));
+    }
+    if total.minor < 0 {
+        return Err(Error::invalid("The total amount cannot be negative"));
+    }
+
+    let currency = total.currency;
+    let in_currency = |minor: i64| Money::new(minor, currency);
+    let total = total.minor;
+
+    let rules = shares
+        .iter()
+        .map(|share| rule_for(share, currency))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let fixed_total: i64 = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Fixed(amount) => *amount,
+            _ => 0,
+        })
+        .sum();
+    let percent_total: i64 = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Percent(percent) => *percent,
+            _ => 0,
+        })
+        .sum();
+    let weight_total: i64 = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Weight(weight) => *weight,
+            _ => 0,
+        })
+        .sum();
+
+    let rest = total - fixed_total;
+    if rest < 0 {
+        return Err(Error::invalid(format!(
+            "Fixed amounts ({}) exceed the total ({})",
+            in_currency(fixed_total),
+            in_currency(total)
+        )));
+    }
+    if percent_total > HUNDRED_PERCENT {
+        return Err(Error::invalid("Percentages add up to more than 100"));
+    }
+
+    let only_fixed = percent_total == 0 && weight_total == 0;
+    if only_fixed && rest != 0 {
+        return Err(Error::invalid(format!(
+            "Fixed amounts ({}) do not add up to the total ({})",
+            in_currency(fixed_total),
+            in_currency(total)
+        )));
+    }
+    if weight_total == 0 && percent_total > 0 && percent_total != HUNDRED_PERCENT {
+        return Err(Error::invalid(
+            "Percentages must add up to 100 unless some recipients share the rest",
+        ));
+    }
+    if weight_total > 0 && percent_total == HUNDRED_PERCENT {
+        return Err(Error::invalid(
+            "Percentages add up to 100, leaving nothing for the recipients sharing the rest",
+        ));
+    }
+
+    // Every non-fixed share is rest * numerator / denominator; the numerators add up to
+    // rest * denominator exactly, so only whole minor units are left to hand out
+    let weight_divisor = weight_total.max(1) as i128;
+    let denominator = HUNDRED_PERCENT as i128 * weight_divisor;
+    let rest = rest as i128;
+
+    let exact: Vec<Option<i128>> = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Fixed(_) => None,
+            Rule::Percent(percent) => Some(rest * *percent as i128 * weight_divisor),
+            Rule::Weight(weight) => {
+                Some(rest * (HUNDRED_PERCENT - percent_total) as i128 * *weight as i128)
+            }
+        })
+        .collect();
+
+    let fixed: Vec<i64> = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Fixed(amount) => *amount,
+            _ => 0,
+        })
+        .collect();
+
+    Ok(round_shares(total, fixed, &exact, denominator)
+        .into_iter()
+        .map(in_currency)
+        .collect())
+}
+
+// Floor every exact share (numerator / denominator) on top of the fixed amounts, then hand the
+// units lost to rounding to the largest remainders, ties to the earlier share
+fn round_shares(
+    total: i64,
+    fixed: Vec<i64>,
+    exact: &[Option<i128>],
+    denominator: i128,
+) -> Vec<i64> {
+    let mut amounts: Vec<i64> = fixed
+        .iter()
+        .zip(exact)
+        .map(|(fixed, exact)| fixed + exact.map_or(0, |n| (n / denominator) as i64))
+        .collect();
+
+    let mut leftover = total - amounts.iter().sum::<i64>();
+    let mut by_remainder: Vec<(usize, i128)> = exact
+        .iter()
+        .enumerate()
+        .filter_map(|(i, numerator)| numerator.map(|n| (i, n % denominator)))
+        .collect();
+    // Stable sort keeps index order among equal remainders
+    by_remainder.sort_by_key(|(_, remainder)| std::cmp::Reverse(*remainder));
+
+    for (index, _) in by_remainder.iter().cycle() {
+        if leftover <= 0 {
+            break;
+        }
+        amounts[*index] += 1;
+        leftover -= 1;
+    }
+
+    amounts
+}
+
+// Split a non-negative total proportionally to non-negative weights, e.g. a recipient's
+// share across invoice lines. All zero weights give all zero amounts.
+pub fn distribute(total: i64, weights: &[i64]) -> Vec<i64> {
+    let weight_total: i128 = weights.iter().map(|w| *w as i128).sum();
+    if weight_total == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let exact: Vec<Option<i128>> = weights
+        .iter()
+        .map(|w| Some(total as i128 * *w as i128))
+        .collect();
+
+    round_shares(total, vec![0; weights.len()], &exact, weight_total)
+}
+
+// Split a total between recipients according to the configuration
+pub fn plan_split(
+    total: Money,
+    recipients: &[Recipient],
+    config: &SplitConfig,
+) -> Result<Vec<Allocation>, Error> {
+    validate_split_config(config, total.currency)?;
+
+    if let Some(unknown) = config
+        .shares
+        .keys()
+        .find(|name| !recipients.iter().any(|r| &r.name == *name))
+    {
+        return Err(Error::invalid(format!(
+            "Split refers to '{}', who is not among the recipients",
+            unknown
+        )));
+    }
+
+    let shares: Vec<Share> = recipients
+        .iter()
+        .map(|r| config.shares.get(&r.name).cloned().unwrap_or(Share::Equal))
+        .collect();
+
+    let amounts = allocate(total, &shares)?;
+
+    Ok(recipients
+        .iter()
+        .zip(amounts)
+        .map(|(recipient, amount)| Allocation {
+            recipient: recipient.name.clone(),
+            amount,
+        })
+        .collect())
+}
+
+pub fn validate_split_config(config: &SplitConfig, currency: Currency) -> Result<(), Error> {
+    for (name, share) in &config.shares {
+        rule_for(share, currency).map_err(|e| e.context(format!("Invalid share for {}", name)))?;
+    }
+    Ok(())
+}
+
+pub fn decode_split_config(value: &str) -> Result<SplitConfig, Error> {
+    if value.trim().is_empty() {
+        return Ok(SplitConfig::default());
+    }
+    serde_json::from_str(value)
+        .map_err(|e| Error::invalid("Invalid split configuration").details(e))
+}
+
+// Parse and validate a split configuration before it is stored
+pub fn parse_split_config(value: &str, currency: Currency) -> Result<SplitConfig, Error> {
+    let config = decode_split_config(value)?;
+    validate_split_config(&config, currency)?;
+    Ok(config)
+}
+
+pub fn load_split_config() -> Result<SplitConfig, Error> {
+    decode_split_config(&get_setting(SPLIT_KEY)?.unwrap_or_default())
+}
+
+pub fn get_split_config() -> Result<SplitConfig, Error> {
+    load_split_config()
+}
+
+pub fn save_split_config(config: SplitConfig) -> Result<(), Error> {
+    validate_split_config(&config, configured_currency()?)?;
+
+    let value = serde_json::to_string(&config)
+        .map_err(|e| Error::invalid("Failed to serialize split configuration").details(e))?;
+    set_setting(SPLIT_KEY, &value)
+}
+
+// The amount to split: the line item subtotal when line items are defined, otherwise the amount setting
+pub fn configured_total(config: &InvoiceConfig) -> Result<Money, Error> {
+    let currency = config.currency()?;
+    let items = line_items::decode_line_items(config.get(line_items::LINE_ITEMS_KEY))?;
+    if !items.is_empty() {
+        return Ok(line_items::calculate_totals(&items, currency, &config.vat()?)?.subtotal);
+    }
+
+    let total = config.get("amount");
+    if total.trim().is_empty() {
+        return Err(Error::invalid("No invoice amount configured"));
+    }
+    Money::parse(total, currency)
+}
+
+// Split the amount of a configuration between its recipients
+pub fn split_for(config: &InvoiceConfig) -> Result<Vec<Allocation>, Error> {
+    let recipients = recipients::parse_recipients(config.get("recipients"));
+
+    plan_split(
+        configured_total(config)?,
+        &recipients,
+        &decode_split_config(config.get(SPLIT_KEY))?,
+    )
+}
+
+// Split the configured amount between the configured recipients
+pub fn calculate_split() -> Result<Vec<Allocation>, Error> {
+    split_for(&load_config()?)
+}
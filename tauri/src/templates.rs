@@ -0,0 +1,178 @@
+use crate::database::connect_database;
+use crate::errors::Error;
+use crate::generation;
+use crate::preview::{self, SplitPreview};
+use crate::settings::{self, load_config, InvoiceConfig, CONFIG_KEYS};
+use crate::terms::RunOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// A saved invoice: configuration values by setting key. Keys left out keep the value of
+// the configuration the template is applied to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub values: BTreeMap<String, String>,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+fn check_name(name: &str) -> Result<&str, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::invalid("The template needs a name"));
+    }
+    Ok(name)
+}
+
+// The configuration a template bills: the given one with the template's values
+pub fn template_config(template: &Template, base: &InvoiceConfig) -> Result<InvoiceConfig, Error> {
+    base.with_values(&template.values)
+        .map_err(|e| e.context(format!("Template '{}'", template.name)))
+}
+
+fn decode_values(name: &str, values: &str) -> Result<BTreeMap<String, String>, Error> {
+    serde_json::from_str(values)
+        .map_err(|e| Error::database(format!("Invalid template '{}'", name)).details(e))
+}
+
+pub fn list_templates() -> Result<Vec<Template>, Error> {
+    let conn = connect_database()?;
+    let mut stmt = conn
+        .prepare("SELECT name, template_values, created_at FROM templates ORDER BY name")
+        .map_err(|e| Error::database("Failed to prepare query").details(e))?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|e| Error::database("Failed to get templates").details(e))?;
+
+    rows.map(|row| {
+        let (name, values, created_at) =
+            row.map_err(|e| Error::database("Failed to read template").details(e))?;
+        Ok(Template {
+            values: decode_values(&name, &values)?,
+            name,
+            created_at,
+        })
+    })
+    .collect()
+}
+
+pub fn find_template(name: &str) -> Result<Template, Error> {
+    let conn = connect_database()?;
+    match conn.query_row(
+        "SELECT template_values, created_at FROM templates WHERE name = ?",
+        [name],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+    ) {
+        Ok((values, created_at)) => Ok(Template {
+            name: name.to_string(),
+            values: decode_values(name, &values)?,
+            created_at,
+        }),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            Err(Error::not_found(format!("Template '{}' not found", name)))
+        }
+        Err(e) => Err(Error::database("Failed to get template").details(e)),
+    }
+}
+
+fn insert_template(name: &str, values: &BTreeMap<String, String>) -> Result<Template, Error> {
+    let name = check_name(name)?;
+    let template = Template {
+        name: name.to_string(),
+        values: values.clone(),
+        created_at: String::new(),
+    };
+    // Values are stored in their canonical form, e.g. amounts as plain decimals
+    let config = template_config(&template, &load_config()?)?;
+    let values: BTreeMap<String, String> = values
+        .keys()
+        .map(|key| (key.clone(), config.get(key).to_string()))
+        .collect();
+    let encoded = serde_json::to_string(&values)
+        .map_err(|e| Error::invalid("Failed to serialize template").details(e))?;
+
+    let conn = connect_database()?;
+    let exists: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM templates WHERE name = ?",
+            [name],
+            |row| row.get(0),
+        )
+        .map_err(|e| Error::database("Failed to get template").details(e))?;
+    if exists > 0 {
+        return Err(Error::invalid(format!(
+            "A template named '{}' already exists",
+            name
+        )));
+    }
+
+    conn.execute(
+        "INSERT INTO templates (name, template_values) VALUES (?1, ?2)",
+        [name, &encoded],
+    )
+    .map_err(|e| Error::database("Failed to save template").details(e))?;
+
+    find_template(name)
+}
+
+// Save a template with the given values, or with the whole current configuration
+pub fn create_template(
+    name: String,
+    values: Option<BTreeMap<String, String>>,
+) -> Result<Template, Error> {
+    let values = match values {
+        Some(values) => values,
+        None => {
+            let config = load_config()?;
+            CONFIG_KEYS
+                .iter()
+                .map(|key| (key.to_string(), config.get(key).to_string()))
+                .collect()
+        }
+    };
+    insert_template(&name, &values)
+}
+
+pub fn duplicate_template(name: String, new_name: String) -> Result<Template, Error> {
+    insert_template(&new_name, &find_template(&name)?.values)
+}
+
+pub fn delete_template(name: String) -> Result<(), Error> {
+    let conn = connect_database()?;
+    let deleted = conn
+        .execute("DELETE FROM templates WHERE name = ?", [&name])
+        .map_err(|e| Error::database("Failed to delete template").details(e))?;
+    if deleted == 0 {
+        return Err(Error::not_found(format!("Template '{}' not found", name)));
+    }
+    Ok(())
+}
+
+// Replace the current configuration values with the template's
+pub fn load_template(name: String) -> Result<(), Error> {
+    settings::store_config_values(&find_template(&name)?.values)
+}
+
+pub fn preview_template(name: String, options: Option<RunOptions>) -> Result<SplitPreview, Error> {
+    let config = template_config(&find_template(&name)?, &load_config()?)?;
+    preview::preview_run(&config, &options.unwrap_or_default())
+}
+
+// Generate the invoices of a template as previewed, leaving the current configuration as it is
+pub fn generate_from_template(
+    name: String,
+    config_hash: String,
+    options: Option<RunOptions>,
+) -> Result<String, Error> {
+    let options = options.unwrap_or_default();
+    let config = template_config(&find_template(&name)?, &load_config()?)?;
+    generation::run_generation(&config, false, &options, Some(&config_hash)).map(|r| r.output)
+}
@@ -0,0 +1,265 @@
+use crate::errors::Error;
+use crate::invoices;
+use crate::settings::{get_setting, set_setting};
+use chrono::{Datelike, Days, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Setting key holding the invoice date and payment terms as JSON
+pub const TERMS_KEY: &str = "terms";
+
+pub const DEFAULT_PAYMENT_DAYS: u32 = 30;
+
+// Longest payment term accepted, to catch typos such as 300 for 30
+pub const MAX_PAYMENT_DAYS: u32 = 365;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Terms {
+    // Fixed invoice date (YYYY-MM-DD) for the next run; empty for the day of generation
+    #[serde(default)]
+    pub invoice_date: String,
+    #[serde(default = "default_payment_days")]
+    pub payment_days: u32,
+    // Payment days for particular recipients, by name
+    #[serde(default)]
+    pub recipients: BTreeMap<String, u32>,
+}
+
+fn default_payment_days() -> u32 {
+    DEFAULT_PAYMENT_DAYS
+}
+
+impl Default for Terms {
+    fn default() -> Self {
+        Terms {
+            invoice_date: String::new(),
+            payment_days: DEFAULT_PAYMENT_DAYS,
+            recipients: BTreeMap::new(),
+        }
+    }
+}
+
+// Parameters for one run that take precedence over the stored terms
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RunOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_days: Option<u32>,
+    // One due date for every invoice in the run, instead of payment days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    // The day the run bills for, when that is not the invoice date, e.g. a scheduled run that
+    // came due before the open period and is dated today instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period_date: Option<String>,
+    // Generate even if invoices like these were billed recently. Not part of the run's
+    // fingerprint, so a preview can be confirmed with or without it.
+    #[serde(default, skip_serializing)]
+    pub force: bool,
+}
+
+// The dates one invoice gets
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct InvoiceDates {
+    pub invoice_date: String,
+    pub due_date: String,
+    pub payment_days: u32,
+    // The day billed for, which placeholders and the duplicate check go by; usually the
+    // invoice date
+    pub period_date: String,
+}
+
+pub fn parse_date(value: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(value.trim(), DATE_FORMAT).map_err(|_| {
+        Error::invalid(format!(
+            "Invalid date '{}', expected YYYY-MM-DD",
+            value.trim()
+        ))
+    })
+}
+
+fn check_payment_days(days: u32) -> Result<(), Error> {
+    if days > MAX_PAYMENT_DAYS {
+        return Err(Error::invalid(format!(
+            "Payment terms of {} days are longer than {} days",
+            days, MAX_PAYMENT_DAYS
+        )));
+    }
+    Ok(())
+}
+
+fn validate_terms(terms: &Terms) -> Result<(), Error> {
+    if !terms.invoice_date.trim().is_empty() {
+        parse_date(&terms.invoice_date)?;
+    }
+    check_payment_days(terms.payment_days)?;
+    for (name, days) in &terms.recipients {
+        check_payment_days(*days).map_err(|e| e.context(name))?;
+    }
+    Ok(())
+}
+
+// Earliest invoice date still open: the first day of the previous month, and never before
+// the latest stored invoice so invoice numbers stay in date order
+pub fn open_period_start(today: NaiveDate, latest_invoice: Option<NaiveDate>) -> NaiveDate {
+    let first_of_month = today.with_day(1).unwrap_or(today);
+    let period_start = first_of_month
+        .pred_opt()
+        .and_then(|d| d.with_day(1))
+        .unwrap_or(first_of_month);
+
+    match latest_invoice {
+        Some(latest) if latest > period_start => latest,
+        _ => period_start,
+    }
+}
+
+// An invoice may not be dated in the future, nor before `earliest`, the start of the open period
+pub fn check_invoice_date(
+    invoice_date: NaiveDate,
+    today: NaiveDate,
+    earliest: NaiveDate,
+) -> Result<(), Error> {
+    if invoice_date > today {
+        return Err(Error::invalid(format!(
+            "Invoice date {} is in the future",
+            invoice_date.format(DATE_FORMAT)
+        )));
+    }
+    if invoice_date < earliest {
+        return Err(Error::invalid(format!(
+            "Invoice date {} is before {}, the start of the open period",
+            invoice_date.format(DATE_FORMAT),
+            earliest.format(DATE_FORMAT)
+        )));
+    }
+    Ok(())
+}
+
+// Work out the dates for each recipient's invoice. `earliest` is the start of the open period.
+pub fn resolve_dates(
+    terms: &Terms,
+    options: &RunOptions,
+    recipients: &[String],
+    today: NaiveDate,
+    earliest: NaiveDate,
+) -> Result<Vec<InvoiceDates>, Error> {
+    if options.payment_days.is_some() && options.due_date.is_some() {
+        return Err(Error::invalid(
+            "Give either payment days or a due date, not both",
+        ));
+    }
+
+    let invoice_date = match options
+        .invoice_date
+        .as_deref()
+        .unwrap_or(&terms.invoice_date)
+    {
+        value if value.trim().is_empty() => today,
+        value => parse_date(value)?,
+    };
+    check_invoice_date(invoice_date, today, earliest)?;
+
+    let period_date = match options.period_date.as_deref() {
+        Some(value) => parse_date(value)?,
+        None => invoice_date,
+    };
+    let fixed_due_date = options.due_date.as_deref().map(parse_date).transpose()?;
+    if let Some(days) = options.payment_days {
+        check_payment_days(days)?;
+    }
+
+    recipients
+        .iter()
+        .map(|name| {
+            let due_date = match fixed_due_date {
+                Some(due_date) => due_date,
+                None => {
+                    let days = options
+                        .payment_days
+                        .or_else(|| terms.recipients.get(name).copied())
+                        .unwrap_or(terms.payment_days);
+                    invoice_date
+                        .checked_add_days(Days::new(days as u64))
+                        .ok_or_else(|| Error::invalid("Due date is out of range"))?
+                }
+            };
+
+            if due_date < invoice_date {
+                return Err(Error::invalid(format!(
+                    "Due date {} is before the invoice date {}",
+                    due_date.format(DATE_FORMAT),
+                    invoice_date.format(DATE_FORMAT)
+                )));
+            }
+
+            Ok(InvoiceDates {
+                invoice_date: invoice_date.format(DATE_FORMAT).to_string(),
+                due_date: due_date.format(DATE_FORMAT).to_string(),
+                payment_days: (due_date - invoice_date).num_days() as u32,
+                period_date: period_date.format(DATE_FORMAT).to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn parse_terms(value: &str) -> Result<Terms, Error> {
+    if value.trim().is_empty() {
+        return Ok(Terms::default());
+    }
+    let terms: Terms = serde_json::from_str(value)
+        .map_err(|e| Error::invalid("Invalid payment terms").details(e))?;
+    validate_terms(&terms)?;
+    Ok(terms)
+}
+
+pub fn load_terms() -> Result<Terms, Error> {
+    parse_terms(&get_setting(TERMS_KEY)?.unwrap_or_default())
+}
+
+// The start of the open period today, given the invoices already stored
+pub fn current_open_period_start() -> Result<NaiveDate, Error> {
+    let latest = invoices::latest_invoice_date()?.and_then(|date| parse_date(&date).ok());
+    Ok(open_period_start(Local::now().date_naive(), latest))
+}
+
+// Dates for the next run, checked against today and the invoices already stored
+pub fn run_dates(
+    terms: &Terms,
+    options: &RunOptions,
+    recipients: &[String],
+) -> Result<Vec<InvoiceDates>, Error> {
+    resolve_dates(
+        terms,
+        options,
+        recipients,
+        Local::now().date_naive(),
+        current_open_period_start()?,
+    )
+}
+
+pub fn get_terms() -> Result<Terms, Error> {
+    load_terms()
+}
+
+// A fixed invoice date is for one run: once a run of the stored configuration, whose terms are
+// `run_terms`, has been dated by it, later runs are dated the day they are generated again
+pub fn clear_used_invoice_date(run_terms: &str) -> Result<(), Error> {
+    let stored = get_setting(TERMS_KEY)?.unwrap_or_default();
+    let mut terms = parse_terms(&stored)?;
+    if stored != run_terms || terms.invoice_date.trim().is_empty() {
+        return Ok(());
+    }
+    terms.invoice_date.clear();
+    save_terms(terms)
+}
+
+pub fn save_terms(terms: Terms) -> Result<(), Error> {
+    validate_terms(&terms)?;
+    let value = serde_json::to_string(&terms)
+        .map_err(|e| Error::invalid("Failed to serialize payment terms").details(e))?;
+    set_setting(TERMS_KEY, &value)
+}
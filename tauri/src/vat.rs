@@ -0,0 +1,203 @@
+use crate::errors::Error;
+use crate::money::{self, Currency, Money};
+use crate::settings::{get_setting, set_setting};
+use serde::{Deserialize, Serialize};
+
+// Setting key holding the sender's VAT registration as JSON
+pub const VAT_KEY: &str = "vat";
+
+// Norwegian VAT rates: general, food, and transport/accommodation/culture, plus zero-rated
+pub const NORWEGIAN_RATES: [u32; 4] = [25, 15, 12, 0];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum VatTreatment {
+    #[default]
+    Standard,
+    // Outside the scope of VAT, e.g. exempt services; no VAT is charged
+    Exempt,
+    // The buyer accounts for the VAT; no VAT is charged, but the invoice must say so
+    ReverseCharge,
+}
+
+impl VatTreatment {
+    // Name used in run plans and the database, the same as in JSON
+    pub fn code(&self) -> &'static str {
+        match self {
+            VatTreatment::Standard => "standard",
+            VatTreatment::Exempt => "exempt",
+            VatTreatment::ReverseCharge => "reverse_charge",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Result<VatTreatment, Error> {
+        [
+            VatTreatment::Standard,
+            VatTreatment::Exempt,
+            VatTreatment::ReverseCharge,
+        ]
+        .into_iter()
+        .find(|t| t.code() == code)
+        .ok_or_else(|| Error::invalid(format!("Unknown VAT treatment '{}'", code)))
+    }
+}
+
+// VAT registration of the sender. Line items may override the rate and treatment.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct VatSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_rate")]
+    pub rate: u32,
+    #[serde(default)]
+    pub treatment: VatTreatment,
+}
+
+fn default_rate() -> u32 {
+    25
+}
+
+impl Default for VatSettings {
+    fn default() -> Self {
+        VatSettings {
+            enabled: false,
+            rate: default_rate(),
+            treatment: VatTreatment::Standard,
+        }
+    }
+}
+
+// The rate and treatment that apply to one amount
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VatCode {
+    pub rate: u32,
+    pub treatment: VatTreatment,
+}
+
+impl VatCode {
+    // No VAT at all, for senders that are not registered
+    pub const NONE: VatCode = VatCode {
+        rate: 0,
+        treatment: VatTreatment::Standard,
+    };
+
+    // VAT on a net amount, rounded half away from zero; exempt and reverse charge amounts carry none
+    pub fn vat_on(&self, net_minor: i64) -> i64 {
+        match self.treatment {
+            VatTreatment::Standard => {
+                money::div_round(net_minor as i128 * self.rate as i128, 100) as i64
+            }
+            VatTreatment::Exempt | VatTreatment::ReverseCharge => 0,
+        }
+    }
+}
+
+fn check_rate(rate: u32) -> Result<(), Error> {
+    if NORWEGIAN_RATES.contains(&rate) {
+        Ok(())
+    } else {
+        Err(Error::invalid(format!(
+            "VAT rate {}% is not a Norwegian rate ({})",
+            rate,
+            NORWEGIAN_RATES
+                .iter()
+                .map(|r| format!("{}%", r))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )))
+    }
+}
+
+impl VatSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        check_rate(self.rate)
+    }
+
+    // Resolve the VAT for an amount, with optional per-line overrides of the sender's rate and treatment
+    pub fn code_for(
+        &self,
+        rate: Option<u32>,
+        treatment: Option<VatTreatment>,
+    ) -> Result<VatCode, Error> {
+        if !self.enabled {
+            return match (rate, treatment) {
+                (None | Some(0), None | Some(VatTreatment::Standard)) => Ok(VatCode::NONE),
+                _ => Err(Error::invalid("VAT is not enabled for the sender")),
+            };
+        }
+
+        let treatment = treatment.unwrap_or(self.treatment);
+        let rate = match treatment {
+            VatTreatment::Standard => rate.unwrap_or(self.rate),
+            VatTreatment::Exempt | VatTreatment::ReverseCharge => 0,
+        };
+        check_rate(rate)?;
+
+        Ok(VatCode { rate, treatment })
+    }
+}
+
+// Net amount and VAT for one rate and treatment on an invoice
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct VatSummaryLine {
+    pub rate: u32,
+    pub treatment: VatTreatment,
+    pub net: String,
+    pub vat: String,
+}
+
+// Group amounts by rate and treatment, highest rate first. VAT is summed from the
+// amounts' own VAT so the summary always matches the invoice lines.
+pub fn summarize(amounts: &[(VatCode, i64, i64)], currency: Currency) -> Vec<VatSummaryLine> {
+    let mut groups: Vec<(VatCode, i64, i64)> = Vec::new();
+
+    for (code, net, vat) in amounts {
+        match groups.iter_mut().find(|(c, _, _)| c == code) {
+            Some(group) => {
+                group.1 += net;
+                group.2 += vat;
+            }
+            None => groups.push((*code, *net, *vat)),
+        }
+    }
+    groups.sort_by(|a, b| {
+        b.0.rate
+            .cmp(&a.0.rate)
+            .then(a.0.treatment.cmp(&b.0.treatment))
+    });
+
+    groups
+        .into_iter()
+        .map(|(code, net, vat)| VatSummaryLine {
+            rate: code.rate,
+            treatment: code.treatment,
+            net: Money::new(net, currency).plain(),
+            vat: Money::new(vat, currency).plain(),
+        })
+        .collect()
+}
+
+pub fn parse_vat_settings(value: &str) -> Result<VatSettings, Error> {
+    if value.trim().is_empty() {
+        return Ok(VatSettings::default());
+    }
+    let settings: VatSettings = serde_json::from_str(value)
+        .map_err(|e| Error::invalid("Invalid VAT settings").details(e))?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+pub fn load_vat_settings() -> Result<VatSettings, Error> {
+    parse_vat_settings(&get_setting(VAT_KEY)?.unwrap_or_default())
+}
+
+pub fn get_vat_settings() -> Result<VatSettings, Error> {
+    load_vat_settings()
+}
+
+pub fn save_vat_settings(settings: VatSettings) -> Result<(), Error> {
+    settings.validate()?;
+    let value = serde_json::to_string(&settings)
+        .map_err(|e| Error::invalid("Failed to serialize VAT settings").details(e))?;
+    set_setting(VAT_KEY, &value)
+}
@@ -0,0 +1,218 @@
+// Tests for generation batches against a temporary database
+mod common;
+
+use chrono::Local;
+use common::database;
+use invoice_splitter_lib::batches::{
+    add_months, find_batch, list_batches, next_period_options, record_batch, void_batch, NewBatch,
+};
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::generation::run_generation;
+use invoice_splitter_lib::invoices::{self, NewInvoice};
+use invoice_splitter_lib::renderer::RendererKind;
+use invoice_splitter_lib::runs::{store_run_log, RunLog};
+use invoice_splitter_lib::settings::{
+    get_app_settings, load_config, save_app_settings, store_config_values, AppSettings,
+    InvoiceConfig,
+};
+use invoice_splitter_lib::terms::{load_terms, RunOptions};
+use std::collections::BTreeMap;
+
+// Store invoices the way a run would and record the run as a batch
+fn run(options: RunOptions, customers: &[&str]) -> i64 {
+    let latest_id = invoices::latest_invoice_id().unwrap();
+    let conn = connect_database().unwrap();
+    invoices::create_backend_tables(&conn).unwrap();
+    for _ in customers {
+        let number = invoices::next_invoice_number(&conn).unwrap();
+        invoices::insert_invoice(
+            &conn,
+            &NewInvoice {
+                invoice_number: &number,
+                invoice_date: "2025-01-31",
+                due_date: "2025-02-14",
+                service: "",
+                vat_enabled: false,
+                vat_rate: 0,
+                lines: Vec::new(),
+                pdf: b"%PDF-1.4",
+            },
+        )
+        .unwrap();
+    }
+
+    let run_log_id = store_run_log(&RunLog {
+        started_at: "2025-01-31 10:00:00".to_string(),
+        finished_at: "2025-01-31 10:00:01".to_string(),
+        renderer: "native renderer".to_string(),
+        succeeded: true,
+        detail: "completed in-process".to_string(),
+        ..RunLog::default()
+    })
+    .unwrap();
+
+    let recipients: Vec<String> = customers.iter().map(|c| c.to_string()).collect();
+    record_batch(&NewBatch {
+        run_log_id,
+        config: &InvoiceConfig::default(),
+        options: &options,
+        recipients: &recipients,
+        invoice_date: "2025-01-31",
+        invoice_numbers: &invoices::invoice_numbers_after(latest_id).unwrap(),
+    })
+    .unwrap()
+}
+
+#[test]
+fn batches_keep_their_run_and_invoices() {
+    let _db = database();
+    let first = run(RunOptions::default(), &["Ola", "Kari"]);
+    let second = run(RunOptions::default(), &["Per"]);
+
+    let batches = list_batches(None).unwrap();
+    assert_eq!(batches[0].id, second);
+    assert_eq!(batches[1].id, first);
+
+    let batch = find_batch(first).unwrap();
+    assert_eq!(
+        batch.recipients,
+        vec!["Ola".to_string(), "Kari".to_string()]
+    );
+    assert_eq!(batch.invoice_numbers.len(), 2);
+    assert_eq!(batch.log.detail, "completed in-process");
+    assert_eq!(find_batch(second).unwrap().invoice_numbers.len(), 1);
+    assert!(find_batch(9999).is_err());
+}
+
+#[test]
+fn voiding_a_batch_marks_all_its_invoices_once() {
+    let _db = database();
+    let id = run(RunOptions::default(), &["Ola", "Kari"]);
+    let numbers = find_batch(id).unwrap().invoice_numbers;
+
+    assert_eq!(void_batch(id, "Wrong amount".to_string()).unwrap(), numbers);
+    let batch = find_batch(id).unwrap();
+    assert!(!batch.voided_at.is_empty());
+    assert_eq!(batch.void_reason, "Wrong amount");
+    assert!(void_batch(id, String::new())
+        .unwrap_err()
+        .message
+        .contains("already voided"));
+
+    for invoice in invoices::get_all_invoices().unwrap() {
+        assert_eq!(
+            invoice.voided,
+            numbers.contains(&invoice.invoice_number),
+            "{}",
+            invoice.invoice_number
+        );
+    }
+}
+
+#[test]
+fn reruns_move_the_dates_on_by_whole_months() {
+    let _db = database();
+    let id = run(
+        RunOptions {
+            due_date: Some("2025-02-14".to_string()),
+            ..RunOptions::default()
+        },
+        &["Ola"],
+    );
+    let batch = find_batch(id).unwrap();
+
+    let next = next_period_options(&batch, 1).unwrap();
+    // Dated today, billing for the end of February
+    let today = Local::now().date_naive().format("%Y-%m-%d").to_string();
+    assert_eq!(next.invoice_date, Some(today));
+    assert_eq!(next.period_date.as_deref(), Some("2025-02-28"));
+    assert_eq!(next.due_date, None);
+    assert_eq!(next.payment_days, Some(14));
+
+    let next_quarter = next_period_options(&batch, 3).unwrap();
+    assert_eq!(next_quarter.period_date.as_deref(), Some("2025-04-30"));
+    assert!(next_period_options(&batch, 0).is_err());
+
+    // Month ends stay month ends
+    assert_eq!(add_months("2025-02-28", 1).unwrap(), "2025-03-31");
+    assert_eq!(add_months("2025-01-30", 1).unwrap(), "2025-02-28");
+    assert_eq!(add_months("2024-02-29", 12).unwrap(), "2025-02-28");
+}
+
+#[test]
+fn runs_whose_batch_is_not_recorded_say_so_in_their_output() {
+    let _db = database();
+    save_app_settings(AppSettings {
+        renderer: RendererKind::Native,
+        ..get_app_settings().unwrap()
+    })
+    .unwrap();
+    let values: BTreeMap<String, String> = [
+        ("sender", "Utleier AS"),
+        ("bankdetails", "Konto: 1234.56.78901"),
+        ("description", "Strøm"),
+        ("amount", "300"),
+        ("recipients", "Nils"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let config = InvoiceConfig::default().with_values(&values).unwrap();
+
+    // Run logs are still stored, as they only read which run each batch came from
+    connect_database()
+        .unwrap()
+        .execute_batch(
+            "DROP TABLE batches; CREATE TABLE batches (id INTEGER PRIMARY KEY, run_log_id INTEGER);",
+        )
+        .unwrap();
+    let result = run_generation(&config, false, &RunOptions::default(), None).unwrap();
+    assert_eq!(result.invoice_numbers.len(), 1);
+    assert_eq!(result.batch_id, None);
+    assert!(
+        result.output.starts_with("Warning: Batch not recorded: "),
+        "{}",
+        result.output
+    );
+
+    // The table is created again on the next connection
+    connect_database()
+        .unwrap()
+        .execute_batch("DROP TABLE batches;")
+        .unwrap();
+}
+
+#[test]
+fn a_fixed_invoice_date_dates_one_run_only() {
+    let _db = database();
+    save_app_settings(AppSettings {
+        renderer: RendererKind::Native,
+        ..get_app_settings().unwrap()
+    })
+    .unwrap();
+    let today = Local::now().date_naive().format("%Y-%m-%d").to_string();
+    let values: BTreeMap<String, String> = [
+        ("sender", "Utleier AS".to_string()),
+        ("bankdetails", "Konto: 1234.56.78901".to_string()),
+        ("description", "Vann".to_string()),
+        ("amount", "150".to_string()),
+        ("recipients", "Sofie".to_string()),
+        (
+            "terms",
+            format!(r#"{{"invoice_date": "{}", "payment_days": 10}}"#, today),
+        ),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v))
+    .collect();
+    store_config_values(&values).unwrap();
+
+    // A dry run leaves it for the real one
+    run_generation(&load_config().unwrap(), true, &RunOptions::default(), None).unwrap();
+    assert_eq!(load_terms().unwrap().invoice_date, today);
+
+    run_generation(&load_config().unwrap(), false, &RunOptions::default(), None).unwrap();
+    let terms = load_terms().unwrap();
+    assert_eq!(terms.invoice_date, "");
+    assert_eq!(terms.payment_days, 10);
+}
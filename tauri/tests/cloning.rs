@@ -0,0 +1,108 @@
+// Tests for copying stored invoices into drafts and the configuration against a temporary database
+mod common;
+
+use common::database;
+use invoice_splitter_lib::cloning::{clone_invoice_to_draft, clone_invoice_to_settings};
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::invoices::{self, InvoiceParties, NewInvoice, StoredLine};
+use invoice_splitter_lib::line_items::LineItem;
+use invoice_splitter_lib::revisions::{correct_invoice, load_invoice_data};
+use invoice_splitter_lib::settings::load_config;
+use invoice_splitter_lib::terms::parse_terms;
+use invoice_splitter_lib::vat::parse_vat_settings;
+
+fn line(date: &str, description: &str, price: &str) -> StoredLine {
+    StoredLine {
+        date: date.to_string(),
+        description: description.to_string(),
+        price: price.to_string(),
+    }
+}
+
+// Store an invoice the way a run would, returning its id
+fn store(lines: Vec<StoredLine>) -> i32 {
+    let conn = connect_database().unwrap();
+    invoices::create_backend_tables(&conn).unwrap();
+    let number = invoices::next_invoice_number(&conn).unwrap();
+    invoices::insert_invoice(
+        &conn,
+        &NewInvoice {
+            invoice_number: &number,
+            invoice_date: "2025-01-31",
+            due_date: "2025-02-14",
+            service: "",
+            vat_enabled: false,
+            vat_rate: 0,
+            lines,
+            pdf: b"%PDF-1.4",
+        },
+    )
+    .unwrap();
+    invoices::store_invoice_parties(
+        &number,
+        &InvoiceParties {
+            currency: "NOK".to_string(),
+            sender: vec!["Utleier AS".to_string()],
+            customer_name: "Kari".to_string(),
+            customer_address: vec!["Lillegata 2".to_string()],
+            bank_details: vec!["Konto: 1234.56.78901".to_string()],
+        },
+    )
+    .unwrap();
+    conn.query_row(
+        "SELECT id FROM invoices WHERE invoice_number = ?",
+        [&number],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn drafts_copy_the_latest_data_with_the_dates_moved_on() {
+    let _db = database();
+    let id = store(vec![line("2025-01-15", "Husleie", "1000")]);
+    let mut corrected = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+    corrected.lines[0].price = "1100".to_string();
+    correct_invoice(id, corrected, "Wrong amount".to_string()).unwrap();
+
+    let draft = clone_invoice_to_draft(id, None).unwrap();
+    assert!(draft.id > 0);
+    assert_eq!(draft.invoice_number, "");
+    assert_eq!(draft.customer_name, "Kari");
+    // The end of January moves to the end of February
+    assert_eq!(draft.invoice_date, "2025-02-28");
+    assert_eq!(draft.due_date, "2025-03-14");
+    assert_eq!(draft.lines[0].date, "2025-02-15");
+    assert_eq!(draft.lines[0].amount, "1100");
+
+    let quarter = clone_invoice_to_draft(id, Some(3)).unwrap();
+    assert_eq!(quarter.invoice_date, "2025-04-30");
+    assert!(clone_invoice_to_draft(99999, None).is_err());
+}
+
+#[test]
+fn settings_bill_the_customer_alone_the_same_again() {
+    let _db = database();
+    let id = store(vec![
+        line("", "Husleie", "1000"),
+        line("uke 3", "Parkering", "250.50"),
+    ]);
+
+    clone_invoice_to_settings(id, Some(1)).unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.get("recipients"), "Kari\nLillegata 2");
+    assert_eq!(config.get("sender"), "Utleier AS");
+    assert_eq!(config.get("amount"), "1250.50");
+    assert_eq!(config.get("split"), "");
+    assert!(!parse_vat_settings(config.get("vat")).unwrap().enabled);
+
+    let items: Vec<LineItem> = serde_json::from_str(config.get("line_items")).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[1].unit_price, "250.50");
+    // Free-text line dates are kept as they are
+    assert_eq!(items[1].date, "uke 3");
+
+    let terms = parse_terms(config.get("terms")).unwrap();
+    assert_eq!(terms.invoice_date, "2025-02-28");
+    assert_eq!(terms.payment_days, 14);
+}
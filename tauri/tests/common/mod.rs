@@ -0,0 +1,23 @@
+// Shared by the integration tests that need a database
+use invoice_splitter_lib::database::DATABASE_PATH_ENV;
+use invoice_splitter_lib::settings::DATA_DIR_ENV;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tempfile::TempDir;
+
+// A temporary database and data directory, with PDFs exported to "out" inside it. The tests of
+// one file share them, so they run one at a time.
+pub fn database() -> MutexGuard<'static, TempDir> {
+    static DIR: OnceLock<Mutex<TempDir>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let data_dir = dir.path().join("data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::env::set_var(DATABASE_PATH_ENV, dir.path().join("invoices.db"));
+        std::env::set_var(DATA_DIR_ENV, &data_dir);
+        let settings = serde_json::json!({ "output_directory": dir.path().join("out") });
+        std::fs::write(data_dir.join("settings.json"), settings.to_string()).unwrap();
+        Mutex::new(dir)
+    })
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+}
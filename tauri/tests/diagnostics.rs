@@ -0,0 +1,57 @@
+// Tests for the environment checks
+use invoice_splitter_lib::diagnostics::{
+    check_backend_binary, check_disk_space, check_local_api, check_writable, CheckStatus,
+};
+
+#[test]
+fn writable_directories_are_created_and_left_clean() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("invoices").join("2025");
+
+    let check = check_writable("Output directory", &output);
+    assert_eq!(check.status, CheckStatus::Ok, "{:?}", check);
+    assert!(check.fix.is_empty());
+    assert!(output.exists());
+    assert_eq!(std::fs::read_dir(&output).unwrap().count(), 0);
+}
+
+#[test]
+fn unwritable_directories_fail_with_a_fix() {
+    let dir = tempfile::tempdir().unwrap();
+    // A file where the directory should be
+    let blocked = dir.path().join("blocked");
+    std::fs::write(&blocked, "not a directory").unwrap();
+
+    let check = check_writable("Output directory", &blocked.join("out"));
+    assert_eq!(check.status, CheckStatus::Failed);
+    assert!(!check.fix.is_empty());
+}
+
+#[test]
+fn a_missing_backend_binary_fails_with_a_fix() {
+    let dir = tempfile::tempdir().unwrap();
+    let check = check_backend_binary(dir.path());
+    assert_eq!(check.status, CheckStatus::Failed);
+    assert!(check.detail.contains("main.exe"), "{}", check.detail);
+    assert!(check.fix.contains("dune build"));
+}
+
+#[test]
+fn disk_space_is_read_from_the_nearest_existing_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let check = check_disk_space("Disk space", &dir.path().join("not").join("yet"));
+    assert_ne!(check.status, CheckStatus::Failed);
+    assert!(check.detail.contains("MB"), "{}", check.detail);
+}
+
+#[test]
+fn a_local_api_that_did_not_start_is_a_warning() {
+    let listening = check_local_api(&Ok("127.0.0.1:17878".parse().unwrap()));
+    assert_eq!(listening.status, CheckStatus::Ok);
+    assert_eq!(listening.detail, "Listening on 127.0.0.1:17878");
+
+    let failed = check_local_api(&Err("The local API requires a token".to_string()));
+    assert_eq!(failed.status, CheckStatus::Warning);
+    assert!(failed.detail.contains("requires a token"));
+    assert!(failed.fix.contains("Settings"));
+}
@@ -0,0 +1,187 @@
+// Tests for draft invoices against a temporary database
+mod common;
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Days, Local, Months, NaiveDate};
+use common::database;
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::drafts::{
+    delete_draft, finalize_draft, find_draft, preview_draft, save_draft, Draft, DraftLine,
+};
+use invoice_splitter_lib::duplicates;
+use invoice_splitter_lib::errors::Error;
+use invoice_splitter_lib::invoices;
+use invoice_splitter_lib::money::{Money, NOK};
+use invoice_splitter_lib::renderer::RendererKind;
+use invoice_splitter_lib::revisions::NATIVE_RENDER_WARNING;
+use invoice_splitter_lib::settings::get_app_settings;
+use invoice_splitter_lib::vat::{VatSummaryLine, VatTreatment};
+
+// A draft dated `invoice_date`, due two weeks later
+fn dated(invoice_date: NaiveDate, amount: &str) -> Draft {
+    Draft {
+        customer_name: "Kari".to_string(),
+        customer_address: vec!["Lillegata 2".to_string()],
+        invoice_date: invoice_date.to_string(),
+        due_date: (invoice_date + Days::new(14)).to_string(),
+        sender: vec!["Utleier AS".to_string()],
+        bank_details: vec!["Konto: 1234.56.78901".to_string()],
+        lines: vec![DraftLine {
+            date: String::new(),
+            description: "Husleie".to_string(),
+            amount: amount.to_string(),
+            vat_rate: 0,
+            vat_treatment: VatTreatment::Standard,
+        }],
+        ..Draft::default()
+    }
+}
+
+fn draft(amount: &str) -> Draft {
+    dated(Local::now().date_naive(), amount)
+}
+
+fn next_number() -> String {
+    invoices::peek_invoice_numbers(1).unwrap().remove(0)
+}
+
+#[test]
+fn drafts_are_numbered_on_finalization_and_locked_after() {
+    let _db = database();
+    let number = next_number();
+
+    let saved = save_draft(draft("1000")).unwrap();
+    assert!(saved.id > 0);
+    assert_eq!(saved.invoice_number, "");
+    let edited = save_draft(Draft {
+        lines: draft("1250.50").lines,
+        ..saved.clone()
+    })
+    .unwrap();
+    assert_eq!(edited.id, saved.id);
+    assert_eq!(edited.lines[0].amount, "1250.50");
+    // Saving and editing use up no invoice number
+    assert_eq!(next_number(), number);
+
+    let finalized = finalize_draft(saved.id).unwrap();
+    assert_eq!(finalized.invoice_number, number);
+    assert!(!finalized.finalized_at.is_empty());
+    // Rendered natively while the OCaml backend is configured
+    assert_eq!(get_app_settings().unwrap().renderer, RendererKind::Ocaml);
+    assert_eq!(finalized.warnings, vec![NATIVE_RENDER_WARNING.to_string()]);
+    // Keyed like a generated invoice, for the duplicate check
+    let key = duplicates::key(
+        "Kari",
+        &finalized.invoice_date,
+        "Husleie",
+        &Money::new(125050, NOK),
+    )
+    .unwrap();
+    let recorded: String = connect_database()
+        .unwrap()
+        .query_row(
+            "SELECT invoice_number FROM invoice_keys WHERE dedup_key = ?",
+            [&key],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(recorded, number);
+    assert!(invoices::get_all_invoices()
+        .unwrap()
+        .iter()
+        .any(|invoice| invoice.invoice_number == number));
+
+    let locked =
+        |result: Result<(), Error>| result.unwrap_err().message.contains("no longer be changed");
+    assert!(locked(save_draft(finalized.clone()).map(|_| ())));
+    assert!(locked(delete_draft(saved.id)));
+    assert!(locked(finalize_draft(saved.id).map(|_| ())));
+    assert_eq!(find_draft(saved.id).unwrap().lines[0].amount, "1250.50");
+}
+
+#[test]
+fn previews_are_watermarked_and_unnumbered() {
+    let _db = database();
+    let number = next_number();
+
+    let pdf = general_purpose::STANDARD
+        .decode(preview_draft(draft("500")).unwrap())
+        .unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.contains("(UTKAST) Tj"));
+    assert!(text.contains("(Fakturanummer: UTKAST) Tj"));
+    assert_eq!(next_number(), number);
+}
+
+#[test]
+fn incomplete_drafts_are_kept_but_not_finalized() {
+    let _db = database();
+    let number = next_number();
+
+    let saved = save_draft(Draft {
+        due_date: String::new(),
+        ..draft("100")
+    })
+    .unwrap();
+    assert!(finalize_draft(saved.id).is_err());
+    assert_eq!(find_draft(saved.id).unwrap().invoice_number, "");
+    assert_eq!(next_number(), number);
+
+    assert!(save_draft(draft("abc")).is_err());
+    delete_draft(saved.id).unwrap();
+    assert!(find_draft(saved.id).is_err());
+}
+
+#[test]
+fn finalized_drafts_keep_their_vat_terms_and_parties() {
+    let _db = database();
+    let mut vat_draft = draft("1000");
+    vat_draft.vat_enabled = true;
+    vat_draft.lines[0].vat_rate = 25;
+    let finalized = finalize_draft(save_draft(vat_draft).unwrap().id).unwrap();
+
+    let invoice = invoices::get_all_invoices()
+        .unwrap()
+        .into_iter()
+        .find(|invoice| invoice.invoice_number == finalized.invoice_number)
+        .unwrap();
+    let invoice = invoices::get_invoice_by_id(invoice.id).unwrap();
+    assert_eq!(invoice.payment_days, Some(14));
+    assert_eq!(
+        invoice.vat_summary,
+        vec![VatSummaryLine {
+            rate: 25,
+            treatment: VatTreatment::Standard,
+            net: "1000.00".to_string(),
+            vat: "250.00".to_string(),
+        }]
+    );
+    let parties =
+        invoices::load_invoice_parties(&connect_database().unwrap(), &finalized.invoice_number)
+            .unwrap()
+            .unwrap();
+    assert_eq!(parties.customer_name, "Kari");
+    assert_eq!(parties.bank_details, vec!["Konto: 1234.56.78901"]);
+}
+
+#[test]
+fn drafts_are_dated_like_generation_runs() {
+    let _db = database();
+    let number = next_number();
+    let today = Local::now().date_naive();
+
+    let tomorrow = save_draft(dated(today + Days::new(1), "100")).unwrap();
+    let error = finalize_draft(tomorrow.id).unwrap_err();
+    assert!(error.message.contains("in the future"), "{}", error);
+
+    let closed = save_draft(dated(today - Months::new(2), "100")).unwrap();
+    let error = finalize_draft(closed.id).unwrap_err();
+    assert!(
+        error.message.contains("start of the open period"),
+        "{}",
+        error
+    );
+
+    assert_eq!(find_draft(closed.id).unwrap().invoice_number, "");
+    assert_eq!(next_number(), number);
+}
@@ -0,0 +1,96 @@
+// Tests for the duplicate invoice check against a temporary database
+mod common;
+
+use common::database;
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::duplicates::{
+    blocked_error, find_duplicates, invoice_key, record_invoice_key, DUPLICATES_BLOCKED,
+};
+use invoice_splitter_lib::errors::ErrorCode;
+use invoice_splitter_lib::money::{Money, NOK};
+use invoice_splitter_lib::run_plan::RunPlan;
+use invoice_splitter_lib::terms::InvoiceDates;
+
+fn plan(invoice_date: &str, bills: &[(&str, &str, i64)]) -> RunPlan {
+    RunPlan {
+        settings: Vec::new(),
+        currency: NOK,
+        recipients: bills.iter().map(|(name, _, _)| name.to_string()).collect(),
+        amounts: bills
+            .iter()
+            .map(|(_, _, minor)| Money::new(*minor, NOK))
+            .collect(),
+        descriptions: bills.iter().map(|(_, text, _)| text.to_string()).collect(),
+        lines: Vec::new(),
+        vat: Vec::new(),
+        dates: bills
+            .iter()
+            .map(|_| InvoiceDates {
+                invoice_date: invoice_date.to_string(),
+                due_date: invoice_date.to_string(),
+                payment_days: 14,
+                period_date: invoice_date.to_string(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn keys_ignore_case_and_spacing_but_not_period_or_amount() {
+    let january = plan("2025-01-10", &[("Ola", "Rent  January", 50000)]);
+    let same = plan("2025-01-31", &[("ola", "rent january", 50000)]);
+    assert_eq!(invoice_key(&january, 0), invoice_key(&same, 0));
+
+    let february = plan("2025-02-01", &[("Ola", "Rent January", 50000)]);
+    let cheaper = plan("2025-01-10", &[("Ola", "Rent January", 49999)]);
+    assert_ne!(invoice_key(&january, 0), invoice_key(&february, 0));
+    assert_ne!(invoice_key(&january, 0), invoice_key(&cheaper, 0));
+    assert_eq!(invoice_key(&january, 1), None);
+}
+
+#[test]
+fn recent_invoices_with_the_same_key_are_duplicates() {
+    let _db = database();
+    let run = plan(
+        "2025-03-31",
+        &[("Ola", "Rent", 50000), ("Kari", "Rent", 50000)],
+    );
+    assert!(find_duplicates(&run, 31).unwrap().is_empty());
+
+    record_invoice_key("D-1", &invoice_key(&run, 1).unwrap()).unwrap();
+    let found = find_duplicates(&run, 31).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].recipient, "Kari");
+    assert_eq!(found[0].invoice_number, "D-1");
+
+    let error = blocked_error(&found);
+    assert_eq!(error.code, ErrorCode::DuplicatesBlocked);
+    assert_eq!(error.message, DUPLICATES_BLOCKED);
+    assert!(error.to_string().contains("invoice D-1"), "{}", error);
+}
+
+#[test]
+fn old_and_voided_invoices_are_not_duplicates() {
+    let _db = database();
+    let run = plan("2025-04-30", &[("Per", "Parking", 20000)]);
+    let key = invoice_key(&run, 0).unwrap();
+    let conn = connect_database().unwrap();
+
+    record_invoice_key("D-2", &key).unwrap();
+    conn.execute(
+        "UPDATE invoice_keys SET created_at = datetime('now', '-40 days') WHERE invoice_number = 'D-2'",
+        [],
+    )
+    .unwrap();
+    assert!(find_duplicates(&run, 31).unwrap().is_empty());
+    // A window of 0 looks back without limit
+    assert_eq!(find_duplicates(&run, 0).unwrap().len(), 1);
+
+    record_invoice_key("D-3", &key).unwrap();
+    conn.execute(
+        "INSERT INTO voided_invoices (invoice_number, batch_id, reason) VALUES ('D-3', 1, '')",
+        [],
+    )
+    .unwrap();
+    assert!(find_duplicates(&run, 31).unwrap().is_empty());
+}
@@ -0,0 +1,196 @@
+// Tests for the codes errors are raised with and the structured errors commands return
+mod common;
+
+use common::database;
+use invoice_splitter_lib::commands;
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::duplicates;
+use invoice_splitter_lib::errors::{CommandError, Error, ErrorCategory, ErrorCode};
+use invoice_splitter_lib::runs::{self, output_with_timeout, process_error};
+use std::process::Command;
+use std::time::Duration;
+
+#[test]
+fn commands_fail_with_a_code_category_and_action() {
+    let _db = database();
+
+    let missing = commands::get_invoice_by_id(99999).map(|_| ()).unwrap_err();
+    assert_eq!(missing.code, ErrorCode::NotFound);
+    assert_eq!(missing.category, ErrorCategory::Validation);
+    assert_eq!(missing.message, "Invoice 99999 not found");
+    assert_eq!(missing.details, "");
+    assert_eq!(missing.action, ErrorCode::NotFound.action());
+}
+
+#[test]
+fn invalid_values_are_invalid_input() {
+    let _db = database();
+
+    let amount = commands::set_config_setting("amount".to_string(), "abc".to_string()).unwrap_err();
+    assert_eq!(amount.code, ErrorCode::InvalidInput);
+
+    let unknown = commands::write_file("other.txt".to_string(), String::new()).unwrap_err();
+    assert_eq!(unknown.code, ErrorCode::InvalidInput);
+}
+
+#[test]
+fn missing_records_are_not_found() {
+    let _db = database();
+
+    let template = commands::delete_template("monthly".to_string()).unwrap_err();
+    assert_eq!(template.code, ErrorCode::NotFound);
+    assert_eq!(template.message, "Template 'monthly' not found");
+
+    let draft = commands::finalize_draft(99999).unwrap_err();
+    assert_eq!(draft.code, ErrorCode::NotFound);
+}
+
+#[test]
+fn changed_configurations_are_stale_previews() {
+    let _db = database();
+
+    let stale = commands::confirm_generation("stale".to_string(), None).unwrap_err();
+    assert_eq!(stale.code, ErrorCode::StalePreview);
+}
+
+#[test]
+fn runs_while_another_holds_the_lock_are_in_progress() {
+    let _db = database();
+
+    let _lock = runs::lock_database().unwrap();
+    let busy = commands::finalize_draft(1).unwrap_err();
+    assert_eq!(busy.code, ErrorCode::RunInProgress);
+    assert_eq!(busy.category, ErrorCategory::Backend);
+}
+
+#[test]
+fn damaged_tables_are_database_errors() {
+    let _db = database();
+
+    connect_database()
+        .unwrap()
+        .execute_batch("DROP TABLE drafts; CREATE TABLE drafts (id INTEGER PRIMARY KEY);")
+        .unwrap();
+    let damaged = commands::list_drafts().unwrap_err();
+    assert_eq!(damaged.code, ErrorCode::DatabaseError);
+    assert_eq!(damaged.category, ErrorCategory::Database);
+
+    // The table is created again on the next connection
+    connect_database()
+        .unwrap()
+        .execute_batch("DROP TABLE drafts;")
+        .unwrap();
+}
+
+#[test]
+fn unwritable_destinations_are_filesystem_errors() {
+    let _db = database();
+    let dir = tempfile::tempdir().unwrap();
+
+    let destination = dir.path().join("missing").join("backup.db");
+    let failed = commands::backup_database(Some(destination.to_string_lossy().to_string()))
+        .err()
+        .unwrap();
+    assert_eq!(failed.code, ErrorCode::FilesystemError);
+    assert_eq!(failed.category, ErrorCategory::Filesystem);
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_and_stopped_backends_are_backend_errors() {
+    let missing = output_with_timeout(&mut Command::new("/nonexistent/backend"), None)
+        .err()
+        .unwrap();
+    assert_eq!(missing.code, ErrorCode::BackendFailed);
+
+    let output = output_with_timeout(
+        Command::new("sh").args(["-c", "echo Fatal error >&2; exit 2"]),
+        None,
+    )
+    .unwrap();
+    let failed = process_error(&output, 300);
+    assert_eq!(failed.code, ErrorCode::BackendFailed);
+    assert_eq!(failed.message, "The backend exited with code 2");
+    assert_eq!(failed.details.as_deref(), Some("Fatal error"));
+    assert_eq!(
+        failed.to_string(),
+        "The backend exited with code 2: Fatal error"
+    );
+
+    let output = output_with_timeout(
+        Command::new("sh").args(["-c", "sleep 30"]),
+        Some(Duration::from_millis(300)),
+    )
+    .unwrap();
+    let stopped = process_error(&output, 1);
+    assert_eq!(stopped.code, ErrorCode::BackendTimeout);
+    assert_eq!(CommandError::from(stopped).category, ErrorCategory::Backend);
+}
+
+#[test]
+fn details_are_kept_apart_from_the_message() {
+    let error = Error::database("Failed to get drafts")
+        .details("database is locked")
+        .context("Loading");
+    assert_eq!(error.message, "Loading: Failed to get drafts");
+    assert_eq!(
+        error.to_string(),
+        "Loading: Failed to get drafts: database is locked"
+    );
+
+    // Copied across as they are, without reading them out of the message
+    let command = CommandError::from(Error::invalid("Invalid amount 'abc': not a number"));
+    assert_eq!(command.message, "Invalid amount 'abc': not a number");
+    assert_eq!(command.details, "");
+}
+
+#[test]
+fn codes_do_not_depend_on_the_wording() {
+    let error = Error::database("Template 'monthly' not found").context("Loading");
+    assert_eq!(error.code, ErrorCode::DatabaseError);
+    assert_eq!(error.message, "Loading: Template 'monthly' not found");
+    assert_eq!(CommandError::from(error).code, ErrorCode::DatabaseError);
+}
+
+#[test]
+fn errors_serialize_with_stable_snake_case_codes() {
+    let error =
+        CommandError::from(Error::database("Failed to get drafts").details("database is locked"));
+    assert_eq!(
+        serde_json::to_value(error).unwrap(),
+        serde_json::json!({
+            "code": "database_error",
+            "category": "database",
+            "message": "Failed to get drafts",
+            "details": "database is locked",
+            "action": ErrorCode::DatabaseError.action(),
+        })
+    );
+}
+
+#[test]
+fn blocked_duplicates_keep_their_list_as_details() {
+    let blocked = CommandError::from(duplicates::blocked_error(&[]));
+    assert_eq!(blocked.code, ErrorCode::DuplicatesBlocked);
+    assert_eq!(blocked.message, duplicates::DUPLICATES_BLOCKED);
+    // The advice to force the run is the action, not repeated in the details
+    assert_eq!(blocked.details, "");
+    assert_eq!(blocked.to_string(), duplicates::DUPLICATES_BLOCKED);
+
+    let duplicate = |recipient: &str| duplicates::Duplicate {
+        recipient: recipient.to_string(),
+        invoice_number: "D-1".to_string(),
+        generated_at: "2025-01-15".to_string(),
+    };
+    let listed = CommandError::from(duplicates::blocked_error(&[duplicate("Kari")]));
+    assert_eq!(
+        listed.details,
+        "- 'Kari' was already billed for this period, service and amount on invoice D-1 (2025-01-15)"
+    );
+
+    // A list of several starts on its own line where the error is printed
+    let both = duplicates::blocked_error(&[duplicate("Kari"), duplicate("Ola")]);
+    assert!(both
+        .to_string()
+        .starts_with(&format!("{}:\n- 'Kari'", duplicates::DUPLICATES_BLOCKED)));
+}
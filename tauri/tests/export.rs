@@ -0,0 +1,179 @@
+// Tests for reading run manifests and exporting the PDFs they list
+use invoice_splitter_lib::export::{
+    export_files, read_manifest, validate_template, CollisionPolicy, ManifestEntry, NamingTemplates,
+};
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_NAMING: NamingTemplates = NamingTemplates {
+    filename: "",
+    subfolder: "",
+};
+
+// A PDF as a backend run leaves it, with its manifest entry
+fn produced(dir: &Path, number: &str, customer: &str, content: &str) -> ManifestEntry {
+    let path = dir.join(format!("{}.pdf", number));
+    fs::write(&path, content).unwrap();
+    ManifestEntry {
+        path,
+        invoice_number: number.to_string(),
+        customer: customer.to_string(),
+        service: "Husleie".to_string(),
+        invoice_date: "2025-01-31".to_string(),
+    }
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn manifests_tolerate_blank_short_and_long_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(read_manifest(dir.path(), &dir.path().join("missing.txt"))
+        .unwrap()
+        .is_empty());
+
+    let manifest = dir.path().join("manifest.txt");
+    fs::write(
+        &manifest,
+        "out/2025-001.pdf\t2025-001\tKari\tHusleie\t2025-01-31\n\
+         \n   \n\
+         out/2025-002.pdf\t2025-002\n\
+         \x20out/2025-003.pdf \t 2025-003 \tOla\tHusleie\t2025-01-31\textra\n",
+    )
+    .unwrap();
+
+    let entries = read_manifest(dir.path(), &manifest).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].path, dir.path().join("out/2025-001.pdf"));
+    assert_eq!(entries[0].customer, "Kari");
+    assert_eq!(entries[0].invoice_date, "2025-01-31");
+    // Missing fields are left empty
+    assert_eq!(entries[1].invoice_number, "2025-002");
+    assert_eq!(entries[1].customer, "");
+    assert_eq!(entries[1].invoice_date, "");
+    // Fields are trimmed and extra ones ignored
+    assert_eq!(entries[2].path, dir.path().join("out/2025-003.pdf"));
+    assert_eq!(entries[2].invoice_number, "2025-003");
+    assert_eq!(entries[2].invoice_date, "2025-01-31");
+}
+
+#[test]
+fn templates_name_files_and_subfolders() {
+    let source = tempfile::tempdir().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let entries = [
+        produced(source.path(), "2025-001", "Kari/Nordmann", "a"),
+        produced(source.path(), "2025-002", "CON", "b"),
+    ];
+
+    let naming = NamingTemplates {
+        filename: "{number} {customer}",
+        subfolder: "{year}/{month}",
+    };
+    let report = export_files(&entries, target.path(), &naming, CollisionPolicy::Suffix).unwrap();
+    // Each value is made safe on its own: no separators, and device names are escaped
+    let folder = target.path().join("2025").join("01");
+    assert_eq!(
+        files(&folder),
+        vec!["2025-001 Kari_Nordmann.pdf", "2025-002 _CON.pdf"]
+    );
+    assert_eq!(report.copied.len(), 2);
+    assert!(report.skipped.is_empty());
+
+    assert!(validate_template("{number}-{prev_month_name}").is_ok());
+    assert!(validate_template("{amount}").is_err());
+    assert!(validate_template("{number").is_err());
+}
+
+#[test]
+fn collisions_follow_the_policy() {
+    let source = tempfile::tempdir().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    let first = [produced(source.path(), "2025-001", "Kari", "first")];
+    export_files(
+        &first,
+        target.path(),
+        &DEFAULT_NAMING,
+        CollisionPolicy::Suffix,
+    )
+    .unwrap();
+    let second = [produced(source.path(), "2025-001", "Kari", "second")];
+
+    let report = export_files(
+        &second,
+        target.path(),
+        &DEFAULT_NAMING,
+        CollisionPolicy::Skip,
+    )
+    .unwrap();
+    assert!(report.copied.is_empty());
+    assert_eq!(report.skipped, vec!["invoice-2025-001.pdf"]);
+    assert_eq!(
+        fs::read_to_string(target.path().join("invoice-2025-001.pdf")).unwrap(),
+        "first"
+    );
+
+    for _ in 0..2 {
+        export_files(
+            &second,
+            target.path(),
+            &DEFAULT_NAMING,
+            CollisionPolicy::Suffix,
+        )
+        .unwrap();
+    }
+    assert_eq!(
+        files(target.path()),
+        vec![
+            "invoice-2025-001 (1).pdf",
+            "invoice-2025-001 (2).pdf",
+            "invoice-2025-001.pdf"
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(target.path().join("invoice-2025-001 (2).pdf")).unwrap(),
+        "second"
+    );
+
+    let report = export_files(
+        &second,
+        target.path(),
+        &DEFAULT_NAMING,
+        CollisionPolicy::Overwrite,
+    )
+    .unwrap();
+    assert_eq!(report.copied, vec!["invoice-2025-001.pdf"]);
+    assert_eq!(files(target.path()).len(), 3);
+    assert_eq!(
+        fs::read_to_string(target.path().join("invoice-2025-001.pdf")).unwrap(),
+        "second"
+    );
+}
+
+#[test]
+fn a_missing_source_file_fails_the_export() {
+    let target = tempfile::tempdir().unwrap();
+    let missing = ManifestEntry {
+        path: target.path().join("gone.pdf"),
+        invoice_number: "2025-009".to_string(),
+        customer: String::new(),
+        service: String::new(),
+        invoice_date: String::new(),
+    };
+    let error = export_files(
+        &[missing],
+        target.path(),
+        &DEFAULT_NAMING,
+        CollisionPolicy::Suffix,
+    )
+    .err()
+    .unwrap();
+    assert!(error.message.contains("invoice-2025-009.pdf"), "{}", error);
+}
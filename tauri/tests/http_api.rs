@@ -0,0 +1,268 @@
+// Integration tests for the local HTTP API against a temporary database
+use invoice_splitter_lib::api;
+use invoice_splitter_lib::database::{connect_database, DATABASE_PATH_ENV};
+use invoice_splitter_lib::invoices;
+use invoice_splitter_lib::settings::DATA_DIR_ENV;
+use rusqlite::Connection;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+const TOKEN: &str = "test-token-0123456789";
+const PDF_BYTES: &[u8] = b"%PDF-1.4 test invoice";
+
+struct TestEnv {
+    _dir: TempDir,
+    addr: SocketAddr,
+}
+
+fn env() -> &'static TestEnv {
+    static ENV: OnceLock<TestEnv> = OnceLock::new();
+    ENV.get_or_init(|| {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let db_path = dir.path().join("invoices.db");
+        let data_dir = dir.path().join("data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        std::env::set_var(DATABASE_PATH_ENV, &db_path);
+        std::env::set_var(DATA_DIR_ENV, &data_dir);
+
+        let settings = serde_json::json!({
+            "output_directory": dir.path().join("out"),
+            "api_enabled": true,
+            "api_token": TOKEN,
+        });
+        std::fs::write(data_dir.join("settings.json"), settings.to_string()).unwrap();
+
+        // The invoices table is normally created by the OCaml backend
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE invoices (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                invoice_number TEXT NOT NULL UNIQUE,
+                locale TEXT NOT NULL,
+                currency_id INTEGER NOT NULL,
+                your_company_id INTEGER NOT NULL,
+                your_bank_id INTEGER NOT NULL,
+                customer_id INTEGER NOT NULL,
+                author TEXT NOT NULL,
+                service TEXT NOT NULL,
+                pdf_title TEXT NOT NULL,
+                invoice_date TEXT NOT NULL,
+                due_date TEXT NOT NULL,
+                vat_enabled BOOLEAN NOT NULL,
+                vat_rate INTEGER NOT NULL,
+                pdf_content BLOB NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO invoices (invoice_number, locale, currency_id, your_company_id, your_bank_id,
+                customer_id, author, service, pdf_title, invoice_date, due_date, vat_enabled, vat_rate, pdf_content)
+             VALUES ('2025-1', 'nb-NO', 1, 1, 1, 1, '', 'Consulting', 'Invoice', '2025-01-10', '2025-02-09', 0, 0, ?1)",
+            [PDF_BYTES],
+        )
+        .unwrap();
+
+        let server = api::start(0, TOKEN.to_string()).expect("start API");
+        TestEnv {
+            _dir: dir,
+            addr: server.addr(),
+        }
+    })
+}
+
+struct Reply {
+    status: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("JSON body")
+    }
+}
+
+fn request(method: &str, path: &str, token: Option<&str>, body: &str) -> Reply {
+    let mut stream = TcpStream::connect(env().addr).expect("connect");
+    let auth = token
+        .map(|t| format!("Authorization: Bearer {}\r\n", t))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        auth,
+        body.len(),
+        body
+    )
+    .unwrap();
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("header terminator");
+    let head = String::from_utf8_lossy(&raw[..split]).to_string();
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .expect("status code");
+    let content_type = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-type")
+                .then(|| value.trim().to_string())
+        })
+        .unwrap_or_default();
+
+    Reply {
+        status,
+        content_type,
+        body: raw[split + 4..].to_vec(),
+    }
+}
+
+#[test]
+fn openapi_description_is_public() {
+    let reply = request("GET", "/api/openapi.json", None, "");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.json()["openapi"], "3.0.3");
+}
+
+#[test]
+fn requests_without_valid_token_are_rejected() {
+    assert_eq!(request("GET", "/api/config", None, "").status, 401);
+    assert_eq!(
+        request("GET", "/api/config", Some("wrong-token-0123456789"), "").status,
+        401
+    );
+}
+
+#[test]
+fn config_values_round_trip() {
+    let reply = request(
+        "PUT",
+        "/api/config/description",
+        Some(TOKEN),
+        r#"{"value": "Internet share"}"#,
+    );
+    assert_eq!(reply.status, 200);
+
+    let reply = request("GET", "/api/config/description", Some(TOKEN), "");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.json()["value"], "Internet share");
+
+    let reply = request("GET", "/api/config", Some(TOKEN), "");
+    assert_eq!(reply.json()["description"], "Internet share");
+}
+
+#[test]
+fn unknown_config_key_and_bad_body_are_reported() {
+    assert_eq!(
+        request("GET", "/api/config/password", Some(TOKEN), "").status,
+        404
+    );
+    assert_eq!(
+        request("PUT", "/api/config/amount", Some(TOKEN), "not json").status,
+        400
+    );
+    // Values the library rejects are the client's error too
+    let reply = request(
+        "PUT",
+        "/api/config/amount",
+        Some(TOKEN),
+        r#"{"value": "abc"}"#,
+    );
+    assert_eq!(reply.status, 400);
+    assert!(reply.json()["error"].as_str().unwrap().contains("abc"));
+}
+
+#[test]
+fn app_settings_never_expose_the_token() {
+    let reply = request("GET", "/api/app-settings", Some(TOKEN), "");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.json()["api_token"], "");
+}
+
+#[test]
+fn invoices_can_be_listed_and_downloaded() {
+    let reply = request("GET", "/api/invoices", Some(TOKEN), "");
+    assert_eq!(reply.status, 200);
+    let list = reply.json();
+    assert_eq!(list[0]["invoice_number"], "2025-1");
+    assert!(list[0].get("pdf_base64").is_none());
+
+    let id = list[0]["id"].as_i64().unwrap();
+    let reply = request("GET", &format!("/api/invoices/{}/pdf", id), Some(TOKEN), "");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.content_type, "application/pdf");
+    assert_eq!(reply.body, PDF_BYTES);
+}
+
+#[test]
+fn missing_invoices_and_routes_return_errors() {
+    assert_eq!(
+        request("GET", "/api/invoices/9999", Some(TOKEN), "").status,
+        404
+    );
+    assert_eq!(
+        request("GET", "/api/invoices/abc", Some(TOKEN), "").status,
+        400
+    );
+    assert_eq!(
+        request("DELETE", "/api/invoices", Some(TOKEN), "").status,
+        405
+    );
+    assert_eq!(request("GET", "/api/unknown", Some(TOKEN), "").status, 404);
+}
+
+#[test]
+fn preview_splits_the_example_configuration() {
+    let reply = request("GET", "/api/preview", Some(TOKEN), "");
+    assert_eq!(reply.status, 200);
+    let preview = reply.json();
+    assert_eq!(preview["total"], "5000.00");
+    assert_eq!(preview["lines"][0]["amount"], "2500.00");
+    assert_eq!(preview["config_hash"].as_str().unwrap().len(), 64);
+}
+
+#[test]
+fn generation_with_a_stale_preview_is_refused() {
+    let reply = request(
+        "POST",
+        "/api/generate",
+        Some(TOKEN),
+        r#"{"config_hash": "0000"}"#,
+    );
+    assert_eq!(reply.status, 409);
+}
+
+#[test]
+fn a_preview_is_stale_once_its_invoice_numbers_are_taken() {
+    let config_hash = request("GET", "/api/preview", Some(TOKEN), "").json()["config_hash"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Another run or a finalized draft takes the number the preview showed
+    let conn = connect_database().unwrap();
+    invoices::create_backend_tables(&conn).unwrap();
+    invoices::next_invoice_number(&conn).unwrap();
+
+    let preview = request("GET", "/api/preview", Some(TOKEN), "").json();
+    assert_ne!(preview["config_hash"], config_hash.as_str());
+    let body = serde_json::json!({ "config_hash": config_hash }).to_string();
+    assert_eq!(
+        request("POST", "/api/generate", Some(TOKEN), &body).status,
+        409
+    );
+}
@@ -0,0 +1,130 @@
+// Tests for line item totals; amounts are in minor units (øre)
+use invoice_splitter_lib::line_items::{calculate_totals, describe, LineItem};
+use invoice_splitter_lib::money::{Currency, NOK};
+use invoice_splitter_lib::split::distribute;
+use invoice_splitter_lib::vat::VatSettings;
+
+fn item(
+    description: &str,
+    quantity: &str,
+    unit: &str,
+    unit_price: &str,
+    vat_rate: u32,
+) -> LineItem {
+    LineItem {
+        description: description.to_string(),
+        date: String::new(),
+        quantity: quantity.to_string(),
+        unit: unit.to_string(),
+        unit_price: unit_price.to_string(),
+        vat_rate: Some(vat_rate),
+        vat_treatment: None,
+    }
+}
+
+fn registered() -> VatSettings {
+    VatSettings {
+        enabled: true,
+        ..VatSettings::default()
+    }
+}
+
+#[test]
+fn totals_are_exact() {
+    let totals = calculate_totals(
+        &[
+            item("Consulting", "7.5", "hours", "1250.00", 25),
+            item("Travel", "1", "", "0.10", 0),
+            item("Licence", "3", "", "33.33", 25),
+        ],
+        NOK,
+        &registered(),
+    )
+    .unwrap();
+
+    assert_eq!(totals.lines[0].net.plain(), "9375.00");
+    assert_eq!(totals.lines[0].vat.plain(), "2343.75");
+    assert_eq!(totals.lines[2].net.plain(), "99.99");
+    // 24.9975 rounds half away from zero
+    assert_eq!(totals.lines[2].vat.plain(), "25.00");
+    assert_eq!(totals.subtotal.plain(), "9475.09");
+    assert_eq!(totals.vat_total.plain(), "2368.75");
+    assert_eq!(totals.total.plain(), "11843.84");
+}
+
+#[test]
+fn fractional_quantities_round_once_per_line() {
+    let totals = calculate_totals(
+        &[item("Power", "0.333", "kWh", "1.99", 0)],
+        NOK,
+        &registered(),
+    )
+    .unwrap();
+    // 0.333 * 1.99 = 0.66267
+    assert_eq!(totals.lines[0].net.minor, 66);
+}
+
+#[test]
+fn invalid_lines_name_the_line() {
+    let error = calculate_totals(
+        &[
+            item("Fine", "1", "", "10", 0),
+            item("Broken", "abc", "", "10", 0),
+        ],
+        NOK,
+        &registered(),
+    )
+    .unwrap_err();
+    assert!(error.message.starts_with("Line 2:"), "{}", error);
+
+    assert!(calculate_totals(&[item("", "1", "", "10", 0)], NOK, &registered()).is_err());
+    assert!(calculate_totals(&[item("Zero", "0", "", "10", 0)], NOK, &registered()).is_err());
+    assert!(calculate_totals(&[item("Negative", "1", "", "-10", 0)], NOK, &registered()).is_err());
+    assert!(calculate_totals(
+        &[item("Too precise", "1", "", "10.0001", 0)],
+        NOK,
+        &registered(),
+    )
+    .is_err());
+    assert!(calculate_totals(&[item("VAT", "1", "", "10", 24)], NOK, &registered()).is_err());
+}
+
+#[test]
+fn descriptions_include_quantity_and_unit_price() {
+    let totals = calculate_totals(
+        &[
+            item("Cabin rent", "2", "nights", "1500", 0),
+            item("Cleaning", "1", "", "450", 0),
+            item("Firewood", "1.5", "", "120", 0),
+        ],
+        NOK,
+        &registered(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        describe(&totals.lines[0]),
+        "Cabin rent (2 nights × 1 500,00)"
+    );
+    assert_eq!(describe(&totals.lines[1]), "Cleaning");
+    assert_eq!(describe(&totals.lines[2]), "Firewood (1,5 × 120,00)");
+}
+
+#[test]
+fn zero_decimal_currencies_round_lines_to_whole_units() {
+    let isk = Currency::from_code("ISK").unwrap();
+    let totals =
+        calculate_totals(&[item("Fuel", "2.5", "l", "333", 25)], isk, &registered()).unwrap();
+    // 832.5 rounds to 833, VAT 208.25 rounds to 208
+    assert_eq!(totals.lines[0].net.plain(), "833");
+    assert_eq!(totals.total.plain(), "1041");
+    assert!(calculate_totals(&[item("Fuel", "1", "", "333.5", 0)], isk, &registered()).is_err());
+}
+
+#[test]
+fn shares_are_distributed_across_lines() {
+    assert_eq!(distribute(100, &[1, 1, 1]), vec![34, 33, 33]);
+    assert_eq!(distribute(1_000, &[300_000, 100_000]), vec![750, 250]);
+    assert_eq!(distribute(0, &[5, 0]), vec![0, 0]);
+    assert_eq!(distribute(0, &[0, 0]), vec![0, 0]);
+}
@@ -0,0 +1,198 @@
+// Tests for the native PDF renderer
+use invoice_splitter_lib::database::DATABASE_PATH_ENV;
+use invoice_splitter_lib::invoices;
+use invoice_splitter_lib::money::{Money, NOK};
+use invoice_splitter_lib::pdf::{
+    format_date, render_invoice, text_width, wrap_text, DocumentLine, InvoiceDocument,
+};
+use invoice_splitter_lib::renderer::{BatchMode, InvoiceRenderer, NativeRenderer};
+use invoice_splitter_lib::run_plan::RunPlan;
+use invoice_splitter_lib::runs::RunLog;
+use invoice_splitter_lib::terms::InvoiceDates;
+
+fn document() -> InvoiceDocument {
+    InvoiceDocument {
+        invoice_number: "2025-7".to_string(),
+        invoice_date: "2025-01-31".to_string(),
+        due_date: "2025-02-14".to_string(),
+        sender: vec!["Utleier AS".to_string(), "Storgata 1".to_string()],
+        customer_name: "Kari".to_string(),
+        customer_address: vec!["Lillegata 2".to_string()],
+        service: "Husleie (januar)".to_string(),
+        lines: vec![DocumentLine {
+            date: String::new(),
+            description: "Husleie (januar)".to_string(),
+            amount: Money::new(125050, NOK),
+        }],
+        vat_summary: Vec::new(),
+        bank_details: vec!["Konto: 1234.56.78901".to_string()],
+    }
+}
+
+#[test]
+fn rendered_invoices_are_well_formed_pdfs() {
+    let pdf = render_invoice(&document()).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.starts_with("%PDF-1.4"));
+    assert!(text.trim_end().ends_with("%%EOF"));
+
+    // Every xref entry points at the start of its object
+    let startxref: usize = text
+        .rsplit("startxref\n")
+        .next()
+        .and_then(|rest| rest.lines().next())
+        .and_then(|n| n.parse().ok())
+        .unwrap();
+    assert!(pdf[startxref..].starts_with(b"xref\n"));
+    let table = String::from_utf8_lossy(&pdf[startxref..]);
+    let entries = table.lines().skip(3).take(5);
+    for (i, entry) in entries.enumerate() {
+        let offset: usize = entry[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+    }
+
+    assert!(text.contains("(FAKTURA) Tj"));
+    assert!(text.contains("(Fakturanummer: 2025-7) Tj"));
+    assert!(text.contains("(Fakturadato: 31/01/2025) Tj"));
+    assert!(text.contains("(Husleie \\(januar\\)) Tj"));
+    assert!(text.contains("(1 250,50 kr) Tj"));
+    assert!(text.contains("(Bel\\370p) Tj"));
+}
+
+#[test]
+fn long_descriptions_wrap_and_keep_line_breaks() {
+    let lines = wrap_text(
+        "Husleie for januar inkludert strøm, internett og felleskostnader\nParkering",
+        150.0,
+        11.0,
+    );
+    assert!(lines.len() > 2);
+    assert_eq!(lines.last().unwrap(), "Parkering");
+    assert!(lines.iter().all(|l| text_width(l, 11.0) <= 150.0));
+
+    // Helvetica: B 667, e 556, l 222, ø 611, p 556
+    assert!((text_width("Beløp", 10.0) - 26.12).abs() < 1e-9);
+}
+
+#[test]
+fn dates_are_printed_day_first() {
+    assert_eq!(format_date("2025-01-31"), "31/01/2025");
+    assert_eq!(format_date("2025-01-31T12:00:00"), "31/01/2025");
+    assert_eq!(format_date("31.01.2025"), "31.01.2025");
+}
+
+// Rent for two recipients, as a run would plan it
+fn plan() -> RunPlan {
+    let names = ["Ola", "Kari"];
+    RunPlan {
+        settings: vec![
+            ("sender".to_string(), "Utleier AS\nStorgata 1".to_string()),
+            (
+                "bankdetails".to_string(),
+                "Konto: 1234.56.78901".to_string(),
+            ),
+            ("description".to_string(), "Rent".to_string()),
+            ("amount".to_string(), "1000".to_string()),
+            (
+                "recipients".to_string(),
+                "Ola\n\nKari\nStorgata 1".to_string(),
+            ),
+        ],
+        currency: NOK,
+        recipients: names.iter().map(|n| n.to_string()).collect(),
+        amounts: vec![Money::new(50000, NOK), Money::new(50000, NOK)],
+        descriptions: names.iter().map(|n| format!("Rent for {}", n)).collect(),
+        lines: vec![Vec::new(), Vec::new()],
+        vat: vec![Vec::new(), Vec::new()],
+        dates: names
+            .iter()
+            .map(|_| InvoiceDates {
+                invoice_date: "2025-01-31".to_string(),
+                due_date: "2025-02-14".to_string(),
+                payment_days: 14,
+                period_date: "2025-01-31".to_string(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn native_dry_runs_write_one_preview_per_recipient() {
+    let plan = plan();
+
+    let dir = tempfile::tempdir().unwrap();
+    let renderer = NativeRenderer {
+        out_dir: dir.path().join("out"),
+        batch_mode: BatchMode::default(),
+    };
+    let mut log = RunLog::default();
+    let run = renderer.render_run(&plan, true, &mut log).unwrap();
+    assert!(log.stdout.contains("2 invoices generated"));
+
+    let numbers: Vec<&str> = run
+        .files
+        .iter()
+        .map(|f| f.invoice_number.as_str())
+        .collect();
+    assert_eq!(numbers, vec!["PREVIEW-1", "PREVIEW-2"]);
+    assert_eq!(run.files[1].customer, "Kari");
+    assert_eq!(run.files[0].service, "Rent for Ola");
+    for file in &run.files {
+        let pdf = std::fs::read(&file.path).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+    assert!(
+        String::from_utf8_lossy(&std::fs::read(&run.files[0].path).unwrap())
+            .contains("(Rent for Ola) Tj")
+    );
+}
+
+#[test]
+fn all_or_nothing_runs_store_nothing_when_a_recipient_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var(DATABASE_PATH_ENV, dir.path().join("invoices.db"));
+    let out_dir = dir.path().join("out");
+    let mut plan = plan();
+    // Kari has no amount, so that invoice fails after Ola's was made
+    plan.amounts.pop();
+    let latest_id = invoices::latest_invoice_id().unwrap();
+    let next_number = invoices::peek_invoice_numbers(1).unwrap();
+
+    let renderer = NativeRenderer {
+        out_dir: out_dir.clone(),
+        batch_mode: BatchMode::AllOrNothing,
+    };
+    let mut log = RunLog::default();
+    let error = renderer
+        .render_run(&plan, false, &mut log)
+        .err()
+        .expect("the run fails");
+    assert!(error.message.contains("Kari"), "{}", error);
+    assert!(log.stdout.contains("rolled back"));
+    assert!(invoices::invoice_numbers_after(latest_id)
+        .unwrap()
+        .is_empty());
+    assert_eq!(invoices::peek_invoice_numbers(1).unwrap(), next_number);
+    assert_eq!(std::fs::read_dir(&out_dir).unwrap().count(), 0);
+
+    // Best effort keeps Ola's invoice
+    let renderer = NativeRenderer {
+        out_dir,
+        batch_mode: BatchMode::BestEffort,
+    };
+    let run = renderer
+        .render_run(&plan, false, &mut RunLog::default())
+        .unwrap();
+    assert_eq!(run.files.len(), 1);
+    // Stored with the same service as the OCaml backend stores
+    let stored = invoices::get_all_invoices().unwrap();
+    let ola = stored
+        .iter()
+        .find(|invoice| invoice.invoice_number == run.files[0].invoice_number)
+        .unwrap();
+    assert_eq!(ola.service, "Rent for Ola");
+    assert_eq!(
+        invoices::invoice_numbers_after(latest_id).unwrap(),
+        next_number
+    );
+}
@@ -0,0 +1,98 @@
+// Tests for placeholder expansion in descriptions and file names
+use chrono::NaiveDate;
+use invoice_splitter_lib::export::validate_template;
+use invoice_splitter_lib::placeholders::{expand, validate, Context, RECIPIENT_PLACEHOLDERS};
+use invoice_splitter_lib::recipients::Recipient;
+
+fn context(date: &str) -> Context {
+    Context::new(NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap())
+}
+
+#[test]
+fn month_offsets_cross_the_year_boundary() {
+    let january = context("2025-01-15");
+    assert_eq!(
+        expand("Strøm {prev_month_name} {prev_year}", &january).unwrap(),
+        "Strøm desember 2024"
+    );
+    assert_eq!(
+        expand("{next_month_name} {month-13}/{year+12}", &january).unwrap(),
+        "februar 12/2026"
+    );
+    // Month ends follow the shifted month
+    assert_eq!(
+        expand("{prev_month_end}", &context("2024-03-31")).unwrap(),
+        "29.02.2024"
+    );
+}
+
+#[test]
+fn ranges_and_quarters() {
+    let may = context("2025-05-20");
+    assert_eq!(
+        expand("{month_range}", &may).unwrap(),
+        "01.05.2025–31.05.2025"
+    );
+    assert_eq!(
+        expand("Q{quarter} {quarter_range}", &may).unwrap(),
+        "Q2 01.04.2025–30.06.2025"
+    );
+    assert_eq!(
+        expand("Q{quarter-3} {quarter_start-3}", &may).unwrap(),
+        "Q1 01.01.2025"
+    );
+    assert_eq!(expand("{date} {day}", &may).unwrap(), "2025-05-20 20");
+}
+
+#[test]
+fn recipient_fields_and_escapes() {
+    let recipient = Recipient {
+        name: "Kari".to_string(),
+        address: vec![
+            "Storgata 1".to_string(),
+            "kari@example.com".to_string(),
+            "0155 Oslo".to_string(),
+        ],
+    };
+    let ctx = Context::for_recipient(
+        NaiveDate::from_ymd_opt(2025, 2, 1).unwrap(),
+        &recipient,
+        "2025-02-15",
+    );
+    assert_eq!(
+        expand(
+            "{recipient_name} <{recipient_email}>, {recipient_address}, due {due_date}",
+            &ctx
+        )
+        .unwrap(),
+        "Kari <kari@example.com>, Storgata 1, 0155 Oslo, due 2025-02-15"
+    );
+    assert_eq!(
+        expand("{{literal}} {{{month}}}", &ctx).unwrap(),
+        "{literal} {02}"
+    );
+}
+
+#[test]
+fn invalid_placeholders_are_rejected() {
+    let ctx = context("2025-01-15");
+    assert!(expand("{colour}", &ctx)
+        .unwrap_err()
+        .message
+        .contains("{colour}"));
+    assert!(expand("{recipient_name}", &ctx).is_err());
+    assert!(expand("{month+x}", &ctx).is_err());
+    assert!(expand("{month", &ctx).is_err());
+    assert!(expand("month}", &ctx).is_err());
+
+    assert!(validate(
+        "{prev_month_name} for {recipient_name}",
+        &RECIPIENT_PLACEHOLDERS
+    )
+    .is_ok());
+    assert!(validate("{prev_recipient_name}", &RECIPIENT_PLACEHOLDERS).is_err());
+
+    // File names know the invoice fields and the date placeholders
+    assert!(validate_template("{number}_{customer}_{prev_month_name}").is_ok());
+    assert!(validate_template("{recipient_email}").is_err());
+}
@@ -0,0 +1,46 @@
+// Tests for live previews of a single invoice against a temporary database
+mod common;
+
+use common::database;
+use invoice_splitter_lib::preview::{cached_preview, preview_invoice};
+use invoice_splitter_lib::renderer::RendererKind;
+use invoice_splitter_lib::settings::{get_app_settings, save_app_settings, AppSettings};
+use std::collections::BTreeMap;
+
+fn use_renderer(renderer: RendererKind) {
+    save_app_settings(AppSettings {
+        renderer,
+        ..get_app_settings().unwrap()
+    })
+    .unwrap();
+}
+
+fn values() -> BTreeMap<String, String> {
+    [
+        ("sender", "Utleier AS"),
+        ("bankdetails", "Konto: 1234.56.78901"),
+        ("description", "Husleie"),
+        ("amount", "1000"),
+        ("recipients", "Kari\nLillegata 2"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect()
+}
+
+#[test]
+fn cached_previews_belong_to_the_renderer_that_made_them() {
+    let _db = database();
+    use_renderer(RendererKind::Native);
+
+    let preview = preview_invoice(values(), "Kari".to_string(), None).unwrap();
+    assert_eq!(preview.renderer, RendererKind::Native);
+    let again = preview_invoice(values(), "Kari".to_string(), None).unwrap();
+    assert_eq!(again.pdf_base64, preview.pdf_base64);
+
+    let hash = &preview.config_hash;
+    assert!(cached_preview(hash, "Kari", RendererKind::Native).is_some());
+    // After switching to the OCaml backend the built-in renderer's PDF is not shown
+    assert!(cached_preview(hash, "Kari", RendererKind::Ocaml).is_none());
+    assert!(cached_preview(hash, "Ola", RendererKind::Native).is_none());
+}
@@ -0,0 +1,304 @@
+// Tests for rendering stored invoices again against a temporary database
+mod common;
+
+use base64::{engine::general_purpose, Engine as _};
+use common::database;
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::errors::ErrorCode;
+use invoice_splitter_lib::invoices::{self, InvoiceParties, NewInvoice, StoredLine};
+use invoice_splitter_lib::renderer::RendererKind;
+use invoice_splitter_lib::revisions::{
+    correct_invoice, diff_invoice_revisions, list_invoice_revisions, load_invoice_data,
+    regenerate_invoice, FieldChange, InvoiceData, NATIVE_RENDER_WARNING, ORIGINAL_REASON,
+};
+use invoice_splitter_lib::settings::{get_app_settings, save_app_settings, AppSettings};
+use invoice_splitter_lib::vat::{VatSummaryLine, VatTreatment};
+
+const ORIGINAL_PDF: &[u8] = b"%PDF-1.4 original";
+
+// Store an invoice the way a run would, returning its id and number
+fn store(parties: Option<&InvoiceParties>) -> (i32, String) {
+    let conn = connect_database().unwrap();
+    invoices::create_backend_tables(&conn).unwrap();
+    let number = invoices::next_invoice_number(&conn).unwrap();
+    invoices::insert_invoice(
+        &conn,
+        &NewInvoice {
+            invoice_number: &number,
+            invoice_date: "2025-01-31",
+            due_date: "2025-02-14",
+            service: "",
+            vat_enabled: false,
+            vat_rate: 0,
+            lines: vec![StoredLine {
+                date: String::new(),
+                description: "Husleie (januar)".to_string(),
+                price: "1250.50".to_string(),
+            }],
+            pdf: ORIGINAL_PDF,
+        },
+    )
+    .unwrap();
+    if let Some(parties) = parties {
+        invoices::store_invoice_parties(&number, parties).unwrap();
+    }
+    let id = conn
+        .query_row(
+            "SELECT id FROM invoices WHERE invoice_number = ?",
+            [&number],
+            |row| row.get(0),
+        )
+        .unwrap();
+    (id, number)
+}
+
+// Store an invoice with VAT at the rates in `summary`
+fn store_with_vat(summary: &[VatSummaryLine]) -> i32 {
+    let (id, number) = store(Some(&parties()));
+    connect_database()
+        .unwrap()
+        .execute(
+            "UPDATE invoices SET vat_enabled = 1, vat_rate = 25 WHERE id = ?",
+            [id],
+        )
+        .unwrap();
+    invoices::store_vat_summary(&number, summary).unwrap();
+    id
+}
+
+fn vat_line(rate: u32, net: &str, vat: &str) -> VatSummaryLine {
+    VatSummaryLine {
+        rate,
+        treatment: VatTreatment::Standard,
+        net: net.to_string(),
+        vat: vat.to_string(),
+    }
+}
+
+fn parties() -> InvoiceParties {
+    InvoiceParties {
+        currency: "NOK".to_string(),
+        sender: vec!["Utleier AS".to_string()],
+        customer_name: "Kari".to_string(),
+        customer_address: vec!["Lillegata 2".to_string(), "0150 Oslo".to_string()],
+        bank_details: vec!["Konto: 1234.56.78901".to_string()],
+    }
+}
+
+#[test]
+fn stored_rows_rebuild_the_invoice_data() {
+    let _db = database();
+    let (id, number) = store(Some(&parties()));
+
+    let data = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+    assert_eq!(data.invoice_number, number);
+    assert_eq!(data.due_date, "2025-02-14");
+    assert_eq!(data.parties, parties());
+    assert_eq!(data.lines[0].price, "1250.50");
+
+    let document = data.document().unwrap();
+    assert_eq!(document.customer_address.len(), 2);
+    assert_eq!(document.lines[0].amount.minor, 125050);
+}
+
+#[test]
+fn regenerating_adds_revisions_and_keeps_the_original() {
+    let _db = database();
+    let (id, number) = store(Some(&parties()));
+    assert!(list_invoice_revisions(id).unwrap().is_empty());
+
+    let first = regenerate_invoice(id, "New layout".to_string()).unwrap();
+    assert_eq!(first.revision, 2);
+    assert_eq!(first.reason, "New layout");
+    let pdf = general_purpose::STANDARD.decode(&first.pdf_base64).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.contains(&format!("(Fakturanummer: {}) Tj", number)));
+    assert!(text.contains("(Lillegata 2) Tj"));
+
+    regenerate_invoice(id, "Logo".to_string()).unwrap();
+    let revisions = list_invoice_revisions(id).unwrap();
+    let numbers: Vec<u32> = revisions.iter().map(|r| r.revision).collect();
+    assert_eq!(numbers, vec![1, 2, 3]);
+    assert_eq!(revisions[0].reason, ORIGINAL_REASON);
+    assert_eq!(
+        general_purpose::STANDARD
+            .decode(&revisions[0].pdf_base64)
+            .unwrap(),
+        ORIGINAL_PDF
+    );
+
+    // The invoice itself still has its original PDF
+    let invoice = invoices::get_invoice_by_id(id).unwrap();
+    assert_eq!(
+        general_purpose::STANDARD
+            .decode(&invoice.pdf_base64)
+            .unwrap(),
+        ORIGINAL_PDF
+    );
+}
+
+#[test]
+fn revisions_say_when_they_are_not_rendered_by_the_configured_renderer() {
+    let _db = database();
+    let (id, _) = store(Some(&parties()));
+    let use_renderer = |renderer| {
+        save_app_settings(AppSettings {
+            renderer,
+            ..get_app_settings().unwrap()
+        })
+        .unwrap()
+    };
+
+    use_renderer(RendererKind::Ocaml);
+    let revision = regenerate_invoice(id, "Layout".to_string()).unwrap();
+    assert_eq!(revision.warnings, vec![NATIVE_RENDER_WARNING]);
+    use_renderer(RendererKind::Native);
+    let revision = regenerate_invoice(id, "Layout".to_string()).unwrap();
+    assert!(revision.warnings.is_empty());
+    // Listed revisions carry no warnings
+    assert!(list_invoice_revisions(id)
+        .unwrap()
+        .iter()
+        .all(|r| r.warnings.is_empty()));
+}
+
+#[test]
+fn invoices_without_stored_parties_are_not_regenerated() {
+    let _db = database();
+    let (id, _) = store(None);
+
+    let error = regenerate_invoice(id, String::new()).unwrap_err();
+    assert!(error.message.contains("No customer details"), "{}", error);
+    assert!(list_invoice_revisions(id).unwrap().is_empty());
+    assert!(regenerate_invoice(99999, String::new()).is_err());
+}
+
+#[test]
+fn corrections_are_revisions_that_diff_field_by_field() {
+    let _db = database();
+    let (id, number) = store(Some(&parties()));
+    let stored = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+
+    let mut corrected = stored.clone();
+    corrected.invoice_number = "ignored".to_string();
+    corrected.due_date = "2025-02-28".to_string();
+    corrected.parties.customer_address[0] = "Storgata 9".to_string();
+    corrected.lines[0].price = "1300".to_string();
+    corrected.lines.push(StoredLine {
+        date: String::new(),
+        description: "Parkering".to_string(),
+        price: "200".to_string(),
+    });
+    assert!(correct_invoice(id, corrected.clone(), " ".to_string()).is_err());
+    let revision = correct_invoice(id, corrected, "Wrong address".to_string()).unwrap();
+    assert_eq!(revision.revision, 2);
+    assert_eq!(revision.data.invoice_number, number);
+
+    let changes = diff_invoice_revisions(id, 1, 2).unwrap();
+    let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+    assert_eq!(
+        fields,
+        vec![
+            "due_date",
+            "customer_address",
+            "line 1 price",
+            "line 2",
+            "total"
+        ]
+    );
+    assert_eq!(
+        changes[1],
+        FieldChange {
+            field: "customer_address".to_string(),
+            before: "Lillegata 2\n0150 Oslo".to_string(),
+            after: "Storgata 9\n0150 Oslo".to_string(),
+        }
+    );
+    assert_eq!(changes[3].before, "");
+    assert_eq!(changes[3].after, "Parkering: 200");
+
+    // Regenerating renders the corrected data again
+    regenerate_invoice(id, "Layout".to_string()).unwrap();
+    assert!(diff_invoice_revisions(id, 2, 3).unwrap().is_empty());
+    assert_eq!(diff_invoice_revisions(id, 3, 1).unwrap().len(), 5);
+    assert!(diff_invoice_revisions(id, 1, 9).is_err());
+}
+
+#[test]
+fn corrections_are_checked_like_runs() {
+    let _db = database();
+    let (id, _) = store(Some(&parties()));
+    let stored = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+    let correct = |change: &dyn Fn(&mut InvoiceData)| {
+        let mut corrected = stored.clone();
+        change(&mut corrected);
+        correct_invoice(id, corrected, "Check".to_string()).unwrap_err()
+    };
+
+    let error = correct(&|data| data.parties.customer_name = " ".to_string());
+    assert_eq!(error.message, "A correction needs a customer");
+    let error = correct(&|data| data.due_date = "2025-01-30".to_string());
+    assert!(
+        error.message.contains("is before the invoice date"),
+        "{}",
+        error
+    );
+    // The date it was issued with is kept, but it cannot be moved into a closed period
+    let error = correct(&|data| data.invoice_date = "2025-01-15".to_string());
+    assert!(
+        error.message.contains("the start of the open period"),
+        "{}",
+        error
+    );
+    let error = correct(&|data| data.invoice_date = "31.01.2025".to_string());
+    assert_eq!(error.code, ErrorCode::InvalidInput);
+    assert!(list_invoice_revisions(id).unwrap().is_empty());
+}
+
+#[test]
+fn corrected_amounts_get_their_vat_worked_out_again() {
+    let _db = database();
+    let id = store_with_vat(&[vat_line(25, "1250.50", "312.63")]);
+    let stored = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+
+    // The summary sent along is the one from before the correction
+    let mut corrected = stored.clone();
+    corrected.lines[0].price = "1300".to_string();
+    let revision = correct_invoice(id, corrected, "Wrong rent".to_string()).unwrap();
+    assert_eq!(
+        revision.data.vat_summary,
+        vec![vat_line(25, "1300.00", "325.00")]
+    );
+    let changes = diff_invoice_revisions(id, 1, 2).unwrap();
+    let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+    assert_eq!(fields, vec!["line 1 price", "vat_summary", "total"]);
+    assert_eq!(changes[2].after, "1 625,00 kr");
+}
+
+#[test]
+fn several_vat_rates_must_still_add_up_to_the_lines() {
+    let _db = database();
+    let id = store_with_vat(&[
+        vat_line(25, "1000.00", "250.00"),
+        vat_line(12, "250.50", "30.06"),
+    ]);
+    let stored = load_invoice_data(&connect_database().unwrap(), id).unwrap();
+
+    let mut corrected = stored.clone();
+    corrected.lines[0].price = "1300".to_string();
+    let error = correct_invoice(id, corrected.clone(), "Wrong rent".to_string()).unwrap_err();
+    assert!(
+        error.message.contains("adds up to 1 250,50 kr"),
+        "{}",
+        error
+    );
+
+    corrected.vat_summary[1] = vat_line(12, "300.00", "30.06");
+    let error = correct_invoice(id, corrected.clone(), "Wrong rent".to_string()).unwrap_err();
+    assert!(error.message.contains("VAT at 12%"), "{}", error);
+    assert!(list_invoice_revisions(id).unwrap().is_empty());
+
+    corrected.vat_summary[1] = vat_line(12, "300.00", "36.00");
+    let revision = correct_invoice(id, corrected.clone(), "Wrong rent".to_string()).unwrap();
+    assert_eq!(revision.data.vat_summary, corrected.vat_summary);
+}
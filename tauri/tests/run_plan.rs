@@ -0,0 +1,53 @@
+// Tests for the run plan handed to the backend
+use invoice_splitter_lib::money::{Money, NOK};
+use invoice_splitter_lib::run_plan::RunPlan;
+use invoice_splitter_lib::terms::InvoiceDates;
+
+fn plan() -> RunPlan {
+    let names = ["Ola", "Kari", "Per"];
+    RunPlan {
+        settings: vec![
+            ("description".to_string(), "Rent".to_string()),
+            (
+                "recipients".to_string(),
+                "Ola\nola@example.com\n\nKari\nStorgata 1\n\nPer".to_string(),
+            ),
+        ],
+        currency: NOK,
+        recipients: names.iter().map(|n| n.to_string()).collect(),
+        amounts: vec![
+            Money::new(33334, NOK),
+            Money::new(33333, NOK),
+            Money::new(33333, NOK),
+        ],
+        descriptions: names.iter().map(|n| format!("Rent for {}", n)).collect(),
+        lines: vec![Vec::new(), Vec::new(), Vec::new()],
+        vat: vec![Vec::new(), Vec::new(), Vec::new()],
+        dates: names
+            .iter()
+            .map(|_| InvoiceDates {
+                invoice_date: "2025-01-31".to_string(),
+                due_date: "2025-02-14".to_string(),
+                payment_days: 14,
+                period_date: "2025-01-31".to_string(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn a_plan_narrowed_to_one_recipient_keeps_their_share() {
+    let kari = plan().into_recipient(1).unwrap();
+    assert_eq!(kari.recipients, vec!["Kari".to_string()]);
+    assert_eq!(kari.amounts, vec![Money::new(33333, NOK)]);
+    assert_eq!(kari.descriptions, vec!["Rent for Kari".to_string()]);
+
+    let content = kari.to_file_content();
+    assert!(content.contains("setting\trecipients\tKari\\nStorgata 1\n"));
+    assert!(content.contains("setting\tdescription\tRent\n"));
+    assert!(content.contains("amount\t0\t333.33\n"));
+    assert!(content.contains("description\t0\tRent for Kari\n"));
+    assert!(!content.contains("\t1\t"));
+
+    assert!(plan().into_recipient(3).is_err());
+}
@@ -0,0 +1,80 @@
+// Tests for the run lock, the backend time limit and the run log
+use invoice_splitter_lib::database::DATABASE_PATH_ENV;
+use invoice_splitter_lib::runs::{
+    describe_exit, list_run_logs, output_with_timeout, store_run_log, RunLock, RunLog,
+};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn only_one_run_holds_the_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("invoices.db");
+
+    let first = RunLock::acquire(&db_path).unwrap();
+    let second = RunLock::acquire(&db_path);
+    assert!(second.err().unwrap().message.contains("already running"));
+    // Another database has its own lock
+    assert!(RunLock::acquire(&dir.path().join("other.db")).is_ok());
+
+    drop(first);
+    assert!(RunLock::acquire(&db_path).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn hung_processes_are_stopped_with_their_output_kept() {
+    let started = Instant::now();
+    let output = output_with_timeout(
+        Command::new("sh").args(["-c", "echo started; sleep 30"]),
+        Some(Duration::from_millis(300)),
+    )
+    .unwrap();
+
+    assert!(output.status.is_none());
+    assert_eq!(output.stdout, "started\n");
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[cfg(unix)]
+#[test]
+fn exits_and_signals_are_described() {
+    let output = output_with_timeout(
+        Command::new("sh").args(["-c", "echo out; echo err >&2; exit 3"]),
+        None,
+    )
+    .unwrap();
+    assert_eq!(output.stdout, "out\n");
+    assert_eq!(output.stderr, "err\n");
+    assert_eq!(describe_exit(&output.status.unwrap()), "exited with code 3");
+
+    let output = output_with_timeout(Command::new("sh").args(["-c", "kill -9 $$"]), None).unwrap();
+    assert!(describe_exit(&output.status.unwrap()).starts_with("was killed by signal 9"));
+}
+
+#[test]
+fn run_logs_are_listed_newest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var(DATABASE_PATH_ENV, dir.path().join("invoices.db"));
+
+    for (detail, succeeded) in [("exited with code 0", true), ("exited with code 1", false)] {
+        store_run_log(&RunLog {
+            started_at: "2025-01-31 10:00:00".to_string(),
+            finished_at: "2025-01-31 10:00:02".to_string(),
+            renderer: "OCaml backend".to_string(),
+            succeeded,
+            detail: detail.to_string(),
+            stdout: "Processing 2 recipients...\n".to_string(),
+            stderr: "warning\n".to_string(),
+            ..RunLog::default()
+        })
+        .unwrap();
+    }
+
+    let logs = list_run_logs(None).unwrap();
+    assert_eq!(logs.len(), 2);
+    assert!(!logs[0].succeeded);
+    assert_eq!(logs[0].detail, "exited with code 1");
+    assert_eq!(logs[1].stderr, "warning\n");
+    assert_eq!(list_run_logs(Some(1)).unwrap().len(), 1);
+}
@@ -0,0 +1,236 @@
+// Tests for recurring schedules
+mod common;
+
+use chrono::{Datelike, Local, Months, NaiveDate};
+use common::database;
+use invoice_splitter_lib::database::connect_database;
+use invoice_splitter_lib::renderer::RendererKind;
+use invoice_splitter_lib::runs;
+use invoice_splitter_lib::schedule::{
+    due_scheduled_runs, list_schedule_runs, occurrences, preview_scheduled_run, run_scheduled,
+    save_schedule, schedule_config, skip_scheduled_run, validate_schedule, Cadence, RunStatus,
+    Schedule,
+};
+use invoice_splitter_lib::settings::{
+    get_app_settings, save_app_settings, AppSettings, InvoiceConfig,
+};
+use std::collections::BTreeMap;
+
+fn date(value: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+}
+
+fn dates(values: &[&str]) -> Vec<NaiveDate> {
+    values.iter().map(|v| date(v)).collect()
+}
+
+fn schedule(cadence: Cadence, day_of_month: u32, start_date: &str, end_date: &str) -> Schedule {
+    Schedule {
+        id: 0,
+        name: "Rent".to_string(),
+        template: String::new(),
+        config: BTreeMap::new(),
+        recipients: Vec::new(),
+        cadence,
+        day_of_month,
+        start_date: start_date.to_string(),
+        end_date: end_date.to_string(),
+    }
+}
+
+fn config(values: &[(&str, &str)]) -> InvoiceConfig {
+    let values: BTreeMap<String, String> = values
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    InvoiceConfig::default().with_values(&values).unwrap()
+}
+
+#[test]
+fn monthly_runs_fall_back_to_the_last_day_of_short_months() {
+    let runs = occurrences(
+        &schedule(Cadence::Monthly, 31, "2024-01-15", ""),
+        date("2024-04-30"),
+    )
+    .unwrap();
+    assert_eq!(
+        runs,
+        dates(&["2024-01-31", "2024-02-29", "2024-03-31", "2024-04-30"])
+    );
+}
+
+#[test]
+fn runs_start_on_the_start_date_and_stop_at_the_end_date() {
+    let monthly = schedule(Cadence::Monthly, 1, "2025-01-15", "2025-04-01");
+    assert_eq!(
+        occurrences(&monthly, date("2025-12-31")).unwrap(),
+        dates(&["2025-02-01", "2025-03-01", "2025-04-01"])
+    );
+
+    let quarterly = schedule(Cadence::Quarterly, 10, "2025-01-01", "");
+    assert_eq!(
+        occurrences(&quarterly, date("2025-10-09")).unwrap(),
+        dates(&["2025-01-10", "2025-04-10", "2025-07-10"])
+    );
+
+    let yearly = schedule(Cadence::Yearly, 5, "2023-06-01", "");
+    assert_eq!(
+        occurrences(&yearly, date("2025-06-05")).unwrap(),
+        dates(&["2023-06-05", "2024-06-05", "2025-06-05"])
+    );
+    assert!(occurrences(&yearly, date("2023-06-04")).unwrap().is_empty());
+}
+
+#[test]
+fn schedules_bill_their_own_values_and_recipients() {
+    let base = config(&[
+        ("amount", "1000.00"),
+        (
+            "recipients",
+            "Ola\nola@example.com\n\nKari\n\nPer\nStorgata 1",
+        ),
+    ]);
+    let mut internet = schedule(Cadence::Monthly, 1, "2025-01-01", "");
+    internet.config = BTreeMap::from([("amount".to_string(), "600".to_string())]);
+    internet.recipients = vec!["Per".to_string(), "Ola".to_string()];
+
+    let config = schedule_config(&internet, &base).unwrap();
+    assert_eq!(config.get("amount"), "600.00");
+    // Recipients keep the order of the configuration
+    assert_eq!(
+        config.get("recipients"),
+        "Ola\nola@example.com\n\nPer\nStorgata 1"
+    );
+
+    internet.recipients.push("Nils".to_string());
+    let error = schedule_config(&internet, &base).unwrap_err();
+    assert!(error.message.contains("'Nils'"), "{}", error);
+}
+
+#[test]
+fn invalid_schedules_are_rejected() {
+    let base = config(&[("amount", "1000.00")]);
+
+    assert!(validate_schedule(&schedule(Cadence::Monthly, 1, "2025-01-01", ""), &base).is_ok());
+    assert!(validate_schedule(&schedule(Cadence::Monthly, 0, "2025-01-01", ""), &base).is_err());
+    assert!(validate_schedule(&schedule(Cadence::Monthly, 32, "2025-01-01", ""), &base).is_err());
+    assert!(validate_schedule(&schedule(Cadence::Monthly, 1, "01.01.2025", ""), &base).is_err());
+    assert!(validate_schedule(
+        &schedule(Cadence::Monthly, 1, "2025-01-01", "2024-12-31"),
+        &base
+    )
+    .is_err());
+
+    let mut unknown_key = schedule(Cadence::Monthly, 1, "2025-01-01", "");
+    unknown_key.config = BTreeMap::from([("colour".to_string(), "red".to_string())]);
+    assert!(validate_schedule(&unknown_key, &base).is_err());
+
+    let mut bad_amount = schedule(Cadence::Monthly, 1, "2025-01-01", "");
+    bad_amount.config = BTreeMap::from([("amount".to_string(), "0.125".to_string())]);
+    assert!(validate_schedule(&bad_amount, &base).is_err());
+}
+
+#[test]
+fn overdue_runs_are_dated_today_and_bill_their_own_period() {
+    let _db = database();
+    save_app_settings(AppSettings {
+        renderer: RendererKind::Native,
+        ..get_app_settings().unwrap()
+    })
+    .unwrap();
+
+    // Monthly on the 1st since two months before the open period began
+    let today = Local::now().date_naive();
+    let this_month = today.with_day(1).unwrap();
+    let run_dates: Vec<NaiveDate> = [2, 1]
+        .iter()
+        .map(|months| this_month.checked_sub_months(Months::new(*months)).unwrap())
+        .collect();
+    let saved = save_schedule(Schedule {
+        config: [
+            ("sender", "Utleier AS"),
+            ("bankdetails", "Konto: 1234.56.78901"),
+            ("description", "Internett {year}-{month}"),
+            ("amount", "600"),
+            ("recipients", "Kari\nLillegata 2"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+        ..schedule(
+            Cadence::Monthly,
+            1,
+            &run_dates[0].format("%Y-%m-%d").to_string(),
+            "",
+        )
+    })
+    .unwrap();
+
+    // The second run is overdue too once the first has stored an invoice dated today
+    for run_date in &run_dates {
+        let run_date = run_date.format("%Y-%m-%d").to_string();
+        let preview = preview_scheduled_run(saved.id, run_date.clone()).unwrap();
+        let line = &preview.lines[0];
+        assert_eq!(
+            line.dates.invoice_date,
+            today.format("%Y-%m-%d").to_string()
+        );
+        assert_eq!(line.dates.period_date, run_date);
+        assert_eq!(
+            line.descriptions,
+            vec![format!("Internett {}", &run_date[..7])]
+        );
+
+        run_scheduled(saved.id, run_date.clone(), preview.config_hash).unwrap();
+    }
+
+    let runs = list_schedule_runs(saved.id).unwrap();
+    assert_eq!(runs.len(), 2);
+    assert!(runs.iter().all(|run| run.status == RunStatus::Generated));
+}
+
+#[test]
+fn runs_left_running_are_offered_again_once_no_run_is_in_progress() {
+    let _db = database();
+    let run_date = Local::now()
+        .date_naive()
+        .with_day(1)
+        .unwrap()
+        .format("%Y-%m-%d")
+        .to_string();
+    let saved = save_schedule(Schedule {
+        config: [("amount", "600"), ("recipients", "Kari")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ..schedule(Cadence::Monthly, 1, &run_date, "")
+    })
+    .unwrap();
+    // As a generation that crashed leaves it
+    connect_database()
+        .unwrap()
+        .execute(
+            "INSERT INTO schedule_runs (schedule_id, run_date, status) VALUES (?1, ?2, 'running')",
+            rusqlite::params![saved.id, run_date],
+        )
+        .unwrap();
+    let is_due = || {
+        due_scheduled_runs()
+            .unwrap()
+            .iter()
+            .any(|due| due.schedule_id == saved.id && due.run_date == run_date)
+    };
+
+    let lock = runs::lock_database().unwrap();
+    assert!(!is_due());
+    assert!(skip_scheduled_run(saved.id, run_date.clone()).is_err());
+    drop(lock);
+
+    assert!(is_due());
+    skip_scheduled_run(saved.id, run_date.clone()).unwrap();
+    assert!(!is_due());
+    assert_eq!(
+        list_schedule_runs(saved.id).unwrap()[0].status,
+        RunStatus::Skipped
+    );
+}
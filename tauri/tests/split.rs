@@ -0,0 +1,159 @@
+// Tests for the split engine and money parsing; amounts are in minor units (øre)
+use invoice_splitter_lib::errors::Error;
+use invoice_splitter_lib::money::{Currency, Money, NOK};
+use invoice_splitter_lib::recipients::parse_recipients;
+use invoice_splitter_lib::split::{allocate, plan_split, Share, SplitConfig};
+
+fn percent(value: &str) -> Share {
+    Share::Percent(value.to_string())
+}
+
+fn fixed(value: &str) -> Share {
+    Share::Fixed(value.to_string())
+}
+
+fn nok(minor: i64) -> Money {
+    Money::new(minor, NOK)
+}
+
+fn allocate_minor(total: i64, shares: &[Share]) -> Result<Vec<i64>, Error> {
+    allocate(nok(total), shares).map(|amounts| amounts.iter().map(|m| m.minor).collect())
+}
+
+#[test]
+fn equal_split_assigns_remainder_in_order() {
+    let amounts = allocate_minor(100_000, &[Share::Equal, Share::Equal, Share::Equal]).unwrap();
+    assert_eq!(amounts, vec![33_334, 33_333, 33_333]);
+
+    let amounts = allocate_minor(200, &[Share::Equal, Share::Equal, Share::Equal]).unwrap();
+    assert_eq!(amounts, vec![67, 67, 66]);
+}
+
+#[test]
+fn weights_split_proportionally() {
+    let amounts = allocate_minor(1_000, &[Share::Weight(2), Share::Weight(1)]).unwrap();
+    assert_eq!(amounts, vec![667, 333]);
+}
+
+#[test]
+fn largest_remainder_wins_over_index_order() {
+    // Exact shares are 142.857 and 857.142..., so the first share gets the extra unit
+    let amounts = allocate_minor(1_000, &[Share::Weight(1), Share::Weight(6)]).unwrap();
+    assert_eq!(amounts, vec![143, 857]);
+}
+
+#[test]
+fn percentages_must_cover_everything_without_weights() {
+    let amounts = allocate_minor(100_001, &[percent("60"), percent("40")]).unwrap();
+    assert_eq!(amounts.iter().sum::<i64>(), 100_001);
+    assert_eq!(amounts, vec![60_001, 40_000]);
+
+    assert!(allocate_minor(100_000, &[percent("60"), percent("30")]).is_err());
+    assert!(allocate_minor(100_000, &[percent("60"), percent("50"), Share::Equal]).is_err());
+}
+
+#[test]
+fn fixed_amounts_plus_equal_share_of_the_rest() {
+    let amounts = allocate_minor(
+        500_000,
+        &[fixed("1500.00"), Share::Equal, Share::Equal, Share::Equal],
+    )
+    .unwrap();
+    assert_eq!(amounts, vec![150_000, 116_667, 116_667, 116_666]);
+}
+
+#[test]
+fn percentages_apply_to_the_rest_after_fixed_amounts() {
+    let amounts = allocate_minor(10_000, &[fixed("20.00"), percent("25"), Share::Equal]).unwrap();
+    assert_eq!(amounts, vec![2_000, 2_000, 6_000]);
+}
+
+#[test]
+fn invalid_shares_are_rejected() {
+    assert!(allocate_minor(1_000, &[fixed("20.00")]).is_err());
+    assert!(allocate_minor(1_000, &[fixed("5.00"), fixed("6.00")]).is_err());
+    assert!(allocate_minor(1_000, &[Share::Weight(0)]).is_err());
+    assert!(allocate_minor(1_000, &[percent("0")]).is_err());
+    // Nothing would be left for the weighted share
+    assert!(allocate_minor(1_000, &[percent("100"), Share::Weight(1)]).is_err());
+    assert!(allocate_minor(1_000, &[percent("60"), percent("40"), Share::Equal]).is_err());
+    assert!(allocate_minor(1_000, &[percent("abc")]).is_err());
+    assert!(allocate_minor(1_000, &[]).is_err());
+}
+
+#[test]
+fn allocations_always_add_up() {
+    let shares = [
+        fixed("13.37"),
+        percent("12.5"),
+        percent("33.33"),
+        Share::Weight(3),
+        Share::Equal,
+        Share::Weight(7),
+    ];
+    for total in [1_337, 99_999, 100_000, 123_456_789] {
+        let amounts = allocate_minor(total, &shares).unwrap();
+        assert_eq!(amounts.iter().sum::<i64>(), total, "total {}", total);
+    }
+}
+
+#[test]
+fn plan_matches_shares_to_recipients_by_name() {
+    let recipients = parse_recipients("Alice\nalice@example.com\n\nBob\nStreet 1\n\nCarol\n");
+    let config: SplitConfig =
+        serde_json::from_str(r#"{"shares": {"Alice": {"fixed": "1000"}, "Bob": {"weight": 2}}}"#)
+            .unwrap();
+
+    let plan = plan_split(nok(400_000), &recipients, &config).unwrap();
+    let amounts: Vec<(&str, String)> = plan
+        .iter()
+        .map(|a| (a.recipient.as_str(), a.amount.plain()))
+        .collect();
+    assert_eq!(
+        amounts,
+        vec![
+            ("Alice", "1000.00".to_string()),
+            ("Bob", "2000.00".to_string()),
+            ("Carol", "1000.00".to_string())
+        ]
+    );
+
+    let unknown: SplitConfig = serde_json::from_str(r#"{"shares": {"Dave": "equal"}}"#).unwrap();
+    assert!(plan_split(nok(400_000), &recipients, &unknown).is_err());
+}
+
+#[test]
+fn money_parses_locale_formats() {
+    let parse = |input: &str| Money::parse(input, NOK).map(|m| m.minor);
+
+    assert_eq!(parse("5000.00"), Ok(500_000));
+    assert_eq!(parse("5 000,00"), Ok(500_000));
+    assert_eq!(parse("5.000,50"), Ok(500_050));
+    assert_eq!(parse("5,000.50"), Ok(500_050));
+    assert_eq!(parse("kr 5 000"), Ok(500_000));
+    assert_eq!(parse("5000 NOK"), Ok(500_000));
+    assert_eq!(parse("1,5"), Ok(150));
+    assert_eq!(parse("0.07"), Ok(7));
+
+    assert!(parse("0.125").is_err());
+    assert!(parse("5.00.0").is_err());
+    assert!(parse("50.00,0").is_err());
+    assert!(parse("abc").is_err());
+    assert!(parse("").is_err());
+    assert!(parse("$ 5").is_err());
+}
+
+#[test]
+fn money_follows_the_currency() {
+    let jpy = Currency::from_code("jpy").unwrap();
+    assert_eq!(Money::parse("1 500", jpy).map(|m| m.minor), Ok(1_500));
+    assert!(Money::parse("1.5", jpy).is_err());
+    assert_eq!(Money::new(1_500, jpy).plain(), "1500");
+
+    assert_eq!(Currency::from_code("").unwrap(), NOK);
+    assert!(Currency::from_code("XYZ").is_err());
+
+    assert_eq!(nok(125_050).plain(), "1250.50");
+    assert_eq!(nok(-5).plain(), "-0.05");
+    assert_eq!(nok(123_456_789).display(), "1 234 567,89 kr");
+}
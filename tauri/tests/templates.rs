@@ -0,0 +1,86 @@
+// Tests for saved invoice templates against a temporary database
+mod common;
+
+use common::database;
+use invoice_splitter_lib::settings::{self, load_config};
+use invoice_splitter_lib::templates::{
+    create_template, delete_template, duplicate_template, find_template, list_templates,
+    load_template,
+};
+use std::collections::BTreeMap;
+
+fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn templates_capture_and_restore_the_configuration() {
+    let _db = database();
+    settings::set_config_setting("description".to_string(), "Cabin costs".to_string()).unwrap();
+    settings::set_config_setting("amount".to_string(), "1 200,50".to_string()).unwrap();
+
+    let cabin = create_template("Cabin".to_string(), None).unwrap();
+    assert_eq!(cabin.values["description"], "Cabin costs");
+    assert_eq!(cabin.values["amount"], "1200.50");
+    assert_eq!(cabin.values.len(), settings::CONFIG_KEYS.len());
+
+    let internet = create_template(
+        "Internet".to_string(),
+        Some(values(&[
+            ("description", "Internet share"),
+            ("amount", "499"),
+        ])),
+    )
+    .unwrap();
+    // Values are stored in canonical form
+    assert_eq!(internet.values["amount"], "499.00");
+
+    load_template("Internet".to_string()).unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.get("description"), "Internet share");
+    assert_eq!(config.get("amount"), "499.00");
+    // Keys the template leaves out are kept
+    assert!(!config.get("sender").is_empty());
+
+    load_template("Cabin".to_string()).unwrap();
+    assert_eq!(load_config().unwrap().get("description"), "Cabin costs");
+}
+
+#[test]
+fn templates_can_be_duplicated_and_deleted() {
+    let _db = database();
+    create_template(
+        "Consulting".to_string(),
+        Some(values(&[("description", "Consulting")])),
+    )
+    .unwrap();
+
+    let copy = duplicate_template("Consulting".to_string(), "Consulting 2".to_string()).unwrap();
+    assert_eq!(copy.values, find_template("Consulting").unwrap().values);
+    assert!(duplicate_template("Consulting".to_string(), "Consulting 2".to_string()).is_err());
+
+    delete_template("Consulting".to_string()).unwrap();
+    assert!(find_template("Consulting").is_err());
+    assert!(delete_template("Consulting".to_string()).is_err());
+    assert!(list_templates()
+        .unwrap()
+        .iter()
+        .any(|t| t.name == "Consulting 2"));
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    let _db = database();
+    assert!(create_template(" ".to_string(), Some(BTreeMap::new())).is_err());
+    assert!(create_template("Bad key".to_string(), Some(values(&[("colour", "red")]))).is_err());
+    assert!(create_template("Bad amount".to_string(), Some(values(&[("amount", "abc")]))).is_err());
+    assert!(create_template(
+        "Bad currency".to_string(),
+        Some(values(&[("currency", "XYZ")]))
+    )
+    .is_err());
+    assert!(find_template("Bad amount").is_err());
+}
@@ -0,0 +1,123 @@
+// Tests for invoice dates and payment terms
+use chrono::NaiveDate;
+use invoice_splitter_lib::terms::{open_period_start, resolve_dates, RunOptions, Terms};
+use std::collections::BTreeMap;
+
+fn date(value: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn terms() -> Terms {
+    Terms {
+        invoice_date: String::new(),
+        payment_days: 30,
+        recipients: BTreeMap::from([("Kari".to_string(), 10)]),
+    }
+}
+
+#[test]
+fn recipients_can_have_their_own_payment_days() {
+    let today = date("2025-03-15");
+    let dates = resolve_dates(
+        &terms(),
+        &RunOptions::default(),
+        &names(&["Ola", "Kari"]),
+        today,
+        date("2025-02-01"),
+    )
+    .unwrap();
+
+    assert_eq!(dates[0].invoice_date, "2025-03-15");
+    assert_eq!(dates[0].due_date, "2025-04-14");
+    assert_eq!(dates[1].due_date, "2025-03-25");
+    assert_eq!(dates[1].payment_days, 10);
+}
+
+#[test]
+fn run_options_override_the_stored_terms() {
+    let today = date("2025-03-15");
+    let options = RunOptions {
+        invoice_date: Some("2025-02-28".to_string()),
+        payment_days: Some(14),
+        due_date: None,
+        period_date: None,
+        force: false,
+    };
+    let dates = resolve_dates(
+        &terms(),
+        &options,
+        &names(&["Ola", "Kari"]),
+        today,
+        date("2025-02-01"),
+    )
+    .unwrap();
+    assert!(dates.iter().all(|d| d.due_date == "2025-03-14"));
+
+    let options = RunOptions {
+        due_date: Some("2025-04-01".to_string()),
+        ..RunOptions::default()
+    };
+    let dates = resolve_dates(&terms(), &options, &names(&["Kari"]), today, today).unwrap();
+    assert_eq!(dates[0].due_date, "2025-04-01");
+    assert_eq!(dates[0].payment_days, 17);
+}
+
+#[test]
+fn invalid_dates_are_rejected() {
+    let today = date("2025-03-15");
+    let earliest = date("2025-02-01");
+    let resolve = |options: RunOptions| {
+        resolve_dates(&terms(), &options, &names(&["Ola"]), today, earliest)
+            .unwrap_err()
+            .message
+    };
+
+    let error = resolve(RunOptions {
+        due_date: Some("2025-03-01".to_string()),
+        ..RunOptions::default()
+    });
+    assert!(error.contains("before the invoice date"), "{}", error);
+
+    let error = resolve(RunOptions {
+        invoice_date: Some("2025-03-16".to_string()),
+        ..RunOptions::default()
+    });
+    assert!(error.contains("in the future"), "{}", error);
+
+    let error = resolve(RunOptions {
+        invoice_date: Some("2025-01-31".to_string()),
+        ..RunOptions::default()
+    });
+    assert!(error.contains("open period"), "{}", error);
+
+    let error = resolve(RunOptions {
+        payment_days: Some(14),
+        due_date: Some("2025-04-01".to_string()),
+        ..RunOptions::default()
+    });
+    assert!(error.contains("not both"), "{}", error);
+
+    let error = resolve(RunOptions {
+        invoice_date: Some("15.03.2025".to_string()),
+        ..RunOptions::default()
+    });
+    assert!(error.contains("YYYY-MM-DD"), "{}", error);
+}
+
+#[test]
+fn open_period_starts_last_month_or_at_the_latest_invoice() {
+    let today = date("2025-01-10");
+    assert_eq!(open_period_start(today, None), date("2024-12-01"));
+    assert_eq!(
+        open_period_start(today, Some(date("2024-11-20"))),
+        date("2024-12-01")
+    );
+    assert_eq!(
+        open_period_start(today, Some(date("2025-01-05"))),
+        date("2025-01-05")
+    );
+}
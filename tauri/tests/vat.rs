@@ -0,0 +1,95 @@
+// Tests for VAT rates, treatments and the per-rate summary
+use invoice_splitter_lib::line_items::{calculate_totals, LineItem};
+use invoice_splitter_lib::money::NOK;
+use invoice_splitter_lib::vat::{parse_vat_settings, VatSettings, VatTreatment};
+
+fn item(description: &str, unit_price: &str, vat_rate: Option<u32>) -> LineItem {
+    LineItem {
+        description: description.to_string(),
+        date: String::new(),
+        quantity: "1".to_string(),
+        unit: String::new(),
+        unit_price: unit_price.to_string(),
+        vat_rate,
+        vat_treatment: None,
+    }
+}
+
+fn registered() -> VatSettings {
+    VatSettings {
+        enabled: true,
+        ..VatSettings::default()
+    }
+}
+
+#[test]
+fn lines_use_the_sender_rate_unless_overridden() {
+    let totals = calculate_totals(
+        &[
+            item("Consulting", "1000.00", None),
+            item("Lunch", "200.00", Some(15)),
+            item("Travel", "300.00", Some(12)),
+            item("Hours", "500.00", None),
+        ],
+        NOK,
+        &registered(),
+    )
+    .unwrap();
+
+    let summary: Vec<(u32, &str, &str)> = totals
+        .vat_summary
+        .iter()
+        .map(|g| (g.rate, g.net.as_str(), g.vat.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (25, "1500.00", "375.00"),
+            (15, "200.00", "30.00"),
+            (12, "300.00", "36.00")
+        ]
+    );
+    assert_eq!(totals.vat_total.plain(), "441.00");
+}
+
+#[test]
+fn exempt_and_reverse_charge_carry_no_vat() {
+    let mut exempt = item("Rent", "8000.00", None);
+    exempt.vat_treatment = Some(VatTreatment::Exempt);
+    let reverse_charge = VatSettings {
+        treatment: VatTreatment::ReverseCharge,
+        ..registered()
+    };
+
+    let totals = calculate_totals(
+        &[exempt, item("Cleaning", "400.00", None)],
+        NOK,
+        &reverse_charge,
+    )
+    .unwrap();
+    assert_eq!(totals.vat_total.plain(), "0.00");
+    let treatments: Vec<VatTreatment> = totals.vat_summary.iter().map(|g| g.treatment).collect();
+    assert_eq!(
+        treatments,
+        vec![VatTreatment::Exempt, VatTreatment::ReverseCharge]
+    );
+}
+
+#[test]
+fn unregistered_senders_charge_no_vat() {
+    let unregistered = VatSettings::default();
+    let totals = calculate_totals(&[item("Rent", "100", None)], NOK, &unregistered).unwrap();
+    assert_eq!(totals.vat_total.plain(), "0.00");
+
+    let error = calculate_totals(&[item("Rent", "100", Some(25))], NOK, &unregistered).unwrap_err();
+    assert!(error.message.contains("not enabled"), "{}", error);
+}
+
+#[test]
+fn only_norwegian_rates_are_accepted() {
+    assert!(parse_vat_settings(r#"{"enabled": true, "rate": 15}"#).is_ok());
+    assert!(parse_vat_settings(r#"{"enabled": true, "rate": 20}"#).is_err());
+    assert!(parse_vat_settings(r#"{"treatment": "reverse_charge"}"#).is_ok());
+    assert!(parse_vat_settings(r#"{"treatment": "zero"}"#).is_err());
+    assert_eq!(parse_vat_settings("").unwrap(), VatSettings::default());
+}